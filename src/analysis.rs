@@ -0,0 +1,283 @@
+use crate::builder::*;
+use crate::engine::*;
+use crate::game::*;
+use crate::notation::*;
+use crate::piece::*;
+
+/// How good a played move was compared to the engine's best move.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MoveClass {
+    /// The move the engine would have played.
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+/// Options for analysing a game. The thresholds are the centipawns a move
+/// has to lose to be classified as an inaccuracy, mistake or blunder.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AnalysisOptions {
+    /// The search depth used for every position.
+    pub depth: u32,
+    pub inaccuracy: i32,
+    pub mistake: i32,
+    pub blunder: i32,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            depth: 3,
+            inaccuracy: 50,
+            mistake: 100,
+            blunder: 300,
+        }
+    }
+}
+
+/// The analysis of a single played move. Evaluations are in centipawns
+/// from white's point of view.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalysedMove {
+    pub from: String,
+    pub to: String,
+    pub san: String,
+    pub color: Color,
+    /// The number of the move, as in PGN, which needn't start at 1.
+    pub fullmove_number: u32,
+    pub eval_before: i32,
+    pub eval_after: i32,
+    /// The centipawns lost compared to the best move, never negative.
+    pub loss: i32,
+    pub class: MoveClass,
+    /// The engine's choice as a (from, to) pair, None if the played move
+    /// was the best one.
+    pub best_move: Option<(String, String)>,
+    /// The line the engine expects after its choice in SAN, starting with
+    /// the best move itself. Empty if the played move was the best one.
+    pub best_line: Vec<String>,
+}
+
+/// The analysis of a whole game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalysisReport {
+    pub moves: Vec<AnalysedMove>,
+    /// How close each side played to the engine, from 0 to 100. A side
+    /// without moves gets 100.
+    pub white_accuracy: f64,
+    pub black_accuracy: f64,
+}
+
+impl AnalysisReport {
+    /// Get the moves of a colour with the given class.
+    pub fn moves_with_class(&self, color: Color, class: MoveClass) -> Vec<&AnalysedMove> {
+        self.moves
+            .iter()
+            .filter(|analysed| analysed.color == color && analysed.class == class)
+            .collect()
+    }
+}
+
+// Mates are scored as this many centipawns so that a single missed mate
+// doesn't outweigh the rest of the game
+const EVAL_LIMIT: i32 = 2000;
+
+/// Analyses every move of a game with the engine's search. Each position
+/// is searched once, so a game of n moves takes n + 1 searches plus one
+/// short search per move of the alternative lines.
+pub fn analyse(game: &Game, options: &AnalysisOptions) -> AnalysisReport {
+    let mut replay = GameBuilder::from_fen(&game.get_starting_fen())
+        .and_then(|builder| builder.chess960(game.is_chess960()).build().ok())
+        .unwrap_or_default();
+
+    let mut moves = Vec::new();
+    let mut accuracies = (Vec::new(), Vec::new());
+    let (mut best, mut eval_before) = evaluate_position(&replay, options.depth);
+    for (from, to) in game.get_move_history() {
+        let color = replay.get_active_color();
+        let fullmove_number = replay.get_fullmove_number();
+        let san = to_san(&replay, &from, &to).unwrap_or_default();
+        let mut after = replay.clone();
+        after.make_move(from.clone(), to.clone());
+        let (next_best, eval_after) = evaluate_position(&after, options.depth);
+
+        // Losses are counted from the view of the side that moved
+        let sign = if color == Color::White { 1 } else { -1 };
+        let is_best = best
+            .as_ref()
+            .is_none_or(|result| result.from == from && result.to == to);
+        let loss = if is_best {
+            0
+        } else {
+            (sign * (eval_before - eval_after)).max(0)
+        };
+        let class = if is_best {
+            MoveClass::Best
+        } else if loss >= options.blunder {
+            MoveClass::Blunder
+        } else if loss >= options.mistake {
+            MoveClass::Mistake
+        } else if loss >= options.inaccuracy {
+            MoveClass::Inaccuracy
+        } else {
+            MoveClass::Good
+        };
+        let (best_move, best_line) = match &best {
+            Some(result) if !is_best => (
+                Some((result.from.clone(), result.to.clone())),
+                principal_line(&replay, result, options.depth),
+            ),
+            _ => (None, Vec::new()),
+        };
+
+        let accuracy = move_accuracy(sign * eval_before, sign * eval_after);
+        if color == Color::White {
+            accuracies.0.push(accuracy);
+        } else {
+            accuracies.1.push(accuracy);
+        }
+        moves.push(AnalysedMove {
+            from: from.clone(),
+            to: to.clone(),
+            san,
+            color,
+            fullmove_number,
+            eval_before,
+            eval_after,
+            loss,
+            class,
+            best_move,
+            best_line,
+        });
+        replay = after;
+        best = next_best;
+        eval_before = eval_after;
+    }
+
+    AnalysisReport {
+        moves,
+        white_accuracy: average(&accuracies.0),
+        black_accuracy: average(&accuracies.1),
+    }
+}
+
+// Searches a position and returns the best move with the evaluation from
+// white's point of view
+fn evaluate_position(game: &Game, depth: u32) -> (Option<SearchResult>, i32) {
+    let limits = SearchLimits { depth, time: None };
+    let result = search(game, limits);
+    let score = match (&result, game.get_game_state()) {
+        (Some(result), _) => result.score,
+        (None, GameState::WhiteCheckMate) | (None, GameState::BlackCheckMate) => -MATE_SCORE,
+        (None, _) => 0,
+    };
+    let score = score.clamp(-EVAL_LIMIT, EVAL_LIMIT);
+    match game.get_active_color() {
+        Color::White => (result, score),
+        Color::Black => (result, -score),
+    }
+}
+
+// Follows the engine's best moves from a search result, one ply less deep
+// for every move, and writes them in SAN
+fn principal_line(game: &Game, first: &SearchResult, depth: u32) -> Vec<String> {
+    let mut game = game.clone();
+    let mut line = Vec::new();
+    let (mut from, mut to) = (first.from.clone(), first.to.clone());
+    for remaining in (0..depth).rev() {
+        match to_san(&game, &from, &to) {
+            Some(san) => line.push(san),
+            None => break,
+        }
+        game.make_move(from, to);
+        if remaining == 0 {
+            break;
+        }
+        let limits = SearchLimits {
+            depth: remaining,
+            time: None,
+        };
+        match search(&game, limits) {
+            Some(result) => {
+                from = result.from;
+                to = result.to;
+            }
+            None => break,
+        }
+    }
+    line
+}
+
+// The chance to win in percent for an evaluation in centipawns
+fn win_chance(eval: i32) -> f64 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * eval as f64).exp()) - 1.0)
+}
+
+// Scores a move from 0 to 100 by how much it lowered the mover's chance to win
+fn move_accuracy(before: i32, after: i32) -> f64 {
+    let drop = (win_chance(before) - win_chance(after)).max(0.0);
+    (103.1668 * (-0.04354 * drop).exp() - 3.1669).clamp(0.0, 100.0)
+}
+
+fn average(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        100.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> AnalysisOptions {
+        AnalysisOptions {
+            depth: 2,
+            ..AnalysisOptions::default()
+        }
+    }
+
+    #[test]
+    fn finds_the_blunder_that_allows_mate() {
+        let (game, _) = parse_pgn("1. f3 e5 2. g4 Qh4# 0-1").unwrap();
+        let report = analyse(&game, &options());
+        assert_eq!(report.moves.len(), 4);
+        assert_eq!(report.moves[2].san, "g4");
+        assert_eq!(report.moves[3].fullmove_number, 2);
+        assert_eq!(report.moves[2].class, MoveClass::Blunder);
+        assert_eq!(report.moves[3].class, MoveClass::Best);
+        assert_eq!(report.moves[3].eval_after, -EVAL_LIMIT);
+        assert!(report.white_accuracy < report.black_accuracy);
+        assert_eq!(
+            report
+                .moves_with_class(Color::White, MoveClass::Blunder)
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn suggests_the_better_line() {
+        // Black hangs the queen instead of saving it
+        let mut game = Game::from_fen("4k3/8/8/3q4/8/8/8/3RK3 b - - 0 30").unwrap();
+        game.make_move(String::from("E8"), String::from("E7"));
+        let report = analyse(&game, &options());
+        let analysed = &report.moves[0];
+        assert_eq!(analysed.class, MoveClass::Blunder);
+        assert_eq!(analysed.fullmove_number, 30);
+        assert!(analysed.loss >= 800);
+        assert_eq!(analysed.best_line.len(), 2);
+        assert!(analysed.best_line[0].starts_with('Q'));
+        assert_eq!(report.white_accuracy, 100.0);
+    }
+
+    #[test]
+    fn accuracy_falls_with_the_loss() {
+        assert!((move_accuracy(0, 0) - 100.0).abs() < 0.01);
+        assert!(move_accuracy(0, -100) > move_accuracy(0, -300));
+        assert_eq!(move_accuracy(EVAL_LIMIT, -EVAL_LIMIT), 0.0);
+    }
+}
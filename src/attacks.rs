@@ -0,0 +1,231 @@
+use crate::piece::*;
+
+/// Finds the pieces of a colour that attack a square, ordered by the square
+/// they stand on. Pins are ignored, so a pinned piece still attacks.
+pub fn attackers_of(board: &[Option<Piece>; 64], square: usize, color: Color) -> Vec<usize> {
+    let mut attackers = Vec::new();
+
+    // Walk outwards from the square until a piece blocks each line
+    for (file_move, rank_move, _) in PieceType::Queen.directions() {
+        for i in 1..8 {
+            let target = match offset(square, file_move * i, rank_move * i) {
+                Some(target) => target,
+                None => break,
+            };
+            let piece = match board[target] {
+                Some(piece) => piece,
+                None => continue,
+            };
+            let is_straight = file_move == 0 || rank_move == 0;
+            let attacks = piece.color() == color
+                && match piece.title() {
+                    PieceType::King => i == 1,
+                    // Pawns attack diagonally forwards, so they stand one rank behind
+                    PieceType::Pawn => i == 1 && !is_straight && rank_move == -color.forward(),
+                    _ => slides_along(piece, file_move, rank_move),
+                };
+            if attacks {
+                attackers.push(target);
+            }
+            break;
+        }
+    }
+
+    for (file_move, rank_move, _) in PieceType::Knight.directions() {
+        if let Some(target) = offset(square, file_move, rank_move) {
+            if board[target]
+                .is_some_and(|piece| piece.color() == color && piece.title() == PieceType::Knight)
+            {
+                attackers.push(target);
+            }
+        }
+    }
+
+    attackers.sort_unstable();
+    attackers
+}
+
+/// If any piece of a colour attacks a square.
+pub fn is_square_attacked(board: &[Option<Piece>; 64], square: usize, color: Color) -> bool {
+    !attackers_of(board, square, color).is_empty()
+}
+
+/// Counts how many pieces of a colour attack each square of the board.
+pub fn attack_map(board: &[Option<Piece>; 64], color: Color) -> [u8; 64] {
+    let mut map = [0; 64];
+    for (square, count) in map.iter_mut().enumerate() {
+        *count = attackers_of(board, square, color).len() as u8;
+    }
+    map
+}
+
+/// A piece that can't leave the line between its king and an enemy
+/// rook, bishop or queen without exposing the king to check.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pin {
+    /// The square of the pinned piece.
+    pub pinned: usize,
+    /// The square of the piece pinning it.
+    pub pinner: usize,
+    /// The squares between the king and the pinner, from the king
+    /// outwards, with the pinned piece's own square and the pinner's.
+    /// The pinned piece may only move to the others.
+    pub ray: Vec<usize>,
+}
+
+/// Finds the pieces of a colour that are pinned to their own king.
+pub fn pins(board: &[Option<Piece>; 64], color: Color) -> Vec<Pin> {
+    match find_king(board, color) {
+        Some(king) => lines_through(board, king, color, color.opposite()),
+        None => Vec::new(),
+    }
+}
+
+/// Finds the pieces of a colour that give a discovered check by moving off
+/// the line between one of their own rooks, bishops or queens and the
+/// enemy king. Moving along the line doesn't uncover the check.
+pub fn discovered_check_candidates(board: &[Option<Piece>; 64], color: Color) -> Vec<usize> {
+    match find_king(board, color.opposite()) {
+        Some(king) => lines_through(board, king, color, color)
+            .iter()
+            .map(|line| line.pinned)
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+// Finds lines from the king where a single piece of `blocker` colour stands
+// between the king and a slider of `slider` colour that moves along the line
+fn lines_through(
+    board: &[Option<Piece>; 64],
+    king: usize,
+    blocker: Color,
+    slider: Color,
+) -> Vec<Pin> {
+    let mut lines = Vec::new();
+    for (file_move, rank_move, _) in PieceType::Queen.directions() {
+        let mut ray = Vec::new();
+        let mut blocking = None;
+        for i in 1..8 {
+            let target = match offset(king, file_move * i, rank_move * i) {
+                Some(target) => target,
+                None => break,
+            };
+            ray.push(target);
+            let piece = match board[target] {
+                Some(piece) => piece,
+                None => continue,
+            };
+            match blocking {
+                None if piece.color() == blocker => blocking = Some(target),
+                Some(pinned)
+                    if piece.color() == slider && slides_along(piece, file_move, rank_move) =>
+                {
+                    lines.push(Pin {
+                        pinned,
+                        pinner: target,
+                        ray: ray.clone(),
+                    });
+                    break;
+                }
+                _ => break,
+            }
+        }
+    }
+    lines.sort_unstable_by_key(|line| line.pinned);
+    lines
+}
+
+// If a piece can move any distance along the given direction
+fn slides_along(piece: Piece, file_move: i32, rank_move: i32) -> bool {
+    let is_straight = file_move == 0 || rank_move == 0;
+    match piece.title() {
+        PieceType::Queen => true,
+        PieceType::Rook => is_straight,
+        PieceType::Bishop => !is_straight,
+        _ => false,
+    }
+}
+
+pub(crate) fn find_king(board: &[Option<Piece>; 64], color: Color) -> Option<usize> {
+    board.iter().position(|tile| {
+        tile.is_some_and(|piece| piece.title() == PieceType::King && piece.color() == color)
+    })
+}
+
+// The square at the given file and rank distance, if it's on the board
+pub(crate) fn offset(square: usize, file_move: i32, rank_move: i32) -> Option<usize> {
+    let file = (square % 8) as i32 + file_move;
+    let rank = (square / 8) as i32 + rank_move;
+    if (0..8).contains(&file) && (0..8).contains(&rank) {
+        Some((file + rank * 8) as usize)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::*;
+
+    #[test]
+    fn finds_attackers_of_all_kinds() {
+        // e4 is attacked by the pawn on d3, knight on f2, rook on e1 and
+        // bishop on h7; the queen on e8 is blocked by the pawn on e6
+        let game = Game::from_fen("4q2k/7b/4p3/8/8/3P4/5N2/K3R3 w - - 0 1").unwrap();
+        let board = game.get_board();
+        assert_eq!(attackers_of(board, 28, Color::White), vec![4, 13, 19]);
+        assert_eq!(attackers_of(board, 28, Color::Black), vec![55]);
+        assert!(!is_square_attacked(board, 56, Color::White));
+    }
+
+    #[test]
+    fn pawns_only_attack_forwards() {
+        let game = Game::from_fen("4k3/8/8/3p4/8/8/8/4K3 w - - 0 1").unwrap();
+        let map = attack_map(game.get_board(), Color::Black);
+        // c4 and e4, not c6 or e6
+        assert_eq!(map[26], 1);
+        assert_eq!(map[28], 1);
+        assert_eq!(map[42], 0);
+        assert_eq!(map[44], 0);
+    }
+
+    #[test]
+    fn maps_the_starting_position() {
+        let map = attack_map(Game::new().get_board(), Color::White);
+        // f3 is covered by the e2 and g2 pawns and the g1 knight
+        assert_eq!(map[21], 3);
+        assert_eq!(map[28], 0);
+        assert_eq!(map.iter().filter(|&&count| count > 0).count(), 22);
+    }
+
+    #[test]
+    fn finds_pins_with_their_rays() {
+        // The knight on d2 is pinned by the bishop on b4, the bishop on
+        // e2 isn't because the rook on e8 is blocked by the black pawn
+        let game = Game::from_fen("4r1k1/8/4p3/8/1b6/8/3NB3/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            pins(game.get_board(), Color::White),
+            vec![Pin {
+                pinned: 11,
+                pinner: 25,
+                ray: vec![11, 18, 25],
+            }]
+        );
+        assert!(pins(game.get_board(), Color::Black).is_empty());
+    }
+
+    #[test]
+    fn finds_discovered_check_candidates() {
+        // The rook on e1 checks through the knight on e4 when it moves, the
+        // black knight on e6 in between is no candidate
+        let game = Game::from_fen("4k3/8/8/8/4N3/8/8/K3R3 w - - 0 1").unwrap();
+        assert_eq!(
+            discovered_check_candidates(game.get_board(), Color::White),
+            vec![28]
+        );
+        let game = Game::from_fen("4k3/8/4n3/8/4N3/8/8/K3R3 w - - 0 1").unwrap();
+        assert!(discovered_check_candidates(game.get_board(), Color::White).is_empty());
+    }
+}
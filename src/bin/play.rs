@@ -0,0 +1,274 @@
+use isaklar_chess::analysis::{analyse, AnalysisOptions, MoveClass};
+use isaklar_chess::book::{BookOptions, OpeningBook};
+use isaklar_chess::engine::{search, SearchLimits};
+use isaklar_chess::game::Game;
+use isaklar_chess::notation::{game_result, parse_move, to_pgn, to_san};
+use isaklar_chess::piece::Color;
+use isaklar_chess::render::{render, RenderOptions};
+use isaklar_chess::save::{load_game, save_game};
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+const HELP: &str = "\
+Enter moves as coordinates (e2 e4, e2e4) or in SAN (e4, Nf3, exd5).
+Commands:
+  moves <square>  list the moves of the piece on a square
+  undo            take back the last move
+  fen             print the position as FEN
+  pgn             print the game as PGN
+  analyse         review the moves played so far with the engine
+  resign          resign the game
+  draw            offer a draw, or accept one that was offered
+  flip            show the board from the other side
+  help            show this message
+  quit            leave the game";
+
+const USAGE: &str =
+    "Usage: play [--engine white|black] [--depth N] [--book FILE] [--save FILE] [--unicode] [--color]";
+
+// Plays a game in the terminal, either between two humans or against the engine
+fn main() {
+    let mut engine_color: Option<Color> = None;
+    let mut depth = SearchLimits::default().depth;
+    let mut options = RenderOptions::default();
+    let mut book: Option<OpeningBook> = None;
+    let mut save_path: Option<String> = None;
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut i = 0;
+    while i < args.len() {
+        match (args[i].as_str(), args.get(i + 1).map(|s| s.as_str())) {
+            ("--unicode", _) => {
+                options.unicode = true;
+                i += 1;
+                continue;
+            }
+            ("--color", _) => {
+                options.colors = true;
+                i += 1;
+                continue;
+            }
+            ("--engine", Some("white")) => engine_color = Some(Color::White),
+            ("--engine", Some("black")) => engine_color = Some(Color::Black),
+            ("--depth", Some(value)) if value.parse::<u32>().is_ok() => {
+                depth = value.parse().unwrap()
+            }
+            ("--book", Some(path)) => match OpeningBook::open(path) {
+                Ok(opened) => book = Some(opened),
+                Err(error) => {
+                    eprintln!("Can't read book {}: {}", path, error);
+                    return;
+                }
+            },
+            ("--save", Some(path)) => save_path = Some(path.to_string()),
+            _ => {
+                eprintln!("{}", USAGE);
+                return;
+            }
+        }
+        i += 2;
+    }
+
+    let mut game = Game::new();
+    // Set when a game ends by resignation or agreement
+    let mut result: Option<String> = None;
+    // Continue a saved game
+    if let Some(path) = save_path.as_ref().filter(|path| Path::new(path).exists()) {
+        match load_game(path) {
+            Ok((loaded, tags)) => {
+                result = tags
+                    .into_iter()
+                    .find(|(name, value)| name == "Result" && value != game_result(&loaded))
+                    .map(|(_, value)| value);
+                game = loaded;
+            }
+            Err(error) => {
+                eprintln!("Can't read saved game {}: {}", path, error);
+                return;
+            }
+        }
+    }
+    let mut draw_offered_by: Option<Color> = None;
+    options.flipped = engine_color == Some(Color::White);
+
+    println!("{}\n", HELP);
+    print!("{}", render(&game, &options));
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        // Save after every command, so the game survives when the process exits
+        if let Some(path) = &save_path {
+            if let Err(error) = save_game(path, &game, &result_tags(&result)) {
+                eprintln!("Can't save the game to {}: {}", path, error);
+            }
+        }
+        let finished = result.is_some() || game.is_game_over();
+
+        if !finished && engine_color == Some(game.get_active_color()) {
+            let limits = SearchLimits { depth, time: None };
+            let book_move = book
+                .as_ref()
+                .and_then(|book| book.choose_move(&game, &BookOptions::default()));
+            let engine_move =
+                book_move.or_else(|| search(&game, limits).map(|best| (best.from, best.to)));
+            if let Some((from, to)) = engine_move {
+                let san = to_san(&game, &from, &to).unwrap();
+                game.make_move(from, to);
+                println!("Engine plays {}", san);
+                print!("{}", render(&game, &options));
+                print_result(&game, &result);
+            }
+            continue;
+        }
+
+        print!("{:?}> ", game.get_active_color());
+        let _ = io::stdout().flush();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words.as_slice() {
+            [] => (),
+            ["help"] => println!("{}", HELP),
+            ["quit"] | ["exit"] => break,
+            ["fen"] => println!("{}", game.to_fen()),
+            ["pgn"] => print!("{}", to_pgn(&game, &result_tags(&result))),
+            ["analyse"] | ["analyze"] => {
+                let report = analyse(
+                    &game,
+                    &AnalysisOptions {
+                        depth,
+                        ..AnalysisOptions::default()
+                    },
+                );
+                for analysed in &report.moves {
+                    let mut line = format!(
+                        "{}{} {} ({:+.2})",
+                        analysed.fullmove_number,
+                        if analysed.color == Color::White {
+                            "."
+                        } else {
+                            "..."
+                        },
+                        analysed.san,
+                        analysed.eval_after as f64 / 100.0
+                    );
+                    if analysed.class > MoveClass::Good {
+                        line.push_str(&format!(
+                            " {:?}, better was {}",
+                            analysed.class,
+                            analysed.best_line.join(" ")
+                        ));
+                    }
+                    println!("{}", line);
+                }
+                println!(
+                    "Accuracy: white {:.1}%, black {:.1}%",
+                    report.white_accuracy, report.black_accuracy
+                );
+            }
+            ["flip"] => {
+                options.flipped = !options.flipped;
+                print!("{}", render(&game, &options));
+            }
+            ["moves", square] => match game.get_possible_moves(square.to_uppercase()) {
+                Some(targets) if !finished => {
+                    let from = square.to_uppercase();
+                    let sans: Vec<String> = targets
+                        .iter()
+                        .map(|to| to_san(&game, &from, to).unwrap())
+                        .collect();
+                    let targets_options = RenderOptions {
+                        targets,
+                        ..options.clone()
+                    };
+                    print!("{}", render(&game, &targets_options));
+                    println!("{}", sans.join(" "));
+                }
+                _ => println!("No moves from {}", square),
+            },
+            ["undo"] => {
+                // Against the engine, take back its reply as well
+                let plies = if engine_color.is_some() { 2 } else { 1 };
+                for _ in 0..plies {
+                    game.undo_move();
+                }
+                result = None;
+                draw_offered_by = None;
+                print!("{}", render(&game, &options));
+            }
+            _ if finished => println!("The game is over, try undo, pgn or quit"),
+            ["resign"] => {
+                let winner = match game.get_active_color() {
+                    Color::White => "0-1",
+                    Color::Black => "1-0",
+                };
+                result = Some(winner.to_string());
+                println!("{:?} resigns. {}", game.get_active_color(), winner);
+            }
+            ["draw"] => {
+                let color = game.get_active_color();
+                match (engine_color, draw_offered_by) {
+                    // The engine accepts when it doesn't think it's winning
+                    (Some(_), _) => {
+                        let limits = SearchLimits {
+                            depth: 2,
+                            time: None,
+                        };
+                        // Scores are from the view of the player offering the draw
+                        let accepts = search(&game, limits).is_none_or(|best| best.score >= 0);
+                        if accepts {
+                            result = Some(String::from("1/2-1/2"));
+                            println!("The engine accepts the draw. 1/2-1/2");
+                        } else {
+                            println!("The engine declines the draw");
+                        }
+                    }
+                    (None, Some(offered_by)) if offered_by != color => {
+                        result = Some(String::from("1/2-1/2"));
+                        println!("Draw agreed. 1/2-1/2");
+                    }
+                    (None, _) => {
+                        draw_offered_by = Some(color);
+                        println!("{:?} offers a draw, type draw to accept", color);
+                    }
+                }
+            }
+            _ => match parse_move(&game, &line) {
+                Some((from, to)) => {
+                    let san = to_san(&game, &from, &to).unwrap();
+                    game.make_move(from, to);
+                    // Making a move declines an offered draw
+                    draw_offered_by =
+                        draw_offered_by.filter(|&color| color != game.get_active_color());
+                    println!("{}", san);
+                    print!("{}", render(&game, &options));
+                    print_result(&game, &result);
+                }
+                None => println!("Illegal move or unknown command, type help for help"),
+            },
+        }
+    }
+}
+
+// The PGN tags for a result that wasn't reached on the board
+fn result_tags(result: &Option<String>) -> Vec<(String, String)> {
+    match result {
+        Some(result) => vec![(String::from("Result"), result.clone())],
+        None => Vec::new(),
+    }
+}
+
+fn print_result(game: &Game, result: &Option<String>) {
+    if game.is_game_over() && result.is_none() {
+        println!(
+            "Game over: {:?}. {}",
+            game.get_game_state(),
+            game_result(game)
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use isaklar_chess::server::serve;
+use std::env;
+use std::net::TcpListener;
+
+// Hosts games for clients on the local network, speaking JSON over TCP or
+// WebSocket. `--port <n>` sets the port, 7878 by default, and `--public`
+// listens on all interfaces instead of localhost only.
+fn main() {
+    let mut port = 7878;
+    let mut host = "127.0.0.1";
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut i = 0;
+    while i < args.len() {
+        match (args[i].as_str(), args.get(i + 1).map(|s| s.as_str())) {
+            ("--public", _) => {
+                host = "0.0.0.0";
+                i += 1;
+                continue;
+            }
+            ("--port", Some(value)) if value.parse::<u16>().is_ok() => {
+                port = value.parse().unwrap()
+            }
+            _ => {
+                eprintln!("Usage: server [--port N] [--public]");
+                return;
+            }
+        }
+        i += 2;
+    }
+
+    let listener = match TcpListener::bind((host, port)) {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("Can't listen on {}:{}: {}", host, port, error);
+            return;
+        }
+    };
+    println!("Serving games on {}:{}", host, port);
+    if let Err(error) = serve(listener) {
+        eprintln!("Server stopped: {}", error);
+    }
+}
@@ -0,0 +1,287 @@
+use isaklar_chess::sprt::{sprt, Decision, SprtOptions};
+use isaklar_chess::tablebase::Tablebase;
+use isaklar_chess::tournament::{
+    play_match, play_tournament, read_openings, BuiltinEngine, Format, MatchOptions, PlayedGame,
+    Player, Score, TimeControl, UciEngine,
+};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: tournament --engine ENGINE --engine ENGINE [--engine ENGINE ...]
+                  [--format round-robin|gauntlet] [--games N] [--openings FILE]
+                  [--tc SECONDS[+INCREMENT] | --movetime MS | --depth N]
+                  [--resign CP MOVES] [--draw CP MOVES START] [--max-moves N]
+                  [--tablebase DIR] [--pgn FILE] [--sprt ELO0 ELO1 ALPHA BETA]
+An engine is a UCI command with its arguments, or `builtin[:DEPTH]` for the
+engine of this crate. With --sprt two engines play until the test decides,
+or until --games have been played.";
+
+// The most games of a test when --games isn't given
+const SPRT_GAMES: u32 = 100_000;
+
+// Plays engines against each other and reports the results with Elo
+// differences. With two engines it's a match, with more a round-robin or a
+// gauntlet of the first engine against the others. A match can also be an
+// SPRT of the first engine against the second.
+fn main() {
+    let mut engines: Vec<String> = Vec::new();
+    let mut format = Format::RoundRobin;
+    let mut options = MatchOptions::default();
+    let mut games: Option<u32> = None;
+    let mut pgn_path: Option<String> = None;
+    let mut sprt_options: Option<SprtOptions> = None;
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut i = 0;
+    while i < args.len() {
+        let value = |offset: usize| args.get(i + offset).map(|s| s.as_str());
+        let number = |offset: usize| value(offset).and_then(|value| value.parse::<u32>().ok());
+        let real = |offset: usize| value(offset).and_then(|value| value.parse::<f64>().ok());
+        let used = match (args[i].as_str(), value(1)) {
+            ("--engine", Some(engine)) => {
+                engines.push(engine.to_string());
+                2
+            }
+            ("--format", Some("round-robin")) => {
+                format = Format::RoundRobin;
+                2
+            }
+            ("--format", Some("gauntlet")) => {
+                format = Format::Gauntlet;
+                2
+            }
+            ("--games", _) if number(1).is_some() => {
+                games = number(1);
+                2
+            }
+            ("--openings", Some(path)) => {
+                match fs::read_to_string(path)
+                    .ok()
+                    .and_then(|text| read_openings(&text))
+                {
+                    Some(openings) => options.openings = openings,
+                    None => {
+                        eprintln!("Can't read openings from {}", path);
+                        return;
+                    }
+                }
+                2
+            }
+            ("--tc", Some(text)) if parse_clock(text).is_some() => {
+                options.time_control = parse_clock(text).unwrap();
+                2
+            }
+            ("--movetime", _) if number(1).is_some() => {
+                let time = Duration::from_millis(number(1).unwrap() as u64);
+                options.time_control = TimeControl::MoveTime(time);
+                2
+            }
+            ("--depth", _) if number(1).is_some() => {
+                options.time_control = TimeControl::Depth(number(1).unwrap());
+                2
+            }
+            ("--resign", _) if number(1).is_some() && number(2).is_some() => {
+                options.adjudication.resign_score = Some(number(1).unwrap() as i32);
+                options.adjudication.resign_moves = number(2).unwrap();
+                3
+            }
+            ("--draw", _) if number(1).is_some() && number(2).is_some() && number(3).is_some() => {
+                options.adjudication.draw_score = Some(number(1).unwrap() as i32);
+                options.adjudication.draw_moves = number(2).unwrap();
+                options.adjudication.draw_start = number(3).unwrap();
+                4
+            }
+            ("--max-moves", _) if number(1).is_some() => {
+                options.adjudication.max_moves = number(1);
+                2
+            }
+            ("--tablebase", Some(path)) => match Tablebase::open(path) {
+                Ok(tablebase) => {
+                    options.adjudication.tablebase = Some(tablebase);
+                    2
+                }
+                Err(error) => {
+                    eprintln!("Can't read tablebase {}: {}", path, error);
+                    return;
+                }
+            },
+            ("--pgn", Some(path)) => {
+                pgn_path = Some(path.to_string());
+                2
+            }
+            ("--sprt", _) if (1..=4).all(|offset| real(offset).is_some()) => {
+                sprt_options = Some(SprtOptions {
+                    elo0: real(1).unwrap(),
+                    elo1: real(2).unwrap(),
+                    alpha: real(3).unwrap(),
+                    beta: real(4).unwrap(),
+                });
+                5
+            }
+            _ => {
+                eprintln!("{}", USAGE);
+                return;
+            }
+        };
+        i += used;
+    }
+    if engines.len() < 2 || (sprt_options.is_some() && engines.len() != 2) {
+        eprintln!("{}", USAGE);
+        return;
+    }
+    options.games = match (games, sprt_options) {
+        (Some(games), _) => games,
+        (None, Some(_)) => SPRT_GAMES,
+        (None, None) => MatchOptions::default().games,
+    };
+
+    let mut players: Vec<Box<dyn Player>> = Vec::new();
+    for engine in &engines {
+        match start_engine(engine) {
+            Ok(player) => players.push(player),
+            Err(error) => {
+                eprintln!("Can't start {}: {}", engine, error);
+                return;
+            }
+        }
+    }
+    let names: Vec<String> = players.iter().map(|player| player.name()).collect();
+
+    let mut round = 0;
+    let mut record = |played: &PlayedGame| {
+        round += 1;
+        println!(
+            "Game {}: {} - {} {} ({})",
+            round,
+            played.white,
+            played.black,
+            played.outcome.as_pgn(),
+            played.termination
+        );
+        if let Some(path) = &pgn_path {
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", played.to_pgn("Engine match", round)));
+            if let Err(error) = written {
+                eprintln!("Can't write {}: {}", path, error);
+            }
+        }
+    };
+
+    if let Some(sprt_options) = sprt_options {
+        let (first, second) = players.split_at_mut(1);
+        let mut played_games = 0;
+        let tested = play_match(
+            first[0].as_mut(),
+            second[0].as_mut(),
+            &options,
+            |played, score| {
+                record(played);
+                // The test is updated after each pair of games
+                played_games += 1;
+                if played_games % 2 != 0 {
+                    return true;
+                }
+                let status = sprt(score, &sprt_options);
+                println!(
+                    "LLR {:.2} ({:.2}, {:.2})",
+                    status.llr, status.lower, status.upper
+                );
+                status.decision == Decision::Continue
+            },
+        );
+        match tested {
+            Ok(score) => {
+                println!();
+                println!("{} vs {}: {}", names[0], names[1], describe(&score));
+                let verdict = match sprt(&score, &sprt_options).decision {
+                    Decision::AcceptH1 => "H1 accepted",
+                    Decision::AcceptH0 => "H0 accepted",
+                    Decision::Continue => "no decision",
+                };
+                println!(
+                    "SPRT [{}, {}]: {}",
+                    sprt_options.elo0, sprt_options.elo1, verdict
+                );
+            }
+            Err(error) => eprintln!("The test stopped: {}", error),
+        }
+        return;
+    }
+
+    let results = play_tournament(&mut players, format, &options, record);
+    let results = match results {
+        Ok(results) => results,
+        Err(error) => {
+            eprintln!("The tournament stopped: {}", error);
+            return;
+        }
+    };
+
+    println!();
+    let mut points = vec![0.0; names.len()];
+    for (first, second, score) in &results {
+        points[*first] += score.points();
+        points[*second] += score.games() as f64 - score.points();
+        println!(
+            "{} vs {}: {}",
+            names[*first],
+            names[*second],
+            describe(score)
+        );
+    }
+    if names.len() > 2 {
+        println!();
+        let mut standings: Vec<usize> = (0..names.len()).collect();
+        standings.sort_by(|a, b| points[*b].partial_cmp(&points[*a]).unwrap());
+        for (place, player) in standings.iter().enumerate() {
+            println!("{}. {} {}", place + 1, names[*player], points[*player]);
+        }
+    }
+}
+
+// Starts the builtin engine or a UCI engine from its command line
+fn start_engine(engine: &str) -> std::io::Result<Box<dyn Player>> {
+    if let Some(depth) = engine.strip_prefix("builtin") {
+        let depth = depth
+            .strip_prefix(':')
+            .and_then(|depth| depth.parse().ok())
+            .unwrap_or(4);
+        return Ok(Box::new(BuiltinEngine::new(depth)));
+    }
+    let mut words = engine.split_whitespace().map(|word| word.to_string());
+    let command = words.next().unwrap_or_default();
+    let args: Vec<String> = words.collect();
+    Ok(Box::new(UciEngine::start(&command, &args)?))
+}
+
+// Reads a clock like `60+0.5`, in seconds
+fn parse_clock(text: &str) -> Option<TimeControl> {
+    let (base, increment) = text.split_once('+').unwrap_or((text, "0"));
+    Some(TimeControl::Clock {
+        base: Duration::try_from_secs_f64(base.parse().ok()?).ok()?,
+        increment: Duration::try_from_secs_f64(increment.parse().ok()?).ok()?,
+    })
+}
+
+// The score with the Elo difference, like `+5 =3 -2, Elo +108 ± 190`
+fn describe(score: &Score) -> String {
+    let elo = match score.elo() {
+        Some(elo) => format!("Elo {:+.0} ± {:.0}", elo.difference, elo.error),
+        None => String::from("Elo unknown"),
+    };
+    format!(
+        "+{} ={} -{} ({} of {}), {}",
+        score.wins,
+        score.draws,
+        score.losses,
+        score.points(),
+        score.games(),
+        elo
+    )
+}
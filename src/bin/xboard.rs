@@ -0,0 +1,55 @@
+use isaklar_chess::book::{BookOptions, OpeningBook};
+use isaklar_chess::tablebase::Tablebase;
+use isaklar_chess::xboard::XBoard;
+use std::env;
+use std::io::{self, BufRead, Write};
+
+// Plays over the xboard protocol on standard input and output.
+// `--book <file>` plays the openings from a Polyglot book and
+// `--tablebase <dir>` the endgames from Syzygy tables.
+fn main() {
+    let mut xboard = XBoard::new();
+    let args: Vec<String> = env::args().skip(1).collect();
+    for option in args.chunks(2) {
+        match option {
+            [flag, path] if flag == "--book" => match OpeningBook::open(path) {
+                Ok(book) => xboard.set_book(book, BookOptions::default()),
+                Err(error) => {
+                    eprintln!("Can't read book {}: {}", path, error);
+                    return;
+                }
+            },
+            [flag, path] if flag == "--tablebase" => match Tablebase::open(path) {
+                Ok(tablebase) => xboard.set_tablebase(tablebase),
+                Err(error) => {
+                    eprintln!("Can't read tablebase {}: {}", path, error);
+                    return;
+                }
+            },
+            _ => {
+                eprintln!("Usage: xboard [--book <file>] [--tablebase <dir>]");
+                return;
+            }
+        }
+    }
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim() == "quit" {
+            break;
+        }
+
+        let mut out = stdout.lock();
+        for reply in xboard.handle_command(&line) {
+            if writeln!(out, "{}", reply).is_err() {
+                return;
+            }
+        }
+        let _ = out.flush();
+    }
+}
@@ -0,0 +1,233 @@
+use crate::game::*;
+use crate::notation::*;
+use crate::zobrist::*;
+use std::collections::hash_map::RandomState;
+use std::convert::TryInto;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::Path;
+
+/// A move found in an opening book, with its weight relative to the other
+/// moves for the same position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BookMove {
+    pub from: String,
+    pub to: String,
+    pub weight: u16,
+}
+
+/// How a move is chosen among the book moves for a position.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BookSelection {
+    /// Always play the move with the highest weight.
+    Best,
+    /// Pick a move at random, with a chance proportional to its weight.
+    WeightedRandom,
+}
+
+/// Options for choosing moves from an opening book.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BookOptions {
+    pub selection: BookSelection,
+    /// Stop using the book after this many half moves, None for no limit.
+    pub max_depth: Option<usize>,
+}
+
+impl Default for BookOptions {
+    fn default() -> Self {
+        BookOptions {
+            selection: BookSelection::WeightedRandom,
+            max_depth: None,
+        }
+    }
+}
+
+/// An opening book in the Polyglot `.bin` format.
+#[derive(Clone, Debug, Default)]
+pub struct OpeningBook {
+    // (key, move, weight), sorted by key as in the file
+    entries: Vec<(u64, u16, u16)>,
+}
+
+impl OpeningBook {
+    /// Reads a Polyglot book from a file.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<OpeningBook> {
+        Ok(OpeningBook::from_bytes(&fs::read(path)?))
+    }
+
+    /// Reads a Polyglot book from its bytes. Each entry is 16 bytes; a
+    /// trailing partial entry is ignored.
+    pub fn from_bytes(bytes: &[u8]) -> OpeningBook {
+        let mut entries: Vec<(u64, u16, u16)> = bytes
+            .chunks_exact(16)
+            .map(|entry| {
+                let key = u64::from_be_bytes(entry[0..8].try_into().unwrap());
+                let book_move = u16::from_be_bytes([entry[8], entry[9]]);
+                let weight = u16::from_be_bytes([entry[10], entry[11]]);
+                (key, book_move, weight)
+            })
+            .collect();
+        // Books are sorted already, but a stable sort keeps the order of equal keys
+        entries.sort_by_key(|&(key, _, _)| key);
+        OpeningBook { entries }
+    }
+
+    /// Get the number of entries in the book.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// If the book has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Finds the legal book moves for the current position of a game,
+    /// highest weight first.
+    pub fn lookup(&self, game: &Game) -> Vec<BookMove> {
+        let key = polyglot_hash(game);
+        let start = self.entries.partition_point(|&(k, _, _)| k < key);
+        let mut moves: Vec<BookMove> = self.entries[start..]
+            .iter()
+            .take_while(|&&(k, _, _)| k == key)
+            .filter_map(|&(_, book_move, weight)| {
+                let (from, to) = parse_uci(game, &decode_move(book_move)?, true)?;
+                Some(BookMove { from, to, weight })
+            })
+            .collect();
+        moves.sort_by_key(|book_move| std::cmp::Reverse(book_move.weight));
+        moves
+    }
+
+    /// Chooses a book move for the current position of a game as a
+    /// (from, to) pair. Returns None if the position isn't in the book or
+    /// the game is deeper than the maximum book depth.
+    pub fn choose_move(&self, game: &Game, options: &BookOptions) -> Option<(String, String)> {
+        if options
+            .max_depth
+            .is_some_and(|depth| game.get_move_history().len() >= depth)
+        {
+            return None;
+        }
+        let moves = self.lookup(game);
+        let chosen = match options.selection {
+            BookSelection::Best => moves.first(),
+            BookSelection::WeightedRandom => {
+                pick_weighted(&moves, RandomState::new().build_hasher().finish())
+            }
+        }?;
+        Some((chosen.from.clone(), chosen.to.clone()))
+    }
+}
+
+// Writes a Polyglot move as UCI with castling as king-takes-rook.
+// Promotions can't be played yet, so they are skipped.
+fn decode_move(book_move: u16) -> Option<String> {
+    if book_move >> 12 != 0 {
+        return None;
+    }
+    let square = |bits: u16| {
+        let file = (bits & 7) as usize;
+        let rank = ((bits >> 3) & 7) as usize;
+        as_standard_notation(&(file + rank * 8)).to_lowercase()
+    };
+    Some(format!("{}{}", square(book_move >> 6), square(book_move)))
+}
+
+// Picks a move with a chance proportional to its weight, using a random number.
+// When all weights are 0 every move is equally likely.
+fn pick_weighted(moves: &[BookMove], random: u64) -> Option<&BookMove> {
+    let total: u64 = moves.iter().map(|book_move| book_move.weight as u64).sum();
+    if total == 0 {
+        return moves.get((random % moves.len().max(1) as u64) as usize);
+    }
+    let mut remaining = random % total;
+    for book_move in moves {
+        if remaining < book_move.weight as u64 {
+            return Some(book_move);
+        }
+        remaining -= book_move.weight as u64;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: u64, from: usize, to: usize, weight: u16) -> Vec<u8> {
+        let book_move = (((from / 8) << 9) | ((from % 8) << 6) | ((to / 8) << 3) | (to % 8)) as u16;
+        let mut bytes = key.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&book_move.to_be_bytes());
+        bytes.extend_from_slice(&weight.to_be_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes
+    }
+
+    fn start_book() -> OpeningBook {
+        let start = polyglot_hash(&Game::new());
+        let mut bytes = entry(start, 12, 28, 10);
+        bytes.extend(entry(start, 11, 27, 30));
+        bytes.extend(entry(start ^ 1, 6, 21, 100));
+        OpeningBook::from_bytes(&bytes)
+    }
+
+    #[test]
+    fn finds_moves_of_a_position() {
+        let book = start_book();
+        assert_eq!(book.len(), 3);
+        assert_eq!(
+            book.lookup(&Game::new()),
+            vec![
+                BookMove {
+                    from: String::from("D2"),
+                    to: String::from("D4"),
+                    weight: 30
+                },
+                BookMove {
+                    from: String::from("E2"),
+                    to: String::from("E4"),
+                    weight: 10
+                },
+            ]
+        );
+        let mut game = Game::new();
+        game.make_move(String::from("E2"), String::from("E4"));
+        assert!(book.lookup(&game).is_empty());
+    }
+
+    #[test]
+    fn chooses_best_move_within_depth() {
+        let book = start_book();
+        let options = BookOptions {
+            selection: BookSelection::Best,
+            max_depth: Some(1),
+        };
+        assert_eq!(
+            book.choose_move(&Game::new(), &options),
+            Some((String::from("D2"), String::from("D4")))
+        );
+        let options = BookOptions {
+            max_depth: Some(0),
+            ..options
+        };
+        assert_eq!(book.choose_move(&Game::new(), &options), None);
+    }
+
+    #[test]
+    fn weighted_pick_follows_weights() {
+        let moves = start_book().lookup(&Game::new());
+        assert_eq!(pick_weighted(&moves, 29).unwrap().to, "D4");
+        assert_eq!(pick_weighted(&moves, 30).unwrap().to, "E4");
+        assert_eq!(pick_weighted(&moves, 79).unwrap().to, "E4");
+        assert_eq!(pick_weighted(&[], 5), None);
+    }
+
+    #[test]
+    fn reads_castling_as_king_takes_rook() {
+        let game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let book = OpeningBook::from_bytes(&entry(polyglot_hash(&game), 4, 7, 1));
+        assert_eq!(book.lookup(&game)[0].to, "G1");
+    }
+}
@@ -0,0 +1,339 @@
+use crate::game::*;
+use crate::piece::*;
+use crate::validate::*;
+
+/// Sets up a game from any position, including the side to move, castling
+/// rights, en passant square and move counters.
+///
+/// The position is checked when the game is built, and the state of the
+/// game (check, checkmate or a tie) is calculated from it.
+#[derive(Clone, Debug)]
+pub struct GameBuilder {
+    board: [Option<Piece>; 64],
+    active_color: Color,
+    castling: CastlingRights,
+    en_passant: Option<String>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    chess960: bool,
+}
+
+impl GameBuilder {
+    /// Starts a setup from a board, with white to move, no castling rights
+    /// and no en passant square.
+    pub fn new(board: [Option<Piece>; 64]) -> GameBuilder {
+        GameBuilder {
+            board,
+            active_color: Color::White,
+            castling: CastlingRights::default(),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            chess960: false,
+        }
+    }
+
+    /// Starts a setup from a FEN string. Missing fields after the piece
+    /// placement get the same defaults as `GameBuilder::new`. Returns None if
+    /// the string can't be parsed.
+    pub fn from_fen(fen: &str) -> Option<GameBuilder> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.is_empty() || fields.len() > 6 {
+            return None;
+        }
+        let mut builder = GameBuilder::new(parse_placement(fields[0])?);
+
+        builder.active_color = match fields.get(1) {
+            None | Some(&"w") => Color::White,
+            Some(&"b") => Color::Black,
+            _ => return None,
+        };
+        if let Some(field) = fields.get(2) {
+            builder.castling = parse_castling(&builder.board, field)?;
+        }
+        match fields.get(3) {
+            None | Some(&"-") => (),
+            Some(square) => {
+                parse_square(square)?;
+                builder.en_passant = Some(square.to_uppercase());
+            }
+        }
+        if let Some(field) = fields.get(4) {
+            builder.halfmove_clock = field.parse().ok()?;
+        }
+        if let Some(field) = fields.get(5) {
+            builder.fullmove_number = field.parse().ok()?;
+        }
+        Some(builder)
+    }
+
+    /// Set the colour whose turn it is.
+    pub fn active_color(mut self, color: Color) -> GameBuilder {
+        self.active_color = color;
+        self
+    }
+
+    /// Set the rooks each side may still castle with.
+    pub fn castling_rights(mut self, castling: CastlingRights) -> GameBuilder {
+        self.castling = castling;
+        self
+    }
+
+    /// Set the square a pawn passed over with a double move in the last
+    /// move, e.g. "E3".
+    pub fn en_passant(mut self, square: &str) -> GameBuilder {
+        self.en_passant = Some(square.to_uppercase());
+        self
+    }
+
+    /// Set the number of half moves since the last capture or pawn move.
+    pub fn halfmove_clock(mut self, halfmove_clock: u32) -> GameBuilder {
+        self.halfmove_clock = halfmove_clock;
+        self
+    }
+
+    /// Set the number of the current full move.
+    pub fn fullmove_number(mut self, fullmove_number: u32) -> GameBuilder {
+        self.fullmove_number = fullmove_number;
+        self
+    }
+
+    /// Use Chess960 castling rules, where castling is given as the king
+    /// moving onto its own rook. This is also used when the castling rights
+    /// can't be from the classic starting position.
+    pub fn chess960(mut self, chess960: bool) -> GameBuilder {
+        self.chess960 = chess960;
+        self
+    }
+
+    /// Checks the position and starts a game from it, or returns the
+    /// problems found if the position can't be played.
+    pub fn build(self) -> Result<Game, Vec<PositionError>> {
+        let mut problems = validate_position(&self.board, self.active_color);
+
+        let en_passant = match &self.en_passant {
+            Some(square) => match parse_square(square) {
+                Some(index) if is_possible_en_passant(&self.board, self.active_color, index) => {
+                    Some(index)
+                }
+                _ => {
+                    problems.push(PositionError::ImpossibleEnPassant(square.clone()));
+                    None
+                }
+            },
+            None => None,
+        };
+        for &color in &[Color::White, Color::Black] {
+            if !is_possible_castling(&self.board, color, self.castling) {
+                problems.push(PositionError::ImpossibleCastlingRights(color));
+            }
+        }
+        if self.fullmove_number == 0 {
+            problems.push(PositionError::InvalidMoveNumber);
+        }
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        Ok(Game::from_parts(
+            self.board,
+            self.active_color,
+            self.castling,
+            en_passant,
+            self.halfmove_clock,
+            self.fullmove_number,
+            self.chess960 || !is_classic_castling(&self.board, self.castling),
+        ))
+    }
+}
+
+// Parses the castling field of a FEN string. Besides KQkq, file letters
+// name the rook to castle with, as in X-FEN and Shredder-FEN.
+fn parse_castling(board: &[Option<Piece>; 64], field: &str) -> Option<CastlingRights> {
+    let mut castling = CastlingRights::default();
+    if field == "-" {
+        return Some(castling);
+    }
+    for c in field.chars() {
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let back_rank = if color == Color::White { 0 } else { 7 };
+        let king_file = (0..8).find(|file| {
+            board[back_rank * 8 + file]
+                .is_some_and(|piece| piece.title() == PieceType::King && piece.color() == color)
+        });
+        let is_rook = |file: &usize| {
+            board[back_rank * 8 + file]
+                .is_some_and(|piece| piece.title() == PieceType::Rook && piece.color() == color)
+        };
+        // K and Q mean the outermost rook on that side, falling back to
+        // the standard files when there isn't one so validation can report it
+        let (rook_file, is_king_side) = match c.to_ascii_lowercase() {
+            'k' => {
+                let file = (0..8)
+                    .rev()
+                    .find(is_rook)
+                    .filter(|&file| king_file.is_none_or(|king_file| file > king_file));
+                (file.unwrap_or(7), true)
+            }
+            'q' => {
+                let file = (0..8)
+                    .find(is_rook)
+                    .filter(|&file| king_file.is_none_or(|king_file| file < king_file));
+                (file.unwrap_or(0), false)
+            }
+            letter @ 'a'..='h' => {
+                let file = (letter as u8 - b'a') as usize;
+                (file, king_file.is_none_or(|king_file| file > king_file))
+            }
+            _ => return None,
+        };
+        let side = match (color, is_king_side) {
+            (Color::White, true) => &mut castling.white_king_side,
+            (Color::White, false) => &mut castling.white_queen_side,
+            (Color::Black, true) => &mut castling.black_king_side,
+            (Color::Black, false) => &mut castling.black_queen_side,
+        };
+        *side = Some(rook_file);
+    }
+    Some(castling)
+}
+
+// Checks that the king and the rooks of a colour stand where its castling rights need them
+fn is_possible_castling(
+    board: &[Option<Piece>; 64],
+    color: Color,
+    castling: CastlingRights,
+) -> bool {
+    let (king_side, queen_side) = castling.get(color);
+    if king_side.is_none() && queen_side.is_none() {
+        return true;
+    }
+    let back_rank = if color == Color::White { 0 } else { 7 };
+    let king_file = match (0..8).find(|file| {
+        board[back_rank * 8 + file]
+            .is_some_and(|piece| piece.title() == PieceType::King && piece.color() == color)
+    }) {
+        Some(file) => file,
+        None => return false,
+    };
+    let is_rook = |file: usize| {
+        file < 8
+            && board[back_rank * 8 + file]
+                .is_some_and(|piece| piece.title() == PieceType::Rook && piece.color() == color)
+    };
+    king_side.is_none_or(|file| file > king_file && is_rook(file))
+        && queen_side.is_none_or(|file| file < king_file && is_rook(file))
+}
+
+// Checks that all castling rights are for a king on the e-file and rooks in the corners
+fn is_classic_castling(board: &[Option<Piece>; 64], castling: CastlingRights) -> bool {
+    [(Color::White, 4), (Color::Black, 60)]
+        .iter()
+        .all(|&(color, king_square)| {
+            let (king_side, queen_side) = castling.get(color);
+            let has_king = board[king_square]
+                .is_some_and(|piece| piece.title() == PieceType::King && piece.color() == color);
+            (king_side.is_none() && queen_side.is_none())
+                || (has_king
+                    && king_side.is_none_or(|file| file == 7)
+                    && queen_side.is_none_or(|file| file == 0))
+        })
+}
+
+// Checks that a pawn of the side that just moved could have passed over the square
+fn is_possible_en_passant(board: &[Option<Piece>; 64], active_color: Color, square: usize) -> bool {
+    // The pawn moved in the opposite direction of the active colour's pawns
+    let (rank, forward) = match active_color {
+        Color::White => (5, -8),
+        Color::Black => (2, 8),
+    };
+    let pawn = (square as i32 + forward) as usize;
+    let origin = (square as i32 - forward) as usize;
+    square / 8 == rank
+        && board[square].is_none()
+        && board[origin].is_none()
+        && board[pawn]
+            .is_some_and(|piece| piece.title() == PieceType::Pawn && piece.color() != active_color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_game_with_black_to_move() {
+        let game = GameBuilder::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")
+            .unwrap()
+            .active_color(Color::Black)
+            .fullmove_number(30)
+            .halfmove_clock(12)
+            .build()
+            .unwrap();
+        assert_eq!(game.get_active_color(), Color::Black);
+        assert_eq!(game.get_fullmove_number(), 30);
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 b - - 12 30");
+    }
+
+    #[test]
+    fn calculates_state_of_the_starting_position() {
+        let check = Game::from_fen("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1").unwrap();
+        assert_eq!(check.get_game_state(), GameState::WhiteCheck);
+        let mate = Game::from_fen("4k3/8/8/8/8/8/5PPP/r5K1 w - - 0 1").unwrap();
+        assert_eq!(mate.get_game_state(), GameState::WhiteCheckMate);
+        assert!(mate.is_game_over());
+        let stalemate = Game::from_fen("k7/8/1Q6/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(stalemate.get_game_state(), GameState::Tie);
+    }
+
+    #[test]
+    fn reads_castling_rights_and_en_passant() {
+        let game = Game::from_fen("r3k2r/8/8/3pP3/8/8/8/R3K2R w Kq d6 0 1").unwrap();
+        assert_eq!(
+            game.get_castling_rights(),
+            CastlingRights {
+                white_king_side: Some(7),
+                white_queen_side: None,
+                black_king_side: None,
+                black_queen_side: Some(0),
+            }
+        );
+        assert_eq!(game.get_en_passant(), Some(String::from("D6")));
+        assert_eq!(game.to_fen(), "r3k2r/8/8/3pP3/8/8/8/R3K2R w Kq d6 0 1");
+    }
+
+    #[test]
+    fn rejects_impossible_rights() {
+        let builder = GameBuilder::from_fen("4k3/8/8/8/8/8/8/4K3 w KQ e6 0 0").unwrap();
+        assert_eq!(
+            builder.build().err().unwrap(),
+            vec![
+                PositionError::ImpossibleEnPassant(String::from("E6")),
+                PositionError::ImpossibleCastlingRights(Color::White),
+                PositionError::InvalidMoveNumber,
+            ]
+        );
+        assert!(GameBuilder::from_fen("4k3/8/8/8/8/8/8/4K3 w - e9 0 1").is_none());
+        assert!(GameBuilder::from_fen("4k3/8/8/8/8/8/8/4K3 w X - 0 1").is_none());
+    }
+
+    #[test]
+    fn detects_chess960_castling_rights() {
+        let game =
+            Game::from_fen("bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEge - 0 1").unwrap();
+        assert!(game.is_chess960());
+        assert_eq!(game.get_castling_rights().white_king_side, Some(6));
+        assert_eq!(
+            game.to_fen(),
+            "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w KQkq - 0 1"
+        );
+        assert_eq!(
+            game.to_shredder_fen(),
+            "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEge - 0 1"
+        );
+        assert!(!Game::new().is_chess960());
+    }
+}
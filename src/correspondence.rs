@@ -0,0 +1,979 @@
+use crate::game::*;
+use crate::notation::*;
+use crate::piece::*;
+use crate::save::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Where the games of a `Correspondence` are kept, as PGN text by id.
+pub trait GameStore {
+    /// Reads the PGN of a game, or None if there is no game with the id.
+    fn read(&self, id: u64) -> io::Result<Option<String>>;
+    /// Writes the PGN of a game, replacing the earlier one.
+    fn write(&mut self, id: u64, pgn: &str) -> io::Result<()>;
+    /// The ids of all stored games in increasing order.
+    fn ids(&self) -> io::Result<Vec<u64>>;
+}
+
+/// Keeps each game in a file named after its id, like `12.pgn`, in a
+/// directory.
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    directory: PathBuf,
+}
+
+impl FileStore {
+    /// Uses a directory for the games, creating it if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(directory: P) -> io::Result<FileStore> {
+        fs::create_dir_all(&directory)?;
+        Ok(FileStore {
+            directory: directory.as_ref().to_path_buf(),
+        })
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.directory.join(format!("{}.pgn", id))
+    }
+}
+
+impl GameStore for FileStore {
+    fn read(&self, id: u64) -> io::Result<Option<String>> {
+        match fs::read_to_string(self.path(id)) {
+            Ok(pgn) => Ok(Some(pgn)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn write(&mut self, id: u64, pgn: &str) -> io::Result<()> {
+        write_atomically(&self.path(id), pgn)
+    }
+
+    fn ids(&self) -> io::Result<Vec<u64>> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "pgn") {
+                if let Some(id) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse().ok())
+                {
+                    ids.push(id);
+                }
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+}
+
+/// Keeps games in memory, for tests and servers that store games elsewhere.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    games: BTreeMap<u64, String>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl GameStore for MemoryStore {
+    fn read(&self, id: u64) -> io::Result<Option<String>> {
+        Ok(self.games.get(&id).cloned())
+    }
+
+    fn write(&mut self, id: u64, pgn: &str) -> io::Result<()> {
+        self.games.insert(id, pgn.to_string());
+        Ok(())
+    }
+
+    fn ids(&self) -> io::Result<Vec<u64>> {
+        Ok(self.games.keys().copied().collect())
+    }
+}
+
+/// The rules a correspondence game is created with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CorrespondenceOptions {
+    /// Days each player has for every move.
+    pub days_per_move: u32,
+    /// Days each player may be on vacation during the game, when their
+    /// deadlines are paused.
+    pub vacation_days: u32,
+}
+
+impl Default for CorrespondenceOptions {
+    fn default() -> Self {
+        CorrespondenceOptions {
+            days_per_move: 3,
+            vacation_days: 30,
+        }
+    }
+}
+
+/// If a game is still being played.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameStatus {
+    InProgress,
+    Finished,
+}
+
+/// Which games `Correspondence::query` returns. Every condition that is
+/// set has to match.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GameQuery {
+    /// Games the player plays in.
+    pub player: Option<String>,
+    pub status: Option<GameStatus>,
+    /// Games in progress where it's the player's turn.
+    pub to_move: Option<String>,
+}
+
+/// A reason a correspondence game refused a request.
+#[derive(Debug)]
+pub enum CorrespondenceError {
+    Io(io::Error),
+    NoSuchGame(u64),
+    /// The player doesn't play in the game.
+    NotAPlayer(String),
+    /// Names can't be empty or contain tabs or line breaks.
+    InvalidName(String),
+    /// A player can't play themself.
+    SamePlayer,
+    NotYourTurn,
+    GameOver,
+    IllegalMove,
+    /// The player's deadline passed before the move, so they lost on time.
+    DeadlinePassed,
+    /// Conditional moves have to start with a legal move of the opponent,
+    /// alternate with legal replies and end with a reply.
+    InvalidConditional,
+}
+
+impl fmt::Display for CorrespondenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CorrespondenceError::Io(error) => write!(f, "{}", error),
+            CorrespondenceError::NoSuchGame(id) => write!(f, "there is no game {}", id),
+            CorrespondenceError::NotAPlayer(player) => {
+                write!(f, "{} doesn't play in the game", player)
+            }
+            CorrespondenceError::InvalidName(name) => write!(f, "{:?} isn't a valid name", name),
+            CorrespondenceError::SamePlayer => write!(f, "a player can't play themself"),
+            CorrespondenceError::NotYourTurn => write!(f, "it's not your turn"),
+            CorrespondenceError::GameOver => write!(f, "the game is over"),
+            CorrespondenceError::IllegalMove => write!(f, "the move isn't legal"),
+            CorrespondenceError::DeadlinePassed => {
+                write!(f, "the deadline for the move has passed")
+            }
+            CorrespondenceError::InvalidConditional => {
+                write!(f, "the conditional moves can't be played")
+            }
+        }
+    }
+}
+
+impl From<io::Error> for CorrespondenceError {
+    fn from(error: io::Error) -> Self {
+        CorrespondenceError::Io(error)
+    }
+}
+
+/// A game played over days, with the players, deadlines, vacations and
+/// conditional moves. It's stored as PGN with extra tags.
+#[derive(Clone)]
+pub struct CorrespondenceGame {
+    id: u64,
+    white: String,
+    black: String,
+    game: Game,
+    days_per_move: u32,
+    // Times in seconds since the Unix epoch
+    turn_started: u64,
+    deadline: u64,
+    vacation_days: [u32; 2],
+    vacation_start: [Option<u64>; 2],
+    // Lines of moves each player has prepared, starting with the opponent's
+    // move and alternating with replies
+    conditionals: [Vec<Vec<(String, String)>>; 2],
+    // The result and how the game ended, like `1-0` and `time forfeit`
+    result: Option<(String, String)>,
+}
+
+impl CorrespondenceGame {
+    /// Get the id of the game in its store.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Get the name of the white player.
+    pub fn white(&self) -> &str {
+        &self.white
+    }
+
+    /// Get the name of the black player.
+    pub fn black(&self) -> &str {
+        &self.black
+    }
+
+    /// Get the game on the board.
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Get the days each player has for a move.
+    pub fn days_per_move(&self) -> u32 {
+        self.days_per_move
+    }
+
+    /// The colour a player plays, None if they don't play in the game.
+    pub fn color_of(&self, player: &str) -> Option<Color> {
+        if self.white == player {
+            Some(Color::White)
+        } else if self.black == player {
+            Some(Color::Black)
+        } else {
+            None
+        }
+    }
+
+    /// When the side to move has to have moved, later while they are on
+    /// vacation. None if the game is over.
+    pub fn deadline(&self, now: SystemTime) -> Option<SystemTime> {
+        if self.result.is_some() {
+            return None;
+        }
+        Some(UNIX_EPOCH + Duration::from_secs(self.effective_deadline(unix_seconds(now))))
+    }
+
+    /// The vacation days a colour has left.
+    pub fn vacation_days(&self, color: Color) -> u32 {
+        self.vacation_days[color_index(color)]
+    }
+
+    /// If the player of a colour is on vacation.
+    pub fn is_on_vacation(&self, color: Color) -> bool {
+        self.vacation_start[color_index(color)].is_some()
+    }
+
+    /// The lines of conditional moves a colour has prepared, as `(from, to)`
+    /// pairs starting with the opponent's move.
+    pub fn conditionals(&self, color: Color) -> &[Vec<(String, String)>] {
+        &self.conditionals[color_index(color)]
+    }
+
+    /// The result of the game, like `1-0`, or None if it's in progress.
+    pub fn result(&self) -> Option<&str> {
+        self.result.as_ref().map(|(result, _)| result.as_str())
+    }
+
+    /// How the game ended: `normal` on the board or `time forfeit`.
+    pub fn termination(&self) -> Option<&str> {
+        self.result.as_ref().map(|(_, reason)| reason.as_str())
+    }
+
+    /// If the game is still being played.
+    pub fn status(&self) -> GameStatus {
+        if self.result.is_some() {
+            GameStatus::Finished
+        } else {
+            GameStatus::InProgress
+        }
+    }
+
+    fn make_move(
+        &mut self,
+        player: &str,
+        from: String,
+        to: String,
+        now: u64,
+    ) -> Result<GameState, CorrespondenceError> {
+        let color = self
+            .color_of(player)
+            .ok_or_else(|| CorrespondenceError::NotAPlayer(player.to_string()))?;
+        if self.result.is_some() {
+            return Err(CorrespondenceError::GameOver);
+        }
+        if color != self.game.get_active_color() {
+            return Err(CorrespondenceError::NotYourTurn);
+        }
+        if now > self.effective_deadline(now) {
+            self.forfeit_on_time();
+            return Err(CorrespondenceError::DeadlinePassed);
+        }
+        let (from, to) = (from.to_uppercase(), to.to_uppercase());
+        self.game
+            .make_move(from.clone(), to.clone())
+            .ok_or(CorrespondenceError::IllegalMove)?;
+        // Moving brings a player back from vacation
+        self.end_vacation(color, now);
+        self.after_move(from, to, now);
+        Ok(self.game.get_game_state())
+    }
+
+    // Starts the next turn and plays the conditional replies to the move
+    fn after_move(&mut self, mut from: String, mut to: String, now: u64) {
+        loop {
+            if self.game.is_game_over() {
+                self.result = Some((game_result(&self.game).to_string(), String::from("normal")));
+                self.conditionals = [Vec::new(), Vec::new()];
+                return;
+            }
+            self.turn_started = now;
+            self.deadline = now + self.days_per_move as u64 * DAY;
+
+            let replier = color_index(self.game.get_active_color());
+            let played = (from.clone(), to.clone());
+            let lines: Vec<Vec<(String, String)>> = self.conditionals[replier]
+                .drain(..)
+                .filter(|line| line.first() == Some(&played))
+                .map(|line| line[1..].to_vec())
+                .collect();
+            let reply = match lines.first().and_then(|line| line.first()) {
+                Some(reply) => reply.clone(),
+                None => return,
+            };
+            self.conditionals[replier] = lines
+                .into_iter()
+                .filter(|line| line.first() == Some(&reply))
+                .map(|line| line[1..].to_vec())
+                .filter(|line| !line.is_empty())
+                .collect();
+            if self
+                .game
+                .make_move(reply.0.clone(), reply.1.clone())
+                .is_none()
+            {
+                self.conditionals[replier].clear();
+                return;
+            }
+            from = reply.0;
+            to = reply.1;
+        }
+    }
+
+    fn add_conditional(
+        &mut self,
+        player: &str,
+        line: &[(String, String)],
+    ) -> Result<(), CorrespondenceError> {
+        let color = self
+            .color_of(player)
+            .ok_or_else(|| CorrespondenceError::NotAPlayer(player.to_string()))?;
+        if self.result.is_some() {
+            return Err(CorrespondenceError::GameOver);
+        }
+        if color == self.game.get_active_color() || line.is_empty() || !line.len().is_multiple_of(2)
+        {
+            return Err(CorrespondenceError::InvalidConditional);
+        }
+        let line: Vec<(String, String)> = line
+            .iter()
+            .map(|(from, to)| (from.to_uppercase(), to.to_uppercase()))
+            .collect();
+        let mut replay = self.game.clone();
+        for (from, to) in &line {
+            replay
+                .make_move(from.clone(), to.clone())
+                .ok_or(CorrespondenceError::InvalidConditional)?;
+        }
+        self.conditionals[color_index(color)].push(line);
+        Ok(())
+    }
+
+    // The deadline of the side to move, moved back by the time they have
+    // been on vacation during their turn
+    fn effective_deadline(&self, now: u64) -> u64 {
+        let index = color_index(self.game.get_active_color());
+        match self.vacation_start[index] {
+            Some(start) => self.deadline + self.vacation_pause(index, start, now),
+            None => self.deadline,
+        }
+    }
+
+    // The part of a vacation that falls in the current turn, up to the
+    // vacation days left
+    fn vacation_pause(&self, index: usize, start: u64, now: u64) -> u64 {
+        let paused_from = start.max(self.turn_started);
+        let allowance =
+            (self.vacation_days[index] as u64 * DAY).saturating_sub(paused_from - start);
+        now.saturating_sub(paused_from).min(allowance)
+    }
+
+    fn start_vacation(&mut self, color: Color, now: u64) -> bool {
+        let index = color_index(color);
+        if self.result.is_some()
+            || self.vacation_start[index].is_some()
+            || self.vacation_days[index] == 0
+        {
+            return false;
+        }
+        self.vacation_start[index] = Some(now);
+        true
+    }
+
+    // Ends a vacation, using up every day it was started on
+    fn end_vacation(&mut self, color: Color, now: u64) -> bool {
+        let index = color_index(color);
+        let start = match self.vacation_start[index] {
+            Some(start) => start,
+            None => return false,
+        };
+        if color == self.game.get_active_color() && self.result.is_none() {
+            self.deadline += self.vacation_pause(index, start, now);
+        }
+        let used = now.saturating_sub(start).div_ceil(DAY);
+        self.vacation_days[index] = self.vacation_days[index].saturating_sub(used as u32);
+        self.vacation_start[index] = None;
+        true
+    }
+
+    fn forfeit_on_time(&mut self) {
+        let result = match self.game.get_active_color() {
+            Color::White => "0-1",
+            Color::Black => "1-0",
+        };
+        self.result = Some((result.to_string(), String::from("time forfeit")));
+        self.conditionals = [Vec::new(), Vec::new()];
+    }
+
+    fn to_pgn(&self) -> String {
+        let mut tags = vec![
+            (String::from("Event"), String::from("Correspondence game")),
+            (String::from("White"), self.white.clone()),
+            (String::from("Black"), self.black.clone()),
+            (String::from("DaysPerMove"), self.days_per_move.to_string()),
+            (String::from("TurnStarted"), self.turn_started.to_string()),
+            (String::from("Deadline"), self.deadline.to_string()),
+        ];
+        for &color in &[Color::White, Color::Black] {
+            let index = color_index(color);
+            tags.push((
+                format!("{:?}VacationDays", color),
+                self.vacation_days[index].to_string(),
+            ));
+            if let Some(start) = self.vacation_start[index] {
+                tags.push((format!("{:?}VacationStart", color), start.to_string()));
+            }
+            if !self.conditionals[index].is_empty() {
+                tags.push((
+                    format!("{:?}Conditionals", color),
+                    write_conditionals(&self.conditionals[index]),
+                ));
+            }
+        }
+        if let Some((result, termination)) = &self.result {
+            tags.push((String::from("Result"), result.clone()));
+            tags.push((String::from("Termination"), termination.clone()));
+        }
+        to_pgn(&self.game, &tags)
+    }
+
+    fn from_pgn(id: u64, pgn: &str) -> Option<CorrespondenceGame> {
+        let (game, tags) = parse_pgn(pgn)?;
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(tag, _)| tag == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let number = |name: &str| tag(name)?.parse::<u64>().ok();
+        let per_color = |name: &str| {
+            [
+                tag(&format!("White{}", name)),
+                tag(&format!("Black{}", name)),
+            ]
+        };
+        let [white_days, black_days] = per_color("VacationDays");
+        let [white_start, black_start] = per_color("VacationStart");
+        let [white_lines, black_lines] = per_color("Conditionals");
+        let result = match (tag("Result"), tag("Termination")) {
+            (Some(result), Some(termination)) if result != "*" => {
+                Some((result.to_string(), termination.to_string()))
+            }
+            _ => None,
+        };
+        Some(CorrespondenceGame {
+            id,
+            white: tag("White")?.to_string(),
+            black: tag("Black")?.to_string(),
+            game,
+            days_per_move: number("DaysPerMove")? as u32,
+            turn_started: number("TurnStarted")?,
+            deadline: number("Deadline")?,
+            vacation_days: [white_days?.parse().ok()?, black_days?.parse().ok()?],
+            vacation_start: [
+                white_start.and_then(|start| start.parse().ok()),
+                black_start.and_then(|start| start.parse().ok()),
+            ],
+            conditionals: [
+                white_lines.map_or(Some(Vec::new()), read_conditionals)?,
+                black_lines.map_or(Some(Vec::new()), read_conditionals)?,
+            ],
+            result,
+        })
+    }
+}
+
+/// Many correspondence games by id, kept in a `GameStore`. Every change is
+/// written to the store right away. Times are passed in, so the deadlines
+/// can be checked against any clock.
+pub struct Correspondence<S: GameStore = FileStore> {
+    store: S,
+}
+
+impl Correspondence<FileStore> {
+    /// Keeps the games in files in a directory.
+    pub fn open<P: AsRef<Path>>(directory: P) -> io::Result<Correspondence<FileStore>> {
+        Ok(Correspondence::new(FileStore::open(directory)?))
+    }
+}
+
+impl<S: GameStore> Correspondence<S> {
+    /// Keeps the games in a store.
+    pub fn new(store: S) -> Correspondence<S> {
+        Correspondence { store }
+    }
+
+    /// Get the store the games are kept in.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Starts a new game between two players and returns its id. The first
+    /// deadline is counted from `now`.
+    pub fn create(
+        &mut self,
+        white: &str,
+        black: &str,
+        options: &CorrespondenceOptions,
+        now: SystemTime,
+    ) -> Result<u64, CorrespondenceError> {
+        for name in &[white, black] {
+            if name.is_empty() || name.contains(['\t', '\n', '\r']) {
+                return Err(CorrespondenceError::InvalidName(name.to_string()));
+            }
+        }
+        if white == black {
+            return Err(CorrespondenceError::SamePlayer);
+        }
+        let id = self.store.ids()?.last().map_or(1, |id| id + 1);
+        let now = unix_seconds(now);
+        let game = CorrespondenceGame {
+            id,
+            white: white.to_string(),
+            black: black.to_string(),
+            game: Game::new(),
+            days_per_move: options.days_per_move,
+            turn_started: now,
+            deadline: now + options.days_per_move as u64 * DAY,
+            vacation_days: [options.vacation_days; 2],
+            vacation_start: [None, None],
+            conditionals: [Vec::new(), Vec::new()],
+            result: None,
+        };
+        self.save(&game)?;
+        Ok(id)
+    }
+
+    /// Reads a game from the store.
+    pub fn get(&self, id: u64) -> Result<CorrespondenceGame, CorrespondenceError> {
+        let pgn = self
+            .store
+            .read(id)?
+            .ok_or(CorrespondenceError::NoSuchGame(id))?;
+        CorrespondenceGame::from_pgn(id, &pgn).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "invalid correspondence game").into()
+        })
+    }
+
+    /// Makes a player's move and plays the opponent's conditional replies
+    /// to it. Returns the state of the game afterwards. A player whose
+    /// deadline has passed loses on time instead.
+    pub fn make_move(
+        &mut self,
+        id: u64,
+        player: &str,
+        from: String,
+        to: String,
+        now: SystemTime,
+    ) -> Result<GameState, CorrespondenceError> {
+        let mut game = self.get(id)?;
+        let state = game.make_move(player, from, to, unix_seconds(now));
+        if state.is_ok() || game.result.is_some() {
+            self.save(&game)?;
+        }
+        state
+    }
+
+    /// Prepares replies for a player while the opponent is to move, like
+    /// "if E7-E5 is played, I reply G1-F3". The line starts with the
+    /// opponent's move and alternates with replies. Several lines can be
+    /// added, and they are dropped when the opponent plays another move.
+    pub fn add_conditional(
+        &mut self,
+        id: u64,
+        player: &str,
+        line: &[(String, String)],
+    ) -> Result<(), CorrespondenceError> {
+        let mut game = self.get(id)?;
+        game.add_conditional(player, line)?;
+        self.save(&game)
+    }
+
+    /// Removes the conditional moves of a player.
+    pub fn clear_conditionals(&mut self, id: u64, player: &str) -> Result<(), CorrespondenceError> {
+        let mut game = self.get(id)?;
+        let color = game
+            .color_of(player)
+            .ok_or_else(|| CorrespondenceError::NotAPlayer(player.to_string()))?;
+        game.conditionals[color_index(color)].clear();
+        self.save(&game)
+    }
+
+    /// Sends a player on vacation in all their games in progress where they
+    /// have vacation days left. Their deadlines stop until they return or
+    /// the days run out. Returns the ids of those games.
+    pub fn start_vacation(
+        &mut self,
+        player: &str,
+        now: SystemTime,
+    ) -> Result<Vec<u64>, CorrespondenceError> {
+        self.update_games_of(player, |game, color| {
+            game.start_vacation(color, unix_seconds(now))
+        })
+    }
+
+    /// Brings a player back from vacation. Every day started on vacation
+    /// is taken from their vacation days. Returns the ids of the games the
+    /// vacation ended in.
+    pub fn end_vacation(
+        &mut self,
+        player: &str,
+        now: SystemTime,
+    ) -> Result<Vec<u64>, CorrespondenceError> {
+        self.update_games_of(player, |game, color| {
+            game.end_vacation(color, unix_seconds(now))
+        })
+    }
+
+    /// Ends the games where the side to move has missed their deadline, as
+    /// a loss on time. Returns the ids of those games.
+    pub fn check_deadlines(&mut self, now: SystemTime) -> Result<Vec<u64>, CorrespondenceError> {
+        let now = unix_seconds(now);
+        let mut forfeited = Vec::new();
+        for id in self.store.ids()? {
+            let mut game = self.get(id)?;
+            if game.result.is_none() && now > game.effective_deadline(now) {
+                game.forfeit_on_time();
+                self.save(&game)?;
+                forfeited.push(id);
+            }
+        }
+        Ok(forfeited)
+    }
+
+    /// The games that match a query, by id.
+    pub fn query(&self, query: &GameQuery) -> Result<Vec<CorrespondenceGame>, CorrespondenceError> {
+        let mut games = Vec::new();
+        for id in self.store.ids()? {
+            let game = self.get(id)?;
+            let plays = |player: &Option<String>| {
+                player
+                    .as_ref()
+                    .is_none_or(|player| game.color_of(player).is_some())
+            };
+            let is_to_move = query.to_move.as_ref().is_none_or(|player| {
+                game.status() == GameStatus::InProgress
+                    && game.color_of(player) == Some(game.game.get_active_color())
+            });
+            if plays(&query.player)
+                && query.status.is_none_or(|status| game.status() == status)
+                && is_to_move
+            {
+                games.push(game);
+            }
+        }
+        Ok(games)
+    }
+
+    // Changes the games in progress of a player and saves the ones where
+    // the change returned true
+    fn update_games_of<F>(
+        &mut self,
+        player: &str,
+        mut change: F,
+    ) -> Result<Vec<u64>, CorrespondenceError>
+    where
+        F: FnMut(&mut CorrespondenceGame, Color) -> bool,
+    {
+        let query = GameQuery {
+            player: Some(player.to_string()),
+            status: Some(GameStatus::InProgress),
+            to_move: None,
+        };
+        let mut changed = Vec::new();
+        for mut game in self.query(&query)? {
+            let color = game.color_of(player).unwrap();
+            if change(&mut game, color) {
+                self.save(&game)?;
+                changed.push(game.id);
+            }
+        }
+        Ok(changed)
+    }
+
+    fn save(&mut self, game: &CorrespondenceGame) -> Result<(), CorrespondenceError> {
+        Ok(self.store.write(game.id, &game.to_pgn())?)
+    }
+}
+
+// Lines of moves as squares like `e7e5 g1f3`, separated by commas
+fn write_conditionals(lines: &[Vec<(String, String)>]) -> String {
+    lines
+        .iter()
+        .map(|line| {
+            line.iter()
+                .map(|(from, to)| format!("{}{}", from, to).to_lowercase())
+                .collect::<Vec<String>>()
+                .join(" ")
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn read_conditionals(text: &str) -> Option<Vec<Vec<(String, String)>>> {
+    text.split(',')
+        .map(|line| {
+            line.split_whitespace()
+                .map(|text| {
+                    let squares = (text.get(0..2)?, text.get(2..4)?);
+                    if text.len() != 4
+                        || parse_square(squares.0).is_none()
+                        || parse_square(squares.1).is_none()
+                    {
+                        return None;
+                    }
+                    Some((squares.0.to_uppercase(), squares.1.to_uppercase()))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn at_day(day: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_600_000_000 + day * DAY)
+    }
+
+    fn line(moves: &[(&str, &str)]) -> Vec<(String, String)> {
+        moves
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn stores_and_queries_games() {
+        let directory = env::temp_dir().join(format!("correspondence-test-{}", std::process::id()));
+        let mut games = Correspondence::open(&directory).unwrap();
+        let options = CorrespondenceOptions::default();
+        let first = games.create("Ada", "Bo", &options, at_day(0)).unwrap();
+        let second = games
+            .create("Cy \"C:\\\"", "Ada", &options, at_day(0))
+            .unwrap();
+        assert_eq!((first, second), (1, 2));
+        assert!(matches!(
+            games.create("Ada\n[Result", "Bo", &options, at_day(0)),
+            Err(CorrespondenceError::InvalidName(_))
+        ));
+        assert!(matches!(
+            games.create("Ada", "Ada", &options, at_day(0)),
+            Err(CorrespondenceError::SamePlayer)
+        ));
+
+        assert!(matches!(
+            games.make_move(
+                first,
+                "Bo",
+                String::from("E7"),
+                String::from("E5"),
+                at_day(0)
+            ),
+            Err(CorrespondenceError::NotYourTurn)
+        ));
+        assert!(matches!(
+            games.make_move(
+                first,
+                "Cy",
+                String::from("E2"),
+                String::from("E4"),
+                at_day(0)
+            ),
+            Err(CorrespondenceError::NotAPlayer(_))
+        ));
+        games
+            .make_move(
+                first,
+                "Ada",
+                String::from("E2"),
+                String::from("E4"),
+                at_day(1),
+            )
+            .unwrap();
+
+        // The games are read back from their files
+        let games = Correspondence::open(&directory).unwrap();
+        let game = games.get(first).unwrap();
+        assert_eq!(game.game().get_move_history(), line(&[("E2", "E4")]));
+        assert_eq!(game.deadline(at_day(1)), Some(at_day(4)));
+        assert_eq!(games.get(second).unwrap().white(), "Cy \"C:\\\"");
+        assert!(matches!(
+            games.get(3),
+            Err(CorrespondenceError::NoSuchGame(3))
+        ));
+
+        let ids = |query: &GameQuery| -> Vec<u64> {
+            games
+                .query(query)
+                .unwrap()
+                .iter()
+                .map(|game| game.id())
+                .collect()
+        };
+        let of_ada = GameQuery {
+            player: Some(String::from("Ada")),
+            ..GameQuery::default()
+        };
+        assert_eq!(ids(&of_ada), vec![1, 2]);
+        let ada_to_move = GameQuery {
+            to_move: Some(String::from("Ada")),
+            ..GameQuery::default()
+        };
+        assert_eq!(ids(&ada_to_move), Vec::<u64>::new());
+        let finished = GameQuery {
+            status: Some(GameStatus::Finished),
+            ..GameQuery::default()
+        };
+        assert_eq!(ids(&finished), Vec::<u64>::new());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn plays_conditional_moves() {
+        let mut games = Correspondence::new(MemoryStore::new());
+        let id = games
+            .create("Ada", "Bo", &CorrespondenceOptions::default(), at_day(0))
+            .unwrap();
+        games
+            .make_move(id, "Ada", String::from("E2"), String::from("E4"), at_day(0))
+            .unwrap();
+        games
+            .add_conditional(
+                id,
+                "Ada",
+                &line(&[("E7", "E5"), ("G1", "F3"), ("B8", "C6"), ("F1", "B5")]),
+            )
+            .unwrap();
+        games
+            .add_conditional(id, "Ada", &line(&[("C7", "C5"), ("G1", "F3")]))
+            .unwrap();
+        assert!(matches!(
+            games.add_conditional(id, "Ada", &line(&[("E7", "E5"), ("E4", "E6")])),
+            Err(CorrespondenceError::InvalidConditional)
+        ));
+        assert!(matches!(
+            games.add_conditional(id, "Bo", &line(&[("E7", "E5"), ("G1", "F3")])),
+            Err(CorrespondenceError::InvalidConditional)
+        ));
+
+        games
+            .make_move(id, "Bo", String::from("E7"), String::from("E5"), at_day(1))
+            .unwrap();
+        let game = games.get(id).unwrap();
+        assert_eq!(
+            game.game().get_move_history(),
+            line(&[("E2", "E4"), ("E7", "E5"), ("G1", "F3")])
+        );
+        assert_eq!(
+            game.conditionals(Color::White),
+            &[line(&[("B8", "C6"), ("F1", "B5")])]
+        );
+
+        // Another move drops the remaining lines
+        games
+            .make_move(id, "Bo", String::from("G8"), String::from("F6"), at_day(2))
+            .unwrap();
+        let game = games.get(id).unwrap();
+        assert_eq!(game.game().get_move_history().len(), 4);
+        assert!(game.conditionals(Color::White).is_empty());
+    }
+
+    #[test]
+    fn pauses_deadlines_on_vacation() {
+        let mut games = Correspondence::new(MemoryStore::new());
+        let id = games
+            .create("Ada", "Bo", &CorrespondenceOptions::default(), at_day(0))
+            .unwrap();
+        games
+            .make_move(id, "Ada", String::from("E2"), String::from("E4"), at_day(0))
+            .unwrap();
+
+        assert_eq!(games.start_vacation("Bo", at_day(1)).unwrap(), vec![id]);
+        assert!(games.check_deadlines(at_day(4)).unwrap().is_empty());
+        assert_eq!(games.end_vacation("Bo", at_day(5)).unwrap(), vec![id]);
+        let game = games.get(id).unwrap();
+        assert_eq!(game.deadline(at_day(5)), Some(at_day(7)));
+        assert_eq!(game.vacation_days(Color::Black), 26);
+        assert!(!game.is_on_vacation(Color::Black));
+
+        assert!(games.check_deadlines(at_day(6)).unwrap().is_empty());
+        assert_eq!(games.check_deadlines(at_day(8)).unwrap(), vec![id]);
+        let game = games.get(id).unwrap();
+        assert_eq!(game.result(), Some("1-0"));
+        assert_eq!(game.termination(), Some("time forfeit"));
+        assert!(matches!(
+            games.make_move(id, "Bo", String::from("E7"), String::from("E5"), at_day(8)),
+            Err(CorrespondenceError::GameOver)
+        ));
+
+        // Deadlines run again when the vacation days are used up
+        let options = CorrespondenceOptions {
+            days_per_move: 2,
+            vacation_days: 1,
+        };
+        let id = games.create("Ada", "Bo", &options, at_day(0)).unwrap();
+        games.start_vacation("Ada", at_day(0)).unwrap();
+        assert_eq!(games.get(id).unwrap().deadline(at_day(10)), Some(at_day(3)));
+        assert!(matches!(
+            games.make_move(id, "Ada", String::from("E2"), String::from("E4"), at_day(4)),
+            Err(CorrespondenceError::DeadlinePassed)
+        ));
+        assert_eq!(games.get(id).unwrap().result(), Some("0-1"));
+    }
+}
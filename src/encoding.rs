@@ -0,0 +1,252 @@
+use crate::builder::*;
+use crate::game::*;
+use crate::piece::*;
+
+// The order of the 4 bit piece codes, white pieces first
+const PIECE_CODES: [(Color, PieceType); 12] = [
+    (Color::White, PieceType::Pawn),
+    (Color::White, PieceType::Knight),
+    (Color::White, PieceType::Bishop),
+    (Color::White, PieceType::Rook),
+    (Color::White, PieceType::Queen),
+    (Color::White, PieceType::King),
+    (Color::Black, PieceType::Pawn),
+    (Color::Black, PieceType::Knight),
+    (Color::Black, PieceType::Bishop),
+    (Color::Black, PieceType::Rook),
+    (Color::Black, PieceType::Queen),
+    (Color::Black, PieceType::King),
+];
+
+// The occupancy and the state before the piece codes
+const HEADER_LENGTH: usize = 8 + 7;
+
+/// A move from one square to another, which can be stored in 16 bits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Move {
+    from: usize,
+    to: usize,
+}
+
+impl Move {
+    /// Creates a move between two squares like `E2` and `E4`. Returns None
+    /// if a square doesn't exist.
+    pub fn new(from: &str, to: &str) -> Option<Move> {
+        Some(Move {
+            from: parse_square(from)?,
+            to: parse_square(to)?,
+        })
+    }
+
+    /// Get the square the move starts from.
+    pub fn from(&self) -> String {
+        as_standard_notation(&self.from)
+    }
+
+    /// Get the square the move goes to.
+    pub fn to(&self) -> String {
+        as_standard_notation(&self.to)
+    }
+
+    /// Encodes the move in 16 bits: the start square in bits 6 to 11 and
+    /// the target square in bits 0 to 5, counting squares from A1 to H8.
+    /// The top 4 bits are kept for promotions and are always 0.
+    pub fn encode(&self) -> u16 {
+        ((self.from << 6) | self.to) as u16
+    }
+
+    /// Decodes a move written by `encode`. Returns None if the reserved
+    /// bits are set.
+    pub fn decode(bits: u16) -> Option<Move> {
+        if bits >> 12 != 0 {
+            return None;
+        }
+        Some(Move {
+            from: ((bits >> 6) & 63) as usize,
+            to: (bits & 63) as usize,
+        })
+    }
+}
+
+/// Encodes the current position of a game in 15 bytes plus half a byte per
+/// piece, at most 31 bytes:
+///
+/// - 8 bytes with a bit set for every occupied square, A1 in the lowest bit
+/// - 1 byte of flags: bit 0 if black is to move, bit 1 for Chess960, bit 2
+///   if there is an en passant square and bits 3 to 5 for its file
+/// - 2 bytes of castling rights, 4 bits each for white king side, white
+///   queen side, black king side and black queen side: bit 3 if the right
+///   exists and bits 0 to 2 for the rook's file
+/// - 2 bytes each for the halfmove clock and the fullmove number, which
+///   are capped at 65535
+/// - a 4 bit code for each occupied square in order, two per byte with the
+///   first in the high bits
+///
+/// Numbers are big-endian. The move history isn't encoded.
+pub fn encode_position(game: &Game) -> Vec<u8> {
+    let board = game.get_board();
+    let occupancy = (0..64)
+        .filter(|&square| board[square].is_some())
+        .fold(0u64, |bits, square| bits | 1 << square);
+    let mut bytes = occupancy.to_be_bytes().to_vec();
+
+    let mut flags = 0;
+    if game.get_active_color() == Color::Black {
+        flags |= 1;
+    }
+    if game.is_chess960() {
+        flags |= 2;
+    }
+    if let Some(square) = game
+        .get_en_passant()
+        .and_then(|square| parse_square(&square))
+    {
+        flags |= 4 | ((square % 8) as u8) << 3;
+    }
+    bytes.push(flags);
+
+    let castling = game.get_castling_rights();
+    let rights = [
+        castling.white_king_side,
+        castling.white_queen_side,
+        castling.black_king_side,
+        castling.black_queen_side,
+    ];
+    let castling = rights.iter().fold(0u16, |bits, right| {
+        bits << 4 | right.map_or(0, |file| 8 | file as u16)
+    });
+    bytes.extend_from_slice(&castling.to_be_bytes());
+    for number in &[game.get_halfmove_clock(), game.get_fullmove_number()] {
+        bytes.extend_from_slice(&((*number).min(u16::MAX as u32) as u16).to_be_bytes());
+    }
+
+    let codes: Vec<u8> = board
+        .iter()
+        .flatten()
+        .map(|piece| {
+            PIECE_CODES
+                .iter()
+                .position(|&(color, title)| color == piece.color() && title == piece.title())
+                .unwrap() as u8
+        })
+        .collect();
+    for pair in codes.chunks(2) {
+        bytes.push(pair[0] << 4 | pair.get(1).unwrap_or(&0));
+    }
+    bytes
+}
+
+/// Decodes a position written by `encode_position` into a new game.
+/// Returns None if the bytes are malformed or the position can't be
+/// played.
+pub fn decode_position(bytes: &[u8]) -> Option<Game> {
+    if bytes.len() < HEADER_LENGTH {
+        return None;
+    }
+    let mut occupancy = [0; 8];
+    occupancy.copy_from_slice(&bytes[0..8]);
+    let occupancy = u64::from_be_bytes(occupancy);
+    let pieces = occupancy.count_ones() as usize;
+    if bytes.len() != HEADER_LENGTH + pieces.div_ceil(2) {
+        return None;
+    }
+
+    let mut board = [None; 64];
+    let codes = bytes[HEADER_LENGTH..]
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 15]);
+    let squares = (0..64).filter(|square| occupancy & 1 << square != 0);
+    for (square, code) in squares.zip(codes) {
+        let &(color, title) = PIECE_CODES.get(code as usize)?;
+        // Pawns off their starting rank have moved, like in FEN
+        let start_rank = if color == Color::White { 1 } else { 6 };
+        let has_moved = title == PieceType::Pawn && square / 8 != start_rank;
+        board[square] = Some(Piece::new(color, title, has_moved));
+    }
+
+    let flags = bytes[8];
+    let active_color = if flags & 1 == 0 {
+        Color::White
+    } else {
+        Color::Black
+    };
+    let castling = u16::from_be_bytes([bytes[9], bytes[10]]);
+    let right = |shift: u16| {
+        let bits = (castling >> shift) & 15;
+        if bits & 8 == 0 {
+            None
+        } else {
+            Some((bits & 7) as usize)
+        }
+    };
+    let mut builder = GameBuilder::new(board)
+        .active_color(active_color)
+        .castling_rights(CastlingRights {
+            white_king_side: right(12),
+            white_queen_side: right(8),
+            black_king_side: right(4),
+            black_queen_side: right(0),
+        })
+        .halfmove_clock(u16::from_be_bytes([bytes[11], bytes[12]]) as u32)
+        .fullmove_number(u16::from_be_bytes([bytes[13], bytes[14]]) as u32)
+        .chess960(flags & 2 != 0);
+    if flags & 4 != 0 {
+        // The square the pawn passed over, behind it from the side to move
+        let rank = if active_color == Color::White { 5 } else { 2 };
+        let square = ((flags >> 3) & 7) as usize + rank * 8;
+        builder = builder.en_passant(&as_standard_notation(&square));
+    }
+    builder.build().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_moves_in_16_bits() {
+        let e2e4 = Move::new("E2", "E4").unwrap();
+        assert_eq!(e2e4.encode(), 12 << 6 | 28);
+        assert_eq!(Move::decode(e2e4.encode()), Some(e2e4));
+        assert_eq!(e2e4.from(), "E2");
+        assert_eq!(e2e4.to(), "E4");
+        assert_eq!(Move::decode(1 << 12), None);
+        assert_eq!(Move::new("E9", "E4"), None);
+    }
+
+    #[test]
+    fn round_trips_positions() {
+        let mut game = Game::new();
+        let bytes = encode_position(&game);
+        assert_eq!(bytes.len(), 31);
+        assert_eq!(decode_position(&bytes).unwrap().to_fen(), game.to_fen());
+
+        game.make_move(String::from("E2"), String::from("E4"));
+        game.make_move(String::from("G8"), String::from("F6"));
+        game.make_move(String::from("E4"), String::from("E5"));
+        game.make_move(String::from("D7"), String::from("D5"));
+        let decoded = decode_position(&encode_position(&game)).unwrap();
+        assert_eq!(decoded.to_fen(), game.to_fen());
+        assert_eq!(decoded.get_en_passant(), Some(String::from("D6")));
+
+        let game = Game::from_fen("8/8/8/4k3/8/8/4P3/4K3 b - - 99 300").unwrap();
+        let bytes = encode_position(&game);
+        assert_eq!(bytes.len(), 17);
+        assert_eq!(decode_position(&bytes).unwrap().to_fen(), game.to_fen());
+
+        let game = Game::new_chess960(0).unwrap();
+        let decoded = decode_position(&encode_position(&game)).unwrap();
+        assert!(decoded.is_chess960());
+        assert_eq!(decoded.to_fen(), game.to_fen());
+    }
+
+    #[test]
+    fn refuses_malformed_positions() {
+        let bytes = encode_position(&Game::new());
+        assert!(decode_position(&bytes[..30]).is_none());
+        assert!(decode_position(&[]).is_none());
+        let mut bad_code = bytes.clone();
+        bad_code[HEADER_LENGTH] = 0xf0;
+        assert!(decode_position(&bad_code).is_none());
+    }
+}
@@ -0,0 +1,415 @@
+use crate::attacks::*;
+use crate::game::*;
+use crate::piece::*;
+use crate::tablebase::Tablebase;
+use std::time::{Duration, Instant};
+
+/// Score given to a position where the side to move has been checkmated.
+/// Mates closer to the root are scored higher than mates further away.
+pub const MATE_SCORE: i32 = 100_000;
+
+/// Score given to a position the tablebase says is won, less the half
+/// moves to the next capture or pawn move. Below the mate scores and above
+/// any evaluation.
+pub const TABLEBASE_WIN: i32 = MATE_SCORE / 2;
+
+/// How deep and for how long a search is allowed to run.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SearchLimits {
+    pub depth: u32,
+    pub time: Option<Duration>,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        SearchLimits {
+            depth: 4,
+            time: None,
+        }
+    }
+}
+
+/// The best move found by a search, with its score from the point of view
+/// of the side to move.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    pub from: String,
+    pub to: String,
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+}
+
+/// The material value of a piece in centipawns.
+pub fn piece_value(title: PieceType) -> i32 {
+    match title {
+        PieceType::King => 0,
+        PieceType::Queen => 900,
+        PieceType::Rook => 500,
+        PieceType::Bishop => 330,
+        PieceType::Knight => 320,
+        PieceType::Pawn => 100,
+    }
+}
+
+/// Statically evaluates the capture sequence started by moving the piece on
+/// `from` to `to`, assuming both sides keep recapturing with their least
+/// valuable attacker as long as it gains material. Attackers behind a
+/// rook, bishop or queen join in once it has captured. Returns the material
+/// won in centipawns for the side making the move, negative if it loses
+/// material, and 0 if there is no piece on `from`.
+///
+/// Pins are ignored, so a pinned piece may take part in the exchange.
+pub fn see(game: &Game, from: &str, to: &str) -> i32 {
+    let (from, to) = match (parse_square(from), parse_square(to)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return 0,
+    };
+    let mut board = *game.get_board();
+    let mut piece = match board[from].take() {
+        Some(piece) => piece,
+        None => return 0,
+    };
+
+    // The material each capture wins, assuming the piece that made it is
+    // captured in turn
+    let mut gains = vec![board[to].map_or(0, |captured| exchange_value(captured.title()))];
+    if piece.title() == PieceType::Pawn && from % 8 != to % 8 && board[to].is_none() {
+        // En passant, the captured pawn stands behind the target square
+        board[(to as i32 - 8 * piece.color().forward()) as usize] = None;
+        gains[0] = exchange_value(PieceType::Pawn);
+    }
+    board[to] = Some(piece);
+
+    let mut color = piece.color().opposite();
+    while let Some(attacker) = attackers_of(&board, to, color)
+        .into_iter()
+        .min_by_key(|&square| exchange_value(board[square].unwrap().title()))
+    {
+        gains.push(exchange_value(piece.title()) - gains[gains.len() - 1]);
+        piece = board[attacker].take().unwrap();
+        board[to] = Some(piece);
+        color = color.opposite();
+    }
+
+    // Each side may stop recapturing when it would lose material
+    while gains.len() > 1 {
+        let gain = gains.pop().unwrap();
+        let last = gains.len() - 1;
+        gains[last] = -(-gains[last]).max(gain);
+    }
+    gains[0]
+}
+
+// Kings are the most valuable piece to exchange, so they recapture last
+fn exchange_value(title: PieceType) -> i32 {
+    match title {
+        PieceType::King => 10 * MATE_SCORE,
+        _ => piece_value(title),
+    }
+}
+
+/// Evaluates a position in centipawns from the point of view of the
+/// side to move. Counts material, with small bonuses for advanced pawns
+/// and for minor pieces close to the centre.
+pub fn evaluate(game: &Game) -> i32 {
+    let mut score = 0;
+    for (square, tile) in game.get_board().iter().enumerate() {
+        if let Some(piece) = tile {
+            let file = (square % 8) as i32;
+            let rank = (square / 8) as i32;
+            // Ranks counted from the piece's own side of the board
+            let relative_rank = if piece.color() == Color::White {
+                rank
+            } else {
+                7 - rank
+            };
+
+            let mut value = piece_value(piece.title());
+            match piece.title() {
+                PieceType::Pawn => value += (relative_rank - 1) * 5,
+                PieceType::Knight | PieceType::Bishop => {
+                    let centre_distance = (2 * file - 7).abs() + (2 * rank - 7).abs();
+                    value += 20 - 2 * centre_distance;
+                }
+                _ => (),
+            }
+
+            if piece.color() == game.get_active_color() {
+                score += value;
+            } else {
+                score -= value;
+            }
+        }
+    }
+    score
+}
+
+/// Searches for the best move for the side to move with an iterative
+/// deepening alpha-beta search. Returns None if there are no legal moves.
+///
+/// If the time limit runs out the result of the last completed depth is
+/// returned, or the first legal move if not even depth one was completed.
+pub fn search(game: &Game, limits: SearchLimits) -> Option<SearchResult> {
+    let moves = ordered_moves(game);
+    let (first_from, first_to) = moves.first()?.clone();
+
+    let mut searcher = Searcher {
+        deadline: limits
+            .time
+            .and_then(|time| Instant::now().checked_add(time)),
+        nodes: 0,
+    };
+    let mut best = SearchResult {
+        from: first_from,
+        to: first_to,
+        score: 0,
+        depth: 0,
+        nodes: 0,
+    };
+
+    for depth in 1..=limits.depth.max(1) {
+        match searcher.search_root(game, &moves, depth) {
+            Some((from, to, score)) => {
+                best = SearchResult {
+                    from,
+                    to,
+                    score,
+                    depth,
+                    nodes: searcher.nodes,
+                };
+                // No need to look deeper once a forced mate is found
+                if score.abs() >= MATE_SCORE - depth as i32 {
+                    break;
+                }
+            }
+            None => break,
+        }
+    }
+    best.nodes = searcher.nodes;
+    Some(best)
+}
+
+/// Like `search`, but positions the tablebase can probe are played from
+/// its tables: won positions with the move closest to the next capture,
+/// pawn move or mate, lost ones with the move that holds out longest and
+/// drawn ones with a move that keeps the draw. Other positions, and ones
+/// where a pawn could promote, are searched as usual.
+pub fn search_with_tablebase(
+    game: &Game,
+    limits: SearchLimits,
+    tablebase: &Tablebase,
+) -> Option<SearchResult> {
+    let moves = match tablebase.probe_root(game) {
+        Some(moves) => moves,
+        None => return search(game, limits),
+    };
+    let nodes = moves.len() as u64;
+    // Wins with the smallest DTZ first, then draws, then losses with the largest
+    let (from, to, dtz) = moves
+        .into_iter()
+        .max_by_key(|&(_, _, dtz)| (dtz.signum(), -dtz))?;
+    let score = match dtz.signum() {
+        1 => TABLEBASE_WIN - dtz,
+        -1 => -TABLEBASE_WIN - dtz,
+        _ => 0,
+    };
+    Some(SearchResult {
+        from,
+        to,
+        score,
+        depth: 1,
+        nodes,
+    })
+}
+
+struct Searcher {
+    deadline: Option<Instant>,
+    nodes: u64,
+}
+
+impl Searcher {
+    // Searches all root moves to the given depth, returns None if time ran out
+    fn search_root(
+        &mut self,
+        game: &Game,
+        moves: &[(String, String)],
+        depth: u32,
+    ) -> Option<(String, String, i32)> {
+        let mut alpha = -MATE_SCORE - 1;
+        let mut best = None;
+        for (from, to) in moves {
+            let mut child = game.clone();
+            child.make_move(from.clone(), to.clone());
+            let score = -self.negamax(&child, depth - 1, -MATE_SCORE - 1, -alpha, 1)?;
+            if score > alpha {
+                alpha = score;
+                best = Some((from.clone(), to.clone(), score));
+            }
+        }
+        best
+    }
+
+    // Negamax alpha-beta search, returns None if time ran out
+    fn negamax(
+        &mut self,
+        game: &Game,
+        depth: u32,
+        mut alpha: i32,
+        beta: i32,
+        ply: i32,
+    ) -> Option<i32> {
+        self.nodes += 1;
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return None;
+            }
+        }
+
+        match game.get_game_state() {
+            // The side to move has been checkmated
+            GameState::WhiteCheckMate | GameState::BlackCheckMate => {
+                return Some(-MATE_SCORE + ply)
+            }
+            GameState::Tie => return Some(0),
+            _ => (),
+        }
+        if depth == 0 {
+            return Some(evaluate(game));
+        }
+
+        for (from, to) in ordered_moves(game) {
+            let mut child = game.clone();
+            child.make_move(from, to);
+            let score = -self.negamax(&child, depth - 1, -beta, -alpha, ply + 1)?;
+            if score >= beta {
+                return Some(beta);
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+        Some(alpha)
+    }
+}
+
+// Returns the legal moves with the captures that win the most material first
+// and the captures that lose material last
+fn ordered_moves(game: &Game) -> Vec<(String, String)> {
+    let board = game.get_board();
+    let mut moves = game.get_all_possible_moves();
+    moves.sort_by_cached_key(|(from, to)| {
+        if board[as_coordinate(to)].is_none() {
+            return (1, 0);
+        }
+        match see(game, from, to) {
+            gain if gain >= 0 => (0, -gain),
+            gain => (2, -gain),
+        }
+    });
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn starting_position_is_even() {
+        assert_eq!(evaluate(&Game::new()), 0);
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let mut game = Game::new();
+        game.make_move(String::from("F2"), String::from("F3"));
+        game.make_move(String::from("E7"), String::from("E5"));
+        game.make_move(String::from("G2"), String::from("G4"));
+
+        let result = search(
+            &game,
+            SearchLimits {
+                depth: 2,
+                time: None,
+            },
+        )
+        .unwrap();
+        assert_eq!((result.from.as_str(), result.to.as_str()), ("D8", "H4"));
+        assert_eq!(result.score, MATE_SCORE - 1);
+    }
+
+    #[test]
+    fn captures_hanging_queen() {
+        let game = Game::from_fen("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1").unwrap();
+
+        let result = search(
+            &game,
+            SearchLimits {
+                depth: 2,
+                time: None,
+            },
+        )
+        .unwrap();
+        assert_eq!((result.from.as_str(), result.to.as_str()), ("D1", "D5"));
+    }
+
+    #[test]
+    fn exchanges_on_a_square() {
+        // The pawn on e5 is defended by the pawn on d6
+        let game = Game::from_fen("4k3/8/3p4/4p3/8/8/8/K3R3 w - - 0 1").unwrap();
+        assert_eq!(see(&game, "E1", "E5"), 100 - 500);
+        // Undefended, the rook simply wins it
+        let game = Game::from_fen("4k3/8/8/4p3/8/8/8/K3R3 w - - 0 1").unwrap();
+        assert_eq!(see(&game, "E1", "E5"), 100);
+        assert_eq!(see(&game, "E3", "E5"), 0);
+    }
+
+    #[test]
+    fn exchanges_include_x_ray_attackers() {
+        // The queen behind the rook wins the knight after Rxe5 Rxe5 Qxe5
+        let game = Game::from_fen("4r1k1/8/8/4n3/8/8/4R3/K3Q3 w - - 0 1").unwrap();
+        assert_eq!(see(&game, "E2", "E5"), 320);
+        // Without it, black takes back the rook
+        let game = Game::from_fen("4r1k1/8/8/4n3/8/8/4R3/K7 w - - 0 1").unwrap();
+        assert_eq!(see(&game, "E2", "E5"), 320 - 500);
+    }
+
+    #[test]
+    fn no_result_without_moves() {
+        let mut game = Game::new();
+        game.make_move(String::from("F2"), String::from("F3"));
+        game.make_move(String::from("E7"), String::from("E5"));
+        game.make_move(String::from("G2"), String::from("G4"));
+        game.make_move(String::from("D8"), String::from("H4"));
+
+        assert_eq!(search(&game, SearchLimits::default()), None);
+    }
+
+    #[test]
+    fn plays_tablebase_endgames_perfectly() {
+        let directory = env::temp_dir().join(format!("engine-syzygy-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        crate::tablebase::testing::write_krvk_tables(&directory);
+        let tablebase = Tablebase::open(&directory).unwrap();
+
+        // Both sides play from the tables, so the game lasts exactly the DTZ
+        let mut game = Game::from_fen("8/8/8/4k3/8/8/8/R3K3 w - - 0 1").unwrap();
+        let dtz = tablebase.probe_dtz(&game).unwrap();
+        let limits = SearchLimits {
+            depth: 1,
+            time: None,
+        };
+        let result = search_with_tablebase(&game, limits, &tablebase).unwrap();
+        assert_eq!(result.score, TABLEBASE_WIN - dtz);
+        let mut plies = 0;
+        while !game.is_game_over() {
+            let result = search_with_tablebase(&game, limits, &tablebase).unwrap();
+            game.make_move(result.from, result.to).unwrap();
+            plies += 1;
+        }
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(game.get_game_state(), GameState::BlackCheckMate);
+        assert_eq!(plies, dtz);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::game::*;
+use crate::piece::*;
+use std::sync::Arc;
+
+/// Something that happened in a game, sent to its observers. Squares are
+/// written like `E4`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    /// A move was made. The SAN is written before the move, with `+` or `#`
+    /// if it checks, and the captured piece includes pawns taken en passant.
+    MoveMade {
+        from: String,
+        to: String,
+        san: String,
+        captured: Option<Piece>,
+    },
+    /// The king of a colour is in check after a move.
+    Check { color: Color },
+    /// The game ended with a move.
+    GameOver {
+        state: GameState,
+        reason: GameOverReason,
+    },
+    /// A pawn reached the last rank. This is only informational: promotion
+    /// isn't supported yet, so the pawn stays a pawn and the game goes on.
+    PromotionPending { square: String },
+    /// A move was taken back.
+    MoveUndone { from: String, to: String },
+    /// A colour offered a draw.
+    DrawOffered { by: Color },
+}
+
+/// Why a game ended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameOverReason {
+    Checkmate,
+    Stalemate,
+}
+
+type Observer = Arc<dyn Fn(&GameEvent) + Send + Sync>;
+
+// The observers of a game with their ids. Cloned games start without
+// observers, so copies made for searching or notation stay silent
+#[derive(Default)]
+pub(crate) struct Observers {
+    next_id: usize,
+    observers: Vec<(usize, Observer)>,
+}
+
+impl Observers {
+    pub(crate) fn add(&mut self, observer: Observer) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.observers.push((id, observer));
+        id
+    }
+
+    pub(crate) fn remove(&mut self, id: usize) -> bool {
+        let count = self.observers.len();
+        self.observers.retain(|(other, _)| *other != id);
+        self.observers.len() != count
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    pub(crate) fn emit(&self, event: GameEvent) {
+        for (_, observer) in &self.observers {
+            observer(&event);
+        }
+    }
+}
+
+impl Clone for Observers {
+    fn clone(&self) -> Self {
+        Observers::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Plays moves on a game and returns the events they sent
+    fn events_of(game: &mut Game, moves: &[(&str, &str)]) -> Vec<GameEvent> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sent = Arc::clone(&events);
+        let id = game.add_observer(move |event| sent.lock().unwrap().push(event.clone()));
+        for (from, to) in moves {
+            game.make_move(from.to_string(), to.to_string()).unwrap();
+        }
+        game.remove_observer(id);
+        let events = events.lock().unwrap().clone();
+        events
+    }
+
+    #[test]
+    fn sends_moves_checks_and_game_over() {
+        let mut game = Game::new();
+        let events = events_of(
+            &mut game,
+            &[("F2", "F3"), ("E7", "E5"), ("G2", "G4"), ("D8", "H4")],
+        );
+        assert_eq!(events.len(), 5);
+        assert_eq!(
+            events[0],
+            GameEvent::MoveMade {
+                from: String::from("F2"),
+                to: String::from("F3"),
+                san: String::from("f3"),
+                captured: None,
+            }
+        );
+        assert_eq!(
+            events[4],
+            GameEvent::GameOver {
+                state: GameState::WhiteCheckMate,
+                reason: GameOverReason::Checkmate,
+            }
+        );
+
+        let mut game = Game::from_fen("4k3/8/8/3p4/4P3/8/8/4K2R w K - 0 1").unwrap();
+        let events = events_of(&mut game, &[("E4", "D5"), ("E8", "F7"), ("H1", "H7")]);
+        assert_eq!(
+            events[0],
+            GameEvent::MoveMade {
+                from: String::from("E4"),
+                to: String::from("D5"),
+                san: String::from("exd5"),
+                captured: Some(Piece::new(Color::Black, PieceType::Pawn, true)),
+            }
+        );
+        assert_eq!(
+            events[3],
+            GameEvent::Check {
+                color: Color::Black
+            }
+        );
+    }
+
+    #[test]
+    fn sends_promotions_undos_and_draw_offers() {
+        let mut game = Game::from_fen("k7/4P3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sent = Arc::clone(&events);
+        game.add_observer(move |event| sent.lock().unwrap().push(event.clone()));
+
+        game.make_move(String::from("E7"), String::from("E8"));
+        game.undo_move();
+        game.offer_draw(Color::Black);
+        // Copies of the game don't notify
+        let mut copy = game.clone();
+        copy.make_move(String::from("E1"), String::from("D1"));
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(
+            events[1],
+            GameEvent::PromotionPending {
+                square: String::from("E8")
+            }
+        );
+        assert_eq!(
+            events[2],
+            GameEvent::MoveUndone {
+                from: String::from("E7"),
+                to: String::from("E8"),
+            }
+        );
+        assert_eq!(events[3], GameEvent::DrawOffered { by: Color::Black });
+    }
+}
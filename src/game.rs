@@ -1,78 +1,298 @@
+use crate::attacks::*;
+use crate::builder::*;
+use crate::encoding::*;
+use crate::events::*;
+use crate::notation::*;
 use crate::piece::*;
+use crate::validate::*;
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::Arc;
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GameState {
     InProgress,
     BlackCheck,
     WhiteCheck,
     BlackCheckMate,
     WhiteCheckMate,
-    Tie
-}
-fn color_check(color: Color) -> GameState {
-    match color {
-        Color::Black => GameState::BlackCheck,
-        Color::White => GameState::WhiteCheck,
-    }
+    Tie,
 }
 /* IMPORTANT:
  * - Document well!
  * - Write well structured and clean code!
  */
 
+/// Which rooks each side may still castle with, given as the file of the
+/// rook (0 for the a-file, 7 for the h-file).
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CastlingRights {
+    pub white_king_side: Option<usize>,
+    pub white_queen_side: Option<usize>,
+    pub black_king_side: Option<usize>,
+    pub black_queen_side: Option<usize>,
+}
+
+impl CastlingRights {
+    /// The castling rights of the standard starting position.
+    pub fn standard() -> CastlingRights {
+        CastlingRights {
+            white_king_side: Some(7),
+            white_queen_side: Some(0),
+            black_king_side: Some(7),
+            black_queen_side: Some(0),
+        }
+    }
+
+    /// The rook files a colour may castle with, as (king side, queen side).
+    pub fn get(&self, color: Color) -> (Option<usize>, Option<usize>) {
+        match color {
+            Color::White => (self.white_king_side, self.white_queen_side),
+            Color::Black => (self.black_king_side, self.black_queen_side),
+        }
+    }
+
+    // Removes the rights of a colour whose king has moved
+    fn remove_color(&mut self, color: Color) {
+        match color {
+            Color::White => {
+                self.white_king_side = None;
+                self.white_queen_side = None;
+            }
+            Color::Black => {
+                self.black_king_side = None;
+                self.black_queen_side = None;
+            }
+        }
+    }
+
+    // Removes the right to castle with a rook that moved from or was captured on the square
+    fn remove_rook(&mut self, square: usize) {
+        let file = Some(square % 8);
+        if square / 8 == 0 {
+            if self.white_king_side == file {
+                self.white_king_side = None;
+            }
+            if self.white_queen_side == file {
+                self.white_queen_side = None;
+            }
+        } else if square / 8 == 7 {
+            if self.black_king_side == file {
+                self.black_king_side = None;
+            }
+            if self.black_queen_side == file {
+                self.black_queen_side = None;
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Game {
     /* save board, active colour, ... */
     state: GameState,
     active_color: Color,
     board: [Option<Piece>; 64],
+    castling: CastlingRights,
+    // The square a pawn passed over with a double move in the last move
+    en_passant: Option<usize>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    // Castling is given as the king capturing its own rook
+    chess960: bool,
     possible_moves: HashMap<String, Vec<String>>,
+    history: Vec<PlayedMove>,
+    observers: Observers,
+}
+
+// A move that has been made and the position before it, used to undo moves
+#[derive(Clone)]
+struct PlayedMove {
+    from: String,
+    to: String,
+    board: [Option<Piece>; 64],
+    active_color: Color,
+    state: GameState,
+    castling: CastlingRights,
+    en_passant: Option<usize>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game::new()
+    }
 }
 
 impl Game {
     /// Initialises a new board with pieces.
     pub fn new() -> Game {
-        let mut game = Game {
-            /* initialise board, set active colour to white, ... */
-            state: GameState::InProgress,
-            active_color: Color::White,
-            board: generate_board(),
-            possible_moves: HashMap::new(),
+        /* initialise board, set active colour to white, ... */
+        Game::from_parts(
+            generate_board(),
+            Color::White,
+            CastlingRights::standard(),
+            None,
+            0,
+            1,
+            false,
+        )
+    }
+
+    /// Initialises a new Chess960 game from the starting position with the
+    /// given number, from 0 to 959. Number 518 is the classic position.
+    /// Returns None if the number is out of range.
+    pub fn new_chess960(index: usize) -> Option<Game> {
+        let board = generate_chess960_board(index)?;
+        let rooks: Vec<usize> = (0..8)
+            .filter(|&file| board[file].is_some_and(|piece| piece.title() == PieceType::Rook))
+            .collect();
+        let castling = CastlingRights {
+            white_king_side: Some(rooks[1]),
+            white_queen_side: Some(rooks[0]),
+            black_king_side: Some(rooks[1]),
+            black_queen_side: Some(rooks[0]),
         };
-        game.possible_moves = game.calculate_all_possible_moves();
-        game
+        Some(Game::from_parts(board, Color::White, castling, None, 0, 1, true))
+    }
+
+    /// Initialises a new game with a specified board, white to move and
+    /// no castling rights. Returns the problems found if the position
+    /// can't be played. Use `GameBuilder` to set up the rest of the position.
+    pub fn new_with_board(board: [Option<Piece>; 64]) -> Result<Game, Vec<PositionError>> {
+        GameBuilder::new(board).build()
+    }
+
+    /// Initialises a new game from a FEN string. Returns None if the string
+    /// can't be parsed or the position can't be played.
+    pub fn from_fen(fen: &str) -> Option<Game> {
+        GameBuilder::from_fen(fen)?.build().ok()
     }
 
-    pub fn new_with_board(board: [Option<Piece>; 64]) -> Game {
+    // Initialises a game from a validated position and calculates its state
+    pub(crate) fn from_parts(
+        board: [Option<Piece>; 64],
+        active_color: Color,
+        castling: CastlingRights,
+        en_passant: Option<usize>,
+        halfmove_clock: u32,
+        fullmove_number: u32,
+        chess960: bool,
+    ) -> Game {
         let mut game = Game {
-            /* initialise board, set active colour to white, ... */
             state: GameState::InProgress,
-            active_color: Color::White,
+            active_color,
             board,
+            castling,
+            en_passant,
+            halfmove_clock,
+            fullmove_number,
+            chess960,
             possible_moves: HashMap::new(),
+            history: Vec::new(),
+            observers: Observers::default(),
         };
         game.possible_moves = game.calculate_all_possible_moves();
+        game.state = game.calculate_game_state();
         game
     }
 
-    /// If the current game state is InProgress and the move is legal,
+    /// If the game isn't over and the move is legal,
     /// move a piece and return the resulting state of the game.
+    ///
+    /// Castling is made by moving the king two squares towards the rook,
+    /// or in Chess960 games by moving the king onto the rook.
     pub fn make_move(&mut self, from: String, to: String) -> Option<GameState> {
+        // Finished games can't be continued
+        if self.is_game_over() {
+            return None;
+        }
+
         // If move is legal
         if let Some(moves) = self.possible_moves.get(&from) {
             if moves.contains(&to) {
-                let mut piece = self.board[as_coordinate(&from)].unwrap();
+                // The notation and capture are only needed for observers
+                let made = if self.observers.is_empty() {
+                    None
+                } else {
+                    to_san(self, &from, &to).map(|san| GameEvent::MoveMade {
+                        from: from.clone(),
+                        to: to.clone(),
+                        san,
+                        captured: self.captured_piece(&from, &to),
+                    })
+                };
+
+                // Save the position so the move can be undone
+                self.history.push(PlayedMove {
+                    from: from.clone(),
+                    to: to.clone(),
+                    board: self.board,
+                    active_color: self.active_color,
+                    state: self.state,
+                    castling: self.castling,
+                    en_passant: self.en_passant,
+                    halfmove_clock: self.halfmove_clock,
+                    fullmove_number: self.fullmove_number,
+                });
+
+                let from = as_coordinate(&from);
+                let to = as_coordinate(&to);
+                let mut piece = self.board[from].unwrap();
+                let mut capture = self.board[to].is_some();
 
                 // Piece has Moved
                 piece.set_has_moved();
 
-                // If capturing move
-                if self.board[as_coordinate(&to)].is_some() {
-                    self.board[as_coordinate(&to)] = Some(piece);
-                    self.board[as_coordinate(&from)].take();
+                if self.is_castling(from, to) {
+                    // Castling, the king and rook end up on the g and f or c and d files
+                    let (king_side, queen_side) = self.castling.get(piece.color());
+                    let is_king_side = to % 8 > from % 8;
+                    let rook_file = if is_king_side { king_side } else { queen_side };
+                    let back_rank = (from / 8) * 8;
+                    let rook_from = back_rank + rook_file.unwrap();
+                    let (king_to, rook_to) = if is_king_side {
+                        (back_rank + 6, back_rank + 5)
+                    } else {
+                        (back_rank + 2, back_rank + 3)
+                    };
+                    let mut rook = self.board[rook_from].take().unwrap();
+                    rook.set_has_moved();
+                    self.board[from].take();
+                    self.board[rook_to] = Some(rook);
+                    self.board[king_to] = Some(piece);
+                    capture = false;
                 } else {
-                    self.board[as_coordinate(&to)] = Some(piece);
-                    self.board[as_coordinate(&from)].take();
+                    if piece.title() == PieceType::Pawn && Some(to) == self.en_passant {
+                        // En passant, the captured pawn is behind the target square
+                        let captured = (to as i32 - 8 * piece.color().forward()) as usize;
+                        self.board[captured].take();
+                        capture = true;
+                    }
+                    self.board[to] = Some(piece);
+                    self.board[from].take();
+                }
+
+                // Update castling rights, en passant and the move counters
+                if piece.title() == PieceType::King {
+                    self.castling.remove_color(piece.color());
+                }
+                self.castling.remove_rook(from);
+                self.castling.remove_rook(to);
+                self.en_passant = if piece.title() == PieceType::Pawn && from.abs_diff(to) == 16 {
+                    Some((from + to) / 2)
+                } else {
+                    None
+                };
+                if piece.title() == PieceType::Pawn || capture {
+                    self.halfmove_clock = 0;
+                } else {
+                    self.halfmove_clock += 1;
+                }
+                if self.active_color == Color::Black {
+                    self.fullmove_number += 1;
                 }
 
                 // Move has been made, now switch colors
@@ -83,44 +303,62 @@ impl Game {
 
                 // Calculate all moves for new boardstate
                 self.possible_moves = self.calculate_all_possible_moves();
-
-                // If board is in check
-                if let Some(state) = board_in_check(self.board.clone()) {
-                    // If checkmate
-                    // check possible moves for current color
-                    if self.possible_moves.is_empty() {
-                        // Checkmate
-                        match state {
-                            GameState::WhiteCheck => {
-                                return Some(GameState::WhiteCheckMate);
-                            }
-                            GameState::BlackCheck => {
-                                return Some(GameState::BlackCheckMate);
-                            }
-                            _ => (),
-                        }
-                    } else {
-                        // Not checkmate
-                        self.state = state;
-                        return Some(state);
-                    }
-                }
-                if self.possible_moves.is_empty() {
-                // Tie
-                self.state = GameState::Tie;
-                return Some(GameState::Tie);
+                self.state = self.calculate_game_state();
+                if let Some(made) = made {
+                    self.notify_move(made, piece, to);
                 }
+                return Some(self.state);
             }
         }
         // Impossible move
         None
     }
 
-    /// Set the piece type that a peasant becames following a promotion.
-    pub fn set_promotion(&mut self, _piece: String) -> () {
-        ()
+    /// Take back the last move and return the state of the game before it.
+    /// Returns None if no moves have been made.
+    pub fn undo_move(&mut self) -> Option<GameState> {
+        let played = self.history.pop()?;
+        self.board = played.board;
+        self.active_color = played.active_color;
+        self.state = played.state;
+        self.castling = played.castling;
+        self.en_passant = played.en_passant;
+        self.halfmove_clock = played.halfmove_clock;
+        self.fullmove_number = played.fullmove_number;
+        self.possible_moves = self.calculate_all_possible_moves();
+        self.observers.emit(GameEvent::MoveUndone {
+            from: played.from,
+            to: played.to,
+        });
+        Some(self.state)
+    }
+
+    /// Adds a function that is called with every event of the game, like
+    /// moves, checks and the end of the game. Returns an id to remove it
+    /// with. Clones of the game don't keep the observers.
+    pub fn add_observer<F>(&mut self, observer: F) -> usize
+    where
+        F: Fn(&GameEvent) + Send + Sync + 'static,
+    {
+        self.observers.add(Arc::new(observer))
     }
 
+    /// Removes an observer added with `add_observer`. Returns false if there
+    /// is no observer with the id.
+    pub fn remove_observer(&mut self, id: usize) -> bool {
+        self.observers.remove(id)
+    }
+
+    /// Tells the observers that a colour offers a draw. The game itself
+    /// doesn't change.
+    pub fn offer_draw(&mut self, by: Color) {
+        self.observers.emit(GameEvent::DrawOffered { by });
+    }
+
+    /// Set the piece type that a peasant becames following a promotion.
+    /// Promotion isn't supported yet, so this does nothing.
+    pub fn set_promotion(&mut self, _piece: String) {}
+
     /// Get the current game state.
     pub fn get_game_state(&self) -> GameState {
         self.state
@@ -129,161 +367,397 @@ impl Game {
     /// If a piece is standing on the given tile, return all possible
     /// new positions of that piece. Don't forget to the rules for check.
     ///
-    /// (optional) Don't forget to include en passent and castling.
-
+    /// En passant and castling are included, castling as the king
+    /// moving two squares.
     pub fn get_possible_moves(&self, position: String) -> Option<Vec<String>> {
-        match self.possible_moves.get(&position).is_some() {
-            true => Some(self.possible_moves.get(&position).unwrap().clone()),
-            false => None,
+        self.possible_moves.get(&position).cloned()
+    }
+
+    /// Get all legal moves for the active colour as (from, to) pairs,
+    /// ordered by the square the piece is standing on.
+    pub fn get_all_possible_moves(&self) -> Vec<(String, String)> {
+        let mut moves: Vec<(String, String)> = Vec::new();
+        for square_index in 0..64 {
+            if let Some(targets) = self.possible_moves.get(&as_standard_notation(&square_index)) {
+                for to in targets {
+                    moves.push((as_standard_notation(&square_index), to.clone()));
+                }
+            }
         }
+        moves
     }
 
     pub fn get_board(&self) -> &[Option<Piece>; 64] {
         &self.board
     }
 
-    // Calculates all the possible moves for the given game and returns them as a hashmap
-    fn calculate_all_possible_moves(&mut self) -> HashMap<String, Vec<String>> {
-        let board = self.board;
+    /// Get the colour whose turn it is.
+    pub fn get_active_color(&self) -> Color {
+        self.active_color
+    }
 
-        let mut map: HashMap<String, Vec<String>> = HashMap::new();
-        for square_index in 0..64 {
-            if board[square_index].is_none() {
-                continue;
-            } else if board[square_index].unwrap().color() != self.active_color {
-                continue;
-            }
-            // Calculate moves for the given piece
-            let moves = calculate_possible_moves(&board, as_standard_notation(&square_index));
-            if moves.is_some() {
-                map.insert(as_standard_notation(&square_index), moves.unwrap());
-            }
-        }
-        map
+    /// Get the rooks each side may still castle with.
+    pub fn get_castling_rights(&self) -> CastlingRights {
+        self.castling
     }
-}
 
-// Returns a new board with the given move
-fn board_from_move(mut board: [Option<Piece>; 64], from: usize, to: usize) -> [Option<Piece>; 64] {
-    let piece = board[from];
-    // If capturing move
-    if board[to].is_some() {
-        board[to] = piece.clone();
-        board[from].take();
-    } else {
-        board[to] = piece.clone();
-        board[from].take();
+    /// Get the square a pawn passed over with a double move in the last
+    /// move, if any.
+    pub fn get_en_passant(&self) -> Option<String> {
+        self.en_passant.map(|square| as_standard_notation(&square))
     }
 
-    board
-}
+    /// If the game is played with Chess960 castling rules, where castling
+    /// is given as the king moving onto its own rook.
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
 
+    /// Get the number of half moves since the last capture or pawn move.
+    pub fn get_halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
 
-// calculates if the given board is in check and returns the type of check
-fn board_in_check(board: [Option<Piece>; 64]) -> Option<GameState> {
-    let kings_positions = find_kings(&board);
+    /// Get the number of the current full move, starting at 1 and
+    /// increased after each move by black.
+    pub fn get_fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    /// Get the squares of the pieces of a colour that attack the given
+    /// square. Pinned pieces count as attacking. Returns None if the
+    /// square doesn't exist.
+    pub fn attackers_of(&self, square: String, color: Color) -> Option<Vec<String>> {
+        let square = parse_square(&square)?;
+        Some(
+            attackers_of(&self.board, square, color)
+                .iter()
+                .map(as_standard_notation)
+                .collect(),
+        )
+    }
+
+    /// Get the number of pieces of a colour attacking each square, indexed
+    /// like the board.
+    pub fn attack_map(&self, color: Color) -> [u8; 64] {
+        attack_map(&self.board, color)
+    }
+
+    /// Get the pieces of a colour that are pinned to their king, as
+    /// (pinned, pinner) pairs.
+    pub fn pins(&self, color: Color) -> Vec<(String, String)> {
+        pins(&self.board, color)
+            .iter()
+            .map(|pin| {
+                (
+                    as_standard_notation(&pin.pinned),
+                    as_standard_notation(&pin.pinner),
+                )
+            })
+            .collect()
+    }
 
-    // Do a recursive search of the nearby tiles to see if anyone checks the king
-    let directions = PieceType::Queen.directions();
+    /// Get the pieces of a colour that would give a discovered check by
+    /// moving off the line to the enemy king.
+    pub fn discovered_check_candidates(&self, color: Color) -> Vec<String> {
+        discovered_check_candidates(&self.board, color)
+            .iter()
+            .map(as_standard_notation)
+            .collect()
+    }
+
+    /// Get the moves made so far as (from, to) pairs, oldest first.
+    pub fn get_move_history(&self) -> Vec<(String, String)> {
+        self.history
+            .iter()
+            .map(|played| (played.from.clone(), played.to.clone()))
+            .collect()
+    }
 
-    for position in kings_positions {
-        let king = board[position].unwrap();
-        let check = match king.color() {
-            Color::Black => GameState::BlackCheck,
-            Color::White => GameState::WhiteCheck,
+    /// Get the current position as a FEN string.
+    pub fn to_fen(&self) -> String {
+        position_to_fen(
+            &self.board,
+            self.active_color,
+            self.castling,
+            self.en_passant,
+            self.halfmove_clock,
+            self.fullmove_number,
+            false,
+        )
+    }
+
+    /// Get the current position as a Shredder-FEN string, which names the
+    /// castling rooks by their files instead of with KQkq.
+    pub fn to_shredder_fen(&self) -> String {
+        position_to_fen(
+            &self.board,
+            self.active_color,
+            self.castling,
+            self.en_passant,
+            self.halfmove_clock,
+            self.fullmove_number,
+            true,
+        )
+    }
+
+    /// Encodes the current position in at most 31 bytes, see
+    /// `encoding::encode_position` for the format. The move history isn't
+    /// encoded.
+    pub fn encode(&self) -> Vec<u8> {
+        encode_position(self)
+    }
+
+    /// Starts a game from a position encoded with `Game::encode`. Returns
+    /// None if the bytes are malformed or the position can't be played.
+    pub fn decode(bytes: &[u8]) -> Option<Game> {
+        decode_position(bytes)
+    }
+
+    /// Get the position the game started from as a FEN string.
+    pub fn get_starting_fen(&self) -> String {
+        match self.history.first() {
+            Some(played) => position_to_fen(
+                &played.board,
+                played.active_color,
+                played.castling,
+                played.en_passant,
+                played.halfmove_clock,
+                played.fullmove_number,
+                false,
+            ),
+            None => self.to_fen(),
+        }
+    }
+
+    // If moving the piece on `from` to `to` castles
+    pub(crate) fn is_castling(&self, from: usize, to: usize) -> bool {
+        let piece = match self.board[from] {
+            Some(piece) if piece.title() == PieceType::King => piece,
+            _ => return false,
         };
-        for (file_move, rank_move, _) in directions.clone() {
-            for i in 1..8 {
-                // Calculate move coordinate
-                let temp_move = (i * (file_move + rank_move * 8)) + (position as i32);
+        if self.chess960 {
+            self.board[to].is_some_and(|target| {
+                target.title() == PieceType::Rook && target.color() == piece.color()
+            })
+        } else {
+            (from % 8).abs_diff(to % 8) == 2
+        }
+    }
 
-                // If the move is in bounds of the board
-                if move_in_bounds(temp_move, position, file_move) {
-                    // Check if occupied
-                    if let Some(piece) = board[temp_move as usize] {
-                        // Check occupying piece
-                        if piece.color() == king.color() {
-                            break;
-                        } else {
-                            if file_move == 0 || rank_move == 0 {
-                                match piece.title() {
-                                    PieceType::Queen => return Some(check),
-                                    PieceType::Rook => return Some(check),
-                                    _ => break,
-                                }
-                            } else if rank_move * i == king.color().forward()
-                                && piece.title() == PieceType::Pawn
-                            {
-                                return Some(check);
-                            } else {
-                                match piece.title() {
-                                    PieceType::Queen => return Some(check),
-                                    PieceType::Bishop => return Some(check),
-                                    _ => break,
-                                }
-                            }
-                        }
-                    }
+    /// If the game has ended in a checkmate or a tie.
+    pub fn is_game_over(&self) -> bool {
+        matches!(
+            self.state,
+            GameState::WhiteCheckMate | GameState::BlackCheckMate | GameState::Tie
+        )
+    }
+
+    // The piece a legal move captures, the pawn behind the target square en
+    // passant and nothing when castling onto the own rook
+    fn captured_piece(&self, from: &str, to: &str) -> Option<Piece> {
+        let (from, to) = (as_coordinate(from), as_coordinate(to));
+        let piece = self.board[from]?;
+        if piece.title() == PieceType::Pawn && Some(to) == self.en_passant {
+            return self.board[(to as i32 - 8 * piece.color().forward()) as usize];
+        }
+        self.board[to].filter(|captured| captured.color() != piece.color())
+    }
+
+    // Sends the events of a move that has been made to the observers
+    fn notify_move(&self, made: GameEvent, piece: Piece, to: usize) {
+        self.observers.emit(made);
+        let last_rank = if piece.color() == Color::White { 7 } else { 0 };
+        if piece.title() == PieceType::Pawn && to / 8 == last_rank {
+            self.observers.emit(GameEvent::PromotionPending {
+                square: as_standard_notation(&to),
+            });
+        }
+        match self.state {
+            GameState::WhiteCheck => self.observers.emit(GameEvent::Check {
+                color: Color::White,
+            }),
+            GameState::BlackCheck => self.observers.emit(GameEvent::Check {
+                color: Color::Black,
+            }),
+            GameState::WhiteCheckMate | GameState::BlackCheckMate => {
+                self.observers.emit(GameEvent::GameOver {
+                    state: self.state,
+                    reason: GameOverReason::Checkmate,
+                })
+            }
+            GameState::Tie => self.observers.emit(GameEvent::GameOver {
+                state: self.state,
+                reason: GameOverReason::Stalemate,
+            }),
+            GameState::InProgress => (),
+        }
+    }
+
+    // Calculates the state of the game from the board and the possible moves
+    fn calculate_game_state(&self) -> GameState {
+        // If board is in check
+        match board_in_check(self.board) {
+            // If checkmate
+            // check possible moves for current color
+            Some(GameState::WhiteCheck) if self.possible_moves.is_empty() => {
+                GameState::WhiteCheckMate
+            }
+            Some(GameState::BlackCheck) if self.possible_moves.is_empty() => {
+                GameState::BlackCheckMate
+            }
+            Some(state) => state,
+            // Tie
+            None if self.possible_moves.is_empty() => GameState::Tie,
+            None => GameState::InProgress,
+        }
+    }
+
+    // Calculates all the possible moves for the given game and returns them as a hashmap
+    fn calculate_all_possible_moves(&mut self) -> HashMap<String, Vec<String>> {
+        let board = self.board;
+        // Only the king, pinned pieces and moves out of check can expose the king
+        let in_check = king_in_check(&board, self.active_color);
+        let pinned: Vec<usize> = pins(&board, self.active_color)
+            .iter()
+            .map(|pin| pin.pinned)
+            .collect();
+
+        let mut map: HashMap<String, Vec<String>> = HashMap::new();
+        for square_index in 0..64 {
+            let safe = match board[square_index] {
+                Some(piece) if piece.color() == self.active_color => {
+                    !in_check && piece.title() != PieceType::King && !pinned.contains(&square_index)
                 }
+                _ => continue,
+            };
+            // Calculate moves for the given piece
+            if let Some(moves) =
+                calculate_possible_moves(&board, as_standard_notation(&square_index), safe)
+            {
+                map.insert(as_standard_notation(&square_index), moves);
             }
         }
 
-        // Check for knight pieces
-        let knight_directions = PieceType::Knight.directions();
-        for (file_move, rank_move, _) in knight_directions {
-            // Calculate move coordinate
-            let temp_move = (file_move + rank_move * 8) + (position as i32);
+        // En passant captures
+        if let Some(target) = self.en_passant {
+            let captured = (target as i32 - 8 * self.active_color.forward()) as usize;
+            for file_move in &[-1, 1] {
+                if !move_in_bounds(captured as i32 + file_move, captured, *file_move) {
+                    continue;
+                }
+                let from = (captured as i32 + file_move) as usize;
+                let is_own_pawn = board[from].is_some_and(|piece| {
+                    piece.title() == PieceType::Pawn && piece.color() == self.active_color
+                });
+                if !is_own_pawn {
+                    continue;
+                }
+                let mut after = board_from_move(board, from, target);
+                after[captured] = None;
+                if !king_in_check(&after, self.active_color) {
+                    map.entry(as_standard_notation(&from))
+                        .or_default()
+                        .push(as_standard_notation(&target));
+                }
+            }
+        }
 
-            // If the move is in bounds of the board
-            if move_in_bounds(temp_move, position, file_move) {
-                // Check if occupied
-                if let Some(piece) = board[temp_move as usize] {
-                    // Check occupying piece
-                    if piece.color() == king.color() {
-                        continue;
-                    } else if piece.title() == PieceType::Knight {
-                        return Some(check);
-                    }
+        // Castling
+        let (king_side, queen_side) = self.castling.get(self.active_color);
+        for (rook_file, is_king_side) in &[(king_side, true), (queen_side, false)] {
+            if let Some(rook_file) = rook_file {
+                if let Some((king_from, king_to)) = self.castling_move(*rook_file, *is_king_side) {
+                    let target = if self.chess960 {
+                        king_from - king_from % 8 + rook_file
+                    } else {
+                        king_to
+                    };
+                    map.entry(as_standard_notation(&king_from))
+                        .or_default()
+                        .push(as_standard_notation(&target));
                 }
             }
         }
+        map
     }
 
-    None
-}
+    // Returns the king's move if the active colour can castle with the rook on the given file
+    fn castling_move(&self, rook_file: usize, is_king_side: bool) -> Option<(usize, usize)> {
+        let color = self.active_color;
+        let back_rank = if color == Color::White { 0 } else { 7 };
+        let king_from = find_king(&self.board, color)?;
+        let rook_from = back_rank * 8 + rook_file;
+        let king_to = back_rank * 8 + if is_king_side { 6 } else { 2 };
+        let rook_to = back_rank * 8 + if is_king_side { 5 } else { 3 };
+
+        let is_own_rook = self.board[rook_from]
+            .is_some_and(|piece| piece.title() == PieceType::Rook && piece.color() == color);
+        if king_from / 8 != back_rank || !is_own_rook {
+            return None;
+        }
 
-// finds and returns the position of the kings
-fn find_kings(board: &[Option<Piece>; 64]) -> Vec<usize> {
-    let mut white_king_position = 0;
-    for square in 0..64 {
-        if let Some(piece) = board[square] {
-            if piece.title() == PieceType::King && piece.color() == Color::White {
-                white_king_position = square;
+        // All squares the king and rook cross must be empty
+        let squares = [king_from, rook_from, king_to, rook_to];
+        let lowest = *squares.iter().min().unwrap();
+        let highest = *squares.iter().max().unwrap();
+        for square in lowest..=highest {
+            if square != king_from && square != rook_from && self.board[square].is_some() {
+                return None;
             }
         }
-    }
 
-    let mut black_king_position = 0;
-    for square in 0..64 {
-        if let Some(piece) = board[square] {
-            if piece.title() == PieceType::King && piece.color() == Color::Black {
-                black_king_position = square;
+        // The king can't castle out of, through or into check
+        let mut empty = self.board;
+        empty[king_from].take();
+        empty[rook_from].take();
+        for square in king_from.min(king_to)..=king_from.max(king_to) {
+            if is_square_attacked(&empty, square, color.opposite()) {
+                return None;
             }
         }
+        Some((king_from, king_to))
     }
+}
 
-    vec![white_king_position, black_king_position]
+// Returns a new board with the given move
+fn board_from_move(mut board: [Option<Piece>; 64], from: usize, to: usize) -> [Option<Piece>; 64] {
+    board[to] = board[from].take();
+    board
 }
 
-// Calculates the possible moves for a piece
+
+// calculates if the given board is in check and returns the type of check
+fn board_in_check(board: [Option<Piece>; 64]) -> Option<GameState> {
+    if king_in_check(&board, Color::White) {
+        Some(GameState::WhiteCheck)
+    } else if king_in_check(&board, Color::Black) {
+        Some(GameState::BlackCheck)
+    } else {
+        None
+    }
+}
+
+// Checks if the king of the given colour is attacked, false if there is no such king
+pub(crate) fn king_in_check(board: &[Option<Piece>; 64], color: Color) -> bool {
+    match find_king(board, color) {
+        Some(position) => is_square_attacked(board, position, color.opposite()),
+        None => false,
+    }
+}
+
+// Calculates the possible moves for a piece. Moves of a `safe` piece can't
+// expose its king, so they aren't tried out
 fn calculate_possible_moves(
     board: &[Option<Piece>; 64],
     position: String,
+    safe: bool,
 ) -> Option<Vec<String>> {
-    let board: [Option<Piece>; 64] = board.clone();
+    let board: [Option<Piece>; 64] = *board;
     let position = as_coordinate(&position);
     let piece = board[position].unwrap();
     let directions = piece.title().directions();
+    let checks_self = |to: i32| !safe && would_check_self(&board, position, to as usize);
 
     let mut moves: Vec<String> = Vec::new();
 
@@ -293,24 +767,24 @@ fn calculate_possible_moves(
             let temp_move =
                 (file_move + rank_move * 8 * piece.color().forward()) + (position as i32);
 
-            if move_in_bounds(temp_move, position, file_move) {
-                if board_in_check(board_from_move(board.clone(), position, temp_move as usize))
-                    != Some(color_check(piece.color()))
-                {
-                    if board[temp_move as usize].is_some() {
-                        // Diagonal move
-                        if board[temp_move as usize].unwrap().color() != piece.color()
-                            && file_move != 0
-                        {
-                            moves.push(as_standard_notation(&(temp_move as usize)));
-                        }
-                    // Straight move
-                    } else if file_move == 0 && rank_move == 1 {
-                        moves.push(as_standard_notation(&(temp_move as usize)));
-                    } else if file_move == 0 && rank_move == 2 && !piece.has_moved() {
-                        moves.push(as_standard_notation(&(temp_move as usize)));
-                    }
+            if !move_in_bounds(temp_move, position, file_move) {
+                continue;
+            }
+            let reachable = match board[temp_move as usize] {
+                // Diagonal move
+                Some(target) => file_move != 0 && target.color() != piece.color(),
+                // Straight move, double moves can't jump over pieces
+                None => {
+                    file_move == 0
+                        && (rank_move == 1
+                            || (rank_move == 2
+                                && !piece.has_moved()
+                                && board[(position as i32 + 8 * piece.color().forward()) as usize]
+                                    .is_none()))
                 }
+            };
+            if reachable && !checks_self(temp_move) {
+                moves.push(as_standard_notation(&(temp_move as usize)));
             }
         }
     } else if directions[0].2 {
@@ -323,24 +797,17 @@ fn calculate_possible_moves(
 
                 // If the move is in bounds of the board
                 if move_in_bounds(temp_move, position, file_move * i) {
-                    let would_check_self = board_in_check(board_from_move(
-                        board.clone(),
-                        position,
-                        temp_move as usize,
-                    )) == Some(color_check(piece.color()));
-
                     // Check if occupied
-                    if board[temp_move as usize].is_some() {
+                    if let Some(target) = board[temp_move as usize] {
                         // Check occupying piece
-                        if board[temp_move as usize].unwrap().color() == piece.color() {
-                            break;
-                        } else if !would_check_self {
+                        if target.color() != piece.color()
+                            && !checks_self(temp_move)
+                        {
                             moves.push(as_standard_notation(&(temp_move as usize)));
-                            break;
                         }
-                    } else if !would_check_self {
+                        break;
+                    } else if !checks_self(temp_move) {
                         moves.push(as_standard_notation(&(temp_move as usize)));
-                        continue;
                     }
                 } else {
                     break;
@@ -354,35 +821,36 @@ fn calculate_possible_moves(
             // Calculate move coordinate
             let temp_move = (file_move + rank_move * 8) + (position as i32);
             if move_in_bounds(temp_move, position, file_move) {
-                if board_in_check(board_from_move(board.clone(), position, temp_move as usize))
-                    != Some(color_check(piece.color()))
-                {
-                    if board[temp_move as usize].is_some() {
-                        // Check occupying piece
-                        if board[temp_move as usize].unwrap().color() != piece.color() {
-                            moves.push(as_standard_notation(&(temp_move as usize)));
-                        }
-                    } else {
-                        moves.push(as_standard_notation(&(temp_move as usize)));
-                    }
+                // Check occupying piece
+                let own_piece = board[temp_move as usize]
+                    .is_some_and(|target| target.color() == piece.color());
+                if !own_piece && !checks_self(temp_move) {
+                    moves.push(as_standard_notation(&(temp_move as usize)));
                 }
             }
         }
     }
 
-    if moves.len() > 0 {
+    if !moves.is_empty() {
         Some(moves)
     } else {
         None
     }
 }
+// Checks if moving the piece on `from` would leave its own king in check.
+// The target square must not hold a piece of the same colour.
+fn would_check_self(board: &[Option<Piece>; 64], from: usize, to: usize) -> bool {
+    let color = board[from].unwrap().color();
+    king_in_check(&board_from_move(*board, from, to), color)
+}
+
 // Checks if the given move is in bounds of the board
 fn move_in_bounds(to: i32, from: usize, file_move: i32) -> bool {
-    0 <= file_move + (from % 8) as i32 && file_move + (from % 8) as i32 <= 7 && to <= 63 && to >= 0
+    (0..=7).contains(&(file_move + (from % 8) as i32)) && (0..=63).contains(&to)
 }
 
 // Converts standard chess notation into indexable values
- fn as_coordinate(input: &String) -> usize {
+pub(crate) fn as_coordinate(input: &str) -> usize {
     let chars: Vec<char> = input.chars().collect();
     let file = match chars[0] {
         'A' => 0,
@@ -400,7 +868,7 @@ fn move_in_bounds(to: i32, from: usize, file_move: i32) -> bool {
     (rank + file) as usize
 }
 
- fn as_standard_notation(input: &usize) -> String {
+pub(crate) fn as_standard_notation(input: &usize) -> String {
     let mut file = match input % 8 {
         0 => "A".to_string(),
         1 => "B".to_string(),
@@ -489,6 +957,220 @@ fn generate_board() -> [Option<Piece>; 64] {
     ]
 }
 
+/// Generates the Chess960 starting position with the given number, from 0
+/// to 959, using Scharnagl's numbering. Returns None if the number is out
+/// of range.
+pub fn generate_chess960_board(index: usize) -> Option<[Option<Piece>; 64]> {
+    if index >= 960 {
+        return None;
+    }
+    // Files of the knights among the five squares left after bishops and queen
+    const KNIGHTS: [(usize, usize); 10] = [
+        (0, 1),
+        (0, 2),
+        (0, 3),
+        (0, 4),
+        (1, 2),
+        (1, 3),
+        (1, 4),
+        (2, 3),
+        (2, 4),
+        (3, 4),
+    ];
+
+    let mut back_rank: [Option<PieceType>; 8] = [None; 8];
+    back_rank[(index % 4) * 2 + 1] = Some(PieceType::Bishop);
+    back_rank[(index / 4 % 4) * 2] = Some(PieceType::Bishop);
+    let rest = index / 16;
+    let place_on_empty = |back_rank: &mut [Option<PieceType>; 8], n: usize, title: PieceType| {
+        let file = (0..8).filter(|&file| back_rank[file].is_none()).nth(n).unwrap();
+        back_rank[file] = Some(title);
+    };
+    place_on_empty(&mut back_rank, rest % 6, PieceType::Queen);
+    let (first, second) = KNIGHTS[rest / 6];
+    // Place the second knight first so the first one's index isn't shifted
+    place_on_empty(&mut back_rank, second, PieceType::Knight);
+    place_on_empty(&mut back_rank, first, PieceType::Knight);
+    // The king stands between the two rooks
+    for &title in &[PieceType::Rook, PieceType::King, PieceType::Rook] {
+        place_on_empty(&mut back_rank, 0, title);
+    }
+
+    let mut board = [None; 64];
+    for (file, title) in back_rank.iter().enumerate() {
+        let title = title.unwrap();
+        board[file] = Some(Piece::new(Color::White, title, false));
+        board[8 + file] = Some(Piece::new(Color::White, PieceType::Pawn, false));
+        board[48 + file] = Some(Piece::new(Color::Black, PieceType::Pawn, false));
+        board[56 + file] = Some(Piece::new(Color::Black, title, false));
+    }
+    Some(board)
+}
+
+// Parses the piece placement field of a FEN string.
+// Pawns that aren't on their starting rank are marked as having moved.
+pub(crate) fn parse_placement(field: &str) -> Option<[Option<Piece>; 64]> {
+    let mut board = [None; 64];
+    let ranks: Vec<&str> = field.split('/').collect();
+    if ranks.len() != 8 {
+        return None;
+    }
+    // FEN lists the ranks from the eighth down to the first
+    for (i, rank_string) in ranks.iter().enumerate() {
+        let rank = 7 - i;
+        let mut file = 0;
+        for c in rank_string.chars() {
+            if let Some(empty) = c.to_digit(10) {
+                file += empty as usize;
+                continue;
+            }
+            if file > 7 {
+                return None;
+            }
+            let color = if c.is_ascii_uppercase() {
+                Color::White
+            } else {
+                Color::Black
+            };
+            let title = match c.to_ascii_lowercase() {
+                'k' => PieceType::King,
+                'q' => PieceType::Queen,
+                'b' => PieceType::Bishop,
+                'n' => PieceType::Knight,
+                'r' => PieceType::Rook,
+                'p' => PieceType::Pawn,
+                _ => return None,
+            };
+            let start_rank = if color == Color::White { 1 } else { 6 };
+            let has_moved = title == PieceType::Pawn && rank != start_rank;
+            board[file + rank * 8] = Some(Piece::new(color, title, has_moved));
+            file += 1;
+        }
+        if file != 8 {
+            return None;
+        }
+    }
+    Some(board)
+}
+
+// Converts a square like "e3" or "E3" into an index, or None if it isn't a square
+pub(crate) fn parse_square(input: &str) -> Option<usize> {
+    let chars: Vec<char> = input.to_ascii_uppercase().chars().collect();
+    match chars.as_slice() {
+        [file @ 'A'..='H', rank @ '1'..='8'] => {
+            Some((*file as u8 - b'A') as usize + (*rank as u8 - b'1') as usize * 8)
+        }
+        _ => None,
+    }
+}
+
+// Writes all six fields of a FEN string
+fn position_to_fen(
+    board: &[Option<Piece>; 64],
+    active_color: Color,
+    castling: CastlingRights,
+    en_passant: Option<usize>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    shredder: bool,
+) -> String {
+    let color = match active_color {
+        Color::White => "w",
+        Color::Black => "b",
+    };
+    let en_passant = match en_passant {
+        Some(square) => as_standard_notation(&square).to_lowercase(),
+        None => String::from("-"),
+    };
+    format!(
+        "{} {} {} {} {} {}",
+        board_to_fen(board),
+        color,
+        castling_to_fen(board, castling, shredder),
+        en_passant,
+        halfmove_clock,
+        fullmove_number
+    )
+}
+
+// Writes the castling field of a FEN string. Rooks that aren't the outermost
+// rook on their side are written with their file letter, as in X-FEN.
+// Shredder-FEN always uses the file letter.
+fn castling_to_fen(
+    board: &[Option<Piece>; 64],
+    castling: CastlingRights,
+    shredder: bool,
+) -> String {
+    let mut fen = String::new();
+    let sides = [
+        (castling.white_king_side, Color::White, true),
+        (castling.white_queen_side, Color::White, false),
+        (castling.black_king_side, Color::Black, true),
+        (castling.black_queen_side, Color::Black, false),
+    ];
+    for &(rook_file, color, is_king_side) in &sides {
+        let rook_file = match rook_file {
+            Some(file) => file,
+            None => continue,
+        };
+        let back_rank = if color == Color::White { 0 } else { 7 };
+        // Is there another rook further out on the same side
+        let outer_files: Vec<usize> = if is_king_side {
+            (rook_file + 1..8).collect()
+        } else {
+            (0..rook_file).collect()
+        };
+        let is_outermost = !outer_files.iter().any(|file| {
+            board[back_rank * 8 + file]
+                .is_some_and(|piece| piece.title() == PieceType::Rook && piece.color() == color)
+        });
+        let letter = if is_outermost && !shredder {
+            if is_king_side {
+                'k'
+            } else {
+                'q'
+            }
+        } else {
+            (b'a' + rook_file as u8) as char
+        };
+        fen.push(match color {
+            Color::White => letter.to_ascii_uppercase(),
+            Color::Black => letter,
+        });
+    }
+    if fen.is_empty() {
+        fen.push('-');
+    }
+    fen
+}
+
+// Writes the piece placement field of a FEN string
+fn board_to_fen(board: &[Option<Piece>; 64]) -> String {
+    let mut fen = String::new();
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            match board[file + rank * 8] {
+                Some(piece) => {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push(piece.letter());
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            fen.push_str(&empty.to_string());
+        }
+        if rank > 0 {
+            fen.push('/');
+        }
+    }
+    fen
+}
+
 /// Implement print routine for Game.
 ///
 /// Output example:
@@ -505,7 +1187,7 @@ fn generate_board() -> [Option<Piece>; 64] {
 impl fmt::Display for Game {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         /* build board representation string */
-        write!(f, "A  B  C  D  E  F  G  H \n")?;
+        writeln!(f, "A  B  C  D  E  F  G  H ")?;
         for rank in (0..8).rev() {
             for file in 0..8 {
                 if self.get_board()[file + rank * 8].is_some() {
@@ -514,9 +1196,9 @@ impl fmt::Display for Game {
                     write!(f, "*  ")?;
                 }
             }
-            write!(f, "{} \n", rank + 1)?;
+            writeln!(f, "{} ", rank + 1)?;
         }
-        write!(f, "\n")?;
+        writeln!(f)?;
         writeln!(f, "Turn: {:?}", self.active_color)
     }
 }
@@ -554,7 +1236,7 @@ mod tests {
 
     #[test]
     fn convert_input_to_coordinates() {
-        assert_eq!(as_coordinate(&"B4".to_string()), 25)
+        assert_eq!(as_coordinate("B4"), 25)
     }
 
     #[test]
@@ -569,7 +1251,7 @@ mod tests {
 
     #[test]
     fn convert_coordinates_to_standard_notation() {
-        assert_eq!(as_standard_notation(&(25 as usize)), "B4".to_string());
+        assert_eq!(as_standard_notation(&25), "B4".to_string());
     }
 
     #[test]
@@ -756,8 +1438,9 @@ mod tests {
             None,
             Some(Piece::new(Color::Black, PieceType::King, false)),
             None,
-        ]);
-        
+        ])
+        .unwrap();
+
         game.make_move(String::from("B1"), String::from("A1"));
         print!("{}", game);
         game.make_move(String::from("B3"), String::from("B2"));
@@ -775,6 +1458,113 @@ mod tests {
         print!("{}", game);
     }
 
+    #[test]
+    fn make_move_returns_state_of_quiet_move() {
+        let mut game = Game::new();
+        assert_eq!(
+            game.make_move(String::from("E2"), String::from("E4")),
+            Some(GameState::InProgress)
+        );
+        assert_eq!(game.get_active_color(), Color::Black);
+    }
+
+    #[test]
+    fn undo_restores_previous_position() {
+        let mut game = Game::new();
+        game.make_move(String::from("E2"), String::from("E4"));
+        assert_eq!(game.undo_move(), Some(GameState::InProgress));
+        assert_eq!(game.get_active_color(), Color::White);
+        assert_eq!(
+            game.get_board()[12],
+            Some(Piece::new(Color::White, PieceType::Pawn, false))
+        );
+        assert_eq!(game.undo_move(), None);
+    }
+
+    #[test]
+    fn no_moves_after_checkmate() {
+        let mut game = Game::new();
+        game.make_move(String::from("F2"), String::from("F3"));
+        game.make_move(String::from("E7"), String::from("E5"));
+        game.make_move(String::from("G2"), String::from("G4"));
+        game.make_move(String::from("D8"), String::from("H4"));
+        assert_eq!(game.get_game_state(), GameState::WhiteCheckMate);
+        assert_eq!(game.make_move(String::from("A2"), String::from("A3")), None);
+    }
+
+    #[test]
+    fn pawns_cant_jump_over_pieces() {
+        let game = Game::from_fen("4k3/8/8/8/8/5N2/5P2/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.get_possible_moves(String::from("F2")), None);
+    }
+
+    #[test]
+    fn game_from_fen() {
+        let game =
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        assert_eq!(game.get_active_color(), Color::Black);
+        assert_eq!(
+            game.get_board()[28],
+            Some(Piece::new(Color::White, PieceType::Pawn, true))
+        );
+        assert!(Game::from_fen("rnbqkbnr/pppppppp/8/8").is_none());
+    }
+
+    #[test]
+    fn fen_of_game() {
+        let mut game = Game::new();
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        game.make_move(String::from("E2"), String::from("E4"));
+        game.make_move(String::from("G8"), String::from("F6"));
+        game.make_move(String::from("B1"), String::from("C3"));
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 2"
+        );
+        assert_eq!(
+            game.get_starting_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert_eq!(
+            game.get_move_history()[1],
+            (String::from("G8"), String::from("F6"))
+        );
+    }
+
+    #[test]
+    fn new_with_board_rejects_invalid_positions() {
+        let problems = Game::new_with_board([None; 64]).err().unwrap();
+        assert_eq!(
+            problems,
+            vec![
+                PositionError::MissingKing(Color::White),
+                PositionError::MissingKing(Color::Black)
+            ]
+        );
+        assert!(Game::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").is_some());
+        assert!(Game::from_fen("4k3/8/8/8/8/8/8/8 w - - 0 1").is_none());
+    }
+
+    #[test]
+    fn kings_cant_move_next_to_each_other() {
+        let game = Game::from_fen("8/8/8/3k4/8/3K4/8/8 w - - 0 1").unwrap();
+        let mut moves = game.get_possible_moves(String::from("D3")).unwrap();
+        moves.sort();
+        assert_eq!(moves, vec!["C2", "C3", "D2", "E2", "E3"]);
+    }
+
+    #[test]
+    fn black_king_cant_move_next_to_white_king() {
+        let game = Game::from_fen("7k/8/6K1/8/8/8/8/R7 b - - 0 1").unwrap();
+        assert_eq!(
+            game.get_all_possible_moves(),
+            vec![(String::from("H8"), String::from("G8"))]
+        );
+    }
+
     #[test]
     fn game_doesnt_return_tie(){
         let mut game = Game::new();
@@ -782,4 +1572,115 @@ mod tests {
         print!("{}", game);
         assert_eq!(game.get_game_state(), GameState::InProgress);
     }
+
+    #[test]
+    fn castling_moves_king_and_rook() {
+        let mut game = Game::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1").unwrap();
+        let mut moves = game.get_possible_moves(String::from("E1")).unwrap();
+        moves.sort();
+        assert_eq!(moves, vec!["C1", "D1", "F1", "G1"]);
+
+        game.make_move(String::from("E1"), String::from("G1"));
+        assert_eq!(game.to_fen(), "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R4RK1 b kq - 1 1");
+        game.make_move(String::from("E8"), String::from("C8"));
+        assert_eq!(game.to_fen(), "2kr3r/pppppppp/8/8/8/8/PPPPPPPP/R4RK1 w - - 2 2");
+
+        game.undo_move();
+        game.undo_move();
+        assert_eq!(game.to_fen(), "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1");
+    }
+
+    #[test]
+    fn cant_castle_through_check() {
+        let game = Game::from_fen("4k3/8/8/8/8/8/5r2/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(game.get_game_state(), GameState::InProgress);
+        let moves = game.get_possible_moves(String::from("E1")).unwrap();
+        assert!(moves.contains(&String::from("C1")));
+        assert!(!moves.contains(&String::from("G1")));
+
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/R2NK2R w KQ - 0 1").unwrap();
+        let moves = game.get_possible_moves(String::from("E1")).unwrap();
+        assert!(!moves.contains(&String::from("C1")));
+        assert!(moves.contains(&String::from("G1")));
+    }
+
+    #[test]
+    fn moving_rook_loses_castling_right() {
+        let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        game.make_move(String::from("H1"), String::from("H8"));
+        assert_eq!(game.get_castling_rights().white_king_side, None);
+        assert_eq!(game.get_castling_rights().black_king_side, None);
+        assert_eq!(game.get_castling_rights().black_queen_side, Some(0));
+    }
+
+    #[test]
+    fn en_passant_captures_the_passed_pawn() {
+        let mut game = Game::new();
+        game.make_move(String::from("E2"), String::from("E4"));
+        assert_eq!(game.get_en_passant(), Some(String::from("E3")));
+        game.make_move(String::from("A7"), String::from("A6"));
+        game.make_move(String::from("E4"), String::from("E5"));
+        game.make_move(String::from("D7"), String::from("D5"));
+        assert!(game
+            .get_possible_moves(String::from("E5"))
+            .unwrap()
+            .contains(&String::from("D6")));
+        game.make_move(String::from("E5"), String::from("D6"));
+        assert_eq!(game.get_board()[35], None);
+        assert_eq!(game.get_halfmove_clock(), 0);
+        assert_eq!(game.get_fullmove_number(), 3);
+    }
+
+    #[test]
+    fn chess960_starting_positions() {
+        assert_eq!(generate_chess960_board(518), Some(generate_board()));
+        assert_eq!(generate_chess960_board(960), None);
+        let game = Game::new_chess960(0).unwrap();
+        assert_eq!(
+            game.to_fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+
+        let mut boards: Vec<String> = (0..960)
+            .map(|index| board_to_fen(&generate_chess960_board(index).unwrap()))
+            .collect();
+        boards.sort();
+        boards.dedup();
+        assert_eq!(boards.len(), 960);
+    }
+
+    #[test]
+    fn chess960_castling_moves_king_onto_rook() {
+        let mut game =
+            Game::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/1R2KR2 w FBkq - 0 1").unwrap();
+        assert!(game.is_chess960());
+        let moves = game.get_possible_moves(String::from("E1")).unwrap();
+        assert!(moves.contains(&String::from("F1")));
+        assert!(moves.contains(&String::from("B1")));
+
+        game.make_move(String::from("E1"), String::from("B1"));
+        assert_eq!(
+            game.to_fen(),
+            "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/2KR1R2 b kq - 1 1"
+        );
+        game.make_move(String::from("E8"), String::from("H8"));
+        assert_eq!(
+            game.to_fen(),
+            "r4rk1/pppppppp/8/8/8/8/PPPPPPPP/2KR1R2 w - - 2 2"
+        );
+    }
+
+    #[test]
+    fn pinned_pieces_stay_on_the_pin_ray() {
+        let game = Game::from_fen("4k3/8/8/8/1b6/8/3N4/2R1K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.pins(Color::White),
+            vec![(String::from("D2"), String::from("B4"))]
+        );
+        assert_eq!(game.get_possible_moves(String::from("D2")), None);
+        let game = Game::from_fen("4k3/8/8/8/1b6/8/3B4/2R1K3 w - - 0 1").unwrap();
+        let mut moves = game.get_possible_moves(String::from("D2")).unwrap();
+        moves.sort();
+        assert_eq!(moves, vec![String::from("B4"), String::from("C3")]);
+    }
 }
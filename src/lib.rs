@@ -1,2 +1,28 @@
+pub mod analysis;
+pub mod attacks;
+pub mod book;
+pub mod builder;
+pub mod correspondence;
+pub mod encoding;
+pub mod engine;
+pub mod events;
 pub mod game;
+pub mod notation;
 pub mod piece;
+pub mod problem;
+pub mod rating;
+pub mod render;
+pub mod save;
+#[cfg(feature = "serde")]
+pub mod serialize;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod sprt;
+pub mod svg;
+pub mod tablebase;
+pub mod tactics;
+pub mod tournament;
+pub mod tree;
+pub mod validate;
+pub mod xboard;
+pub mod zobrist;
@@ -0,0 +1,573 @@
+use crate::builder::*;
+use crate::game::*;
+use crate::piece::*;
+
+/// Writes a legal move in Standard Algebraic Notation, e.g. `Nf3`, `exd5`
+/// or `Qh4#`. Returns None if the move isn't legal in the game.
+pub fn to_san(game: &Game, from: &str, to: &str) -> Option<String> {
+    let moves = game.get_all_possible_moves();
+    if !moves.iter().any(|(f, t)| f == from && t == to) {
+        return None;
+    }
+
+    let board = game.get_board();
+    let piece = board[as_coordinate(from)]?;
+    let is_castling = game.is_castling(as_coordinate(from), as_coordinate(to));
+    // A pawn changing file always captures, also en passant
+    let is_capture = (board[as_coordinate(to)].is_some() && !is_castling)
+        || (piece.title() == PieceType::Pawn && from[0..1] != to[0..1]);
+    let mut san = String::new();
+
+    if is_castling {
+        if as_coordinate(to) % 8 > as_coordinate(from) % 8 {
+            san.push_str("O-O");
+        } else {
+            san.push_str("O-O-O");
+        }
+    } else if piece.title() == PieceType::Pawn {
+        if is_capture {
+            san.push_str(&from[0..1].to_lowercase());
+        }
+    } else {
+        san.push(piece_letter(piece.title()));
+
+        // Other pieces of the same kind that can reach the same square
+        let others: Vec<&String> = moves
+            .iter()
+            .filter(|(f, t)| {
+                t == to && f != from && board[as_coordinate(f)].unwrap().title() == piece.title()
+            })
+            .map(|(f, _)| f)
+            .collect();
+        if !others.is_empty() {
+            let same_file = others.iter().any(|f| f[0..1] == from[0..1]);
+            let same_rank = others.iter().any(|f| f[1..2] == from[1..2]);
+            if !same_file {
+                san.push_str(&from[0..1].to_lowercase());
+            } else if !same_rank {
+                san.push_str(&from[1..2]);
+            } else {
+                san.push_str(&from.to_lowercase());
+            }
+        }
+    }
+
+    if !san.starts_with('O') {
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&to.to_lowercase());
+    }
+
+    let mut after = game.clone();
+    match after.make_move(from.to_string(), to.to_string())? {
+        GameState::WhiteCheckMate | GameState::BlackCheckMate => san.push('#'),
+        GameState::WhiteCheck | GameState::BlackCheck => san.push('+'),
+        _ => (),
+    }
+    Some(san)
+}
+
+/// Finds the legal move written in Standard Algebraic Notation and returns
+/// it as a (from, to) pair. Check and annotation symbols are optional.
+pub fn parse_san(game: &Game, san: &str) -> Option<(String, String)> {
+    let wanted = strip_suffixes(san);
+    if wanted.is_empty() {
+        return None;
+    }
+    game.get_all_possible_moves()
+        .into_iter()
+        .find(|(from, to)| to_san(game, from, to).is_some_and(|s| strip_suffixes(&s) == wanted))
+}
+
+/// Finds a move given either in coordinate notation, like `e2e4` or
+/// `e2 e4`, or in Standard Algebraic Notation, and returns it as a
+/// (from, to) pair if it's legal.
+pub fn parse_move(game: &Game, input: &str) -> Option<(String, String)> {
+    let squares: String = input.split_whitespace().collect::<Vec<&str>>().concat();
+    if squares.len() == 4
+        && squares.is_ascii()
+        && is_square(&squares[0..2])
+        && is_square(&squares[2..4])
+    {
+        let from = squares[0..2].to_uppercase();
+        let to = squares[2..4].to_uppercase();
+        return game
+            .get_possible_moves(from.clone())
+            .filter(|targets| targets.contains(&to))
+            .map(|_| (from, to));
+    }
+    parse_san(game, input.trim())
+}
+
+/// Writes a legal move in the UCI long algebraic notation, e.g. `e2e4`.
+/// With `chess960` castling is written as the king capturing its own rook,
+/// as with the `UCI_Chess960` option, otherwise as the king's two square
+/// move. Returns None if the move isn't legal in the game.
+pub fn to_uci(game: &Game, from: &str, to: &str, chess960: bool) -> Option<String> {
+    game.get_possible_moves(from.to_string())?
+        .iter()
+        .find(|target| *target == to)?;
+    let (from_index, to_index) = (as_coordinate(from), as_coordinate(to));
+    let to = if game.is_castling(from_index, to_index) {
+        castling_target(game, from_index, to_index, chess960)
+    } else {
+        to_index
+    };
+    Some(format!("{}{}", from, as_standard_notation(&to)).to_lowercase())
+}
+
+/// Finds the legal move written in UCI long algebraic notation and returns
+/// it as a (from, to) pair. With `chess960` castling is read as the king
+/// capturing its own rook, otherwise as the king's two square move.
+pub fn parse_uci(game: &Game, input: &str, chess960: bool) -> Option<(String, String)> {
+    let input = input.trim();
+    if input.len() != 4 || !input.is_ascii() || !is_square(&input[0..2]) || !is_square(&input[2..4])
+    {
+        return None;
+    }
+    let from = input[0..2].to_uppercase();
+    game.get_possible_moves(from.clone())?
+        .into_iter()
+        .find(|target| to_uci(game, &from, target, chess960).as_deref() == Some(input))
+        .map(|target| (from, target))
+}
+
+// The square a castling king moves to in the given encoding
+fn castling_target(game: &Game, from: usize, to: usize, chess960: bool) -> usize {
+    let is_king_side = to % 8 > from % 8;
+    let back_rank = from - from % 8;
+    match (chess960, game.is_chess960()) {
+        (true, false) => {
+            let (king_side, queen_side) = game.get_castling_rights().get(game.get_active_color());
+            let rook_file = if is_king_side { king_side } else { queen_side };
+            back_rank + rook_file.unwrap()
+        }
+        (false, true) => back_rank + if is_king_side { 6 } else { 2 },
+        _ => to,
+    }
+}
+
+/// Writes the game as PGN. The seven tag roster is always written, with
+/// `?` for tags that aren't given. If no `Result` tag is given, or it isn't
+/// `1-0`, `0-1`, `1/2-1/2` or `*`, it's taken from the state of the game.
+/// `SetUp` and `FEN` tags are written from the starting position of the
+/// game, so given ones are ignored. Tags with names that aren't PGN
+/// identifiers are left out, and control characters in values are written
+/// as spaces, so the output always reads again with `parse_pgn`.
+pub fn to_pgn(game: &Game, tags: &[(String, String)]) -> String {
+    let result = pgn_result(tags, game_result(game));
+    let start_fen = game.get_starting_fen();
+    let mut pgn = pgn_tags(&start_fen, game.is_chess960(), &result, tags);
+
+    // Replay the game to write each move in the position it was made
+    let mut replay = GameBuilder::from_fen(&start_fen)
+        .unwrap()
+        .chess960(game.is_chess960())
+        .build()
+        .unwrap();
+    let mut movetext: Vec<String> = Vec::new();
+    for (from, to) in game.get_move_history() {
+        if let Some(number) = move_number(&replay, movetext.is_empty()) {
+            movetext.push(number);
+        }
+        movetext.push(to_san(&replay, &from, &to).unwrap());
+        replay.make_move(from, to);
+    }
+    movetext.push(result);
+    pgn.push_str(&wrap_movetext(&movetext));
+    pgn
+}
+
+// Writes the tag section of a game followed by the empty line before the
+// movetext. The tags for the starting position are written from the game,
+// so given ones are ignored
+pub(crate) fn pgn_tags(
+    start_fen: &str,
+    chess960: bool,
+    result: &str,
+    tags: &[(String, String)],
+) -> String {
+    let mut pgn = String::new();
+    for name in &["Event", "Site", "Date", "Round", "White", "Black"] {
+        let value = tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map_or("?", |(_, value)| value.as_str());
+        pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag(value)));
+    }
+    pgn.push_str(&format!("[Result \"{}\"]\n", result));
+    for (name, value) in tags {
+        let roster = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+        let is_setup =
+            ["SetUp", "FEN"].contains(&name.as_str()) || (name == "Variant" && chess960);
+        if !roster.contains(&name.as_str()) && !is_setup && is_tag_name(name) {
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag(value)));
+        }
+    }
+
+    if chess960 {
+        pgn.push_str("[Variant \"Chess960\"]\n");
+    }
+    if start_fen != Game::new().to_fen() || chess960 {
+        pgn.push_str("[SetUp \"1\"]\n");
+        pgn.push_str(&format!("[FEN \"{}\"]\n", start_fen));
+    }
+    pgn.push('\n');
+    pgn
+}
+
+// The move number written before a move, like `3.` for white. Black's moves
+// only get one, like `3...`, when `is_first` is set
+pub(crate) fn move_number(game: &Game, is_first: bool) -> Option<String> {
+    let number = game.get_fullmove_number();
+    match game.get_active_color() {
+        Color::White => Some(format!("{}.", number)),
+        Color::Black if is_first => Some(format!("{}...", number)),
+        Color::Black => None,
+    }
+}
+
+// Joins movetext tokens into lines shorter than 80 characters
+pub(crate) fn wrap_movetext(tokens: &[String]) -> String {
+    let mut text = String::new();
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + token.len() >= 80 {
+            text.push_str(&line);
+            text.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(token);
+    }
+    text.push_str(&line);
+    text.push('\n');
+    text
+}
+
+/// Reads the first game of a PGN text and plays out its main line. Returns
+/// the game with the tags in the order they were given. Comments,
+/// variations and NAGs are skipped. Returns None if the PGN can't be read
+/// or a move isn't legal.
+pub fn parse_pgn(pgn: &str) -> Option<(Game, Vec<(String, String)>)> {
+    let (tags, movetext) = split_pgn(pgn)?;
+    let mut game = pgn_start(&tags)?;
+    let mut depth = 0;
+    for token in pgn_tokens(&movetext)? {
+        match token {
+            PgnToken::StartVariation => depth += 1,
+            PgnToken::EndVariation => depth -= 1,
+            PgnToken::Move(san) if depth == 0 => {
+                let (from, to) = parse_san(&game, &san)?;
+                game.make_move(from, to);
+            }
+            PgnToken::Result(_) if depth == 0 => break,
+            _ => (),
+        }
+    }
+    Some((game, tags))
+}
+
+/// A part of PGN movetext.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum PgnToken {
+    /// A move in SAN, with any annotation symbols like `!?` kept.
+    Move(String),
+    Comment(String),
+    Nag(u8),
+    StartVariation,
+    EndVariation,
+    Result(String),
+}
+
+// Splits PGN into its tags and its movetext
+pub(crate) fn split_pgn(pgn: &str) -> Option<(Vec<(String, String)>, String)> {
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+    for line in pgn.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && movetext.trim().is_empty() {
+            let inner = trimmed.strip_prefix('[')?.strip_suffix(']')?;
+            let (name, value) = inner.split_once(' ')?;
+            let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+            tags.push((name.to_string(), unescape_tag(value)));
+        } else {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    Some((tags, movetext))
+}
+
+/// The results a PGN game can end with.
+pub(crate) const PGN_RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+// The `Result` tag if it's a PGN result, otherwise the given one
+pub(crate) fn pgn_result(tags: &[(String, String)], otherwise: &str) -> String {
+    tags.iter()
+        .find(|(name, value)| name == "Result" && PGN_RESULTS.contains(&value.as_str()))
+        .map_or(otherwise, |(_, value)| value.as_str())
+        .to_string()
+}
+
+// Tag names start with a letter, followed by letters, digits and underscores
+pub(crate) fn is_tag_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Escapes the quotes and backslashes of a tag value, and turns control
+// characters like newlines into spaces
+fn escape_tag(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+}
+
+// Reads a tag value escaped with `escape_tag`
+fn unescape_tag(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+// The game at the start of the movetext, from the FEN tag if there is one
+pub(crate) fn pgn_start(tags: &[(String, String)]) -> Option<Game> {
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let chess960 = tag("Variant").is_some_and(|variant| variant.eq_ignore_ascii_case("chess960"));
+    match tag("FEN") {
+        Some(fen) => GameBuilder::from_fen(fen)?.chess960(chess960).build().ok(),
+        None => Some(Game::new()),
+    }
+}
+
+// Splits movetext into moves, comments, NAGs, variations and the result.
+// Move numbers are dropped
+pub(crate) fn pgn_tokens(movetext: &str) -> Option<Vec<PgnToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = movetext.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                tokens.push(PgnToken::Comment(comment.trim().to_string()));
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                tokens.push(PgnToken::Comment(comment.trim().to_string()));
+            }
+            '(' => tokens.push(PgnToken::StartVariation),
+            ')' => tokens.push(PgnToken::EndVariation),
+            c if c.is_whitespace() => (),
+            _ => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || "{};()".contains(next) {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                if let Some(nag) = word.strip_prefix('$') {
+                    tokens.push(PgnToken::Nag(nag.parse().ok()?));
+                } else if ["1-0", "0-1", "1/2-1/2", "*"].contains(&word.as_str()) {
+                    tokens.push(PgnToken::Result(word));
+                } else {
+                    // Move numbers may be written right before the move, e.g. `1.e4`
+                    let san = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                    if !san.is_empty() {
+                        tokens.push(PgnToken::Move(san.to_string()));
+                    }
+                }
+            }
+        }
+    }
+    Some(tokens)
+}
+
+/// The PGN result of a game: `1-0`, `0-1`, `1/2-1/2` or `*` if it's
+/// still in progress.
+pub fn game_result(game: &Game) -> &'static str {
+    match game.get_game_state() {
+        GameState::BlackCheckMate => "1-0",
+        GameState::WhiteCheckMate => "0-1",
+        GameState::Tie => "1/2-1/2",
+        _ => "*",
+    }
+}
+
+/// The letter used for a piece type in SAN, `P` for pawns.
+pub fn piece_letter(title: PieceType) -> char {
+    match title {
+        PieceType::King => 'K',
+        PieceType::Queen => 'Q',
+        PieceType::Bishop => 'B',
+        PieceType::Knight => 'N',
+        PieceType::Rook => 'R',
+        PieceType::Pawn => 'P',
+    }
+}
+
+fn is_square(input: &str) -> bool {
+    let chars: Vec<char> = input.to_uppercase().chars().collect();
+    chars.len() == 2 && ('A'..='H').contains(&chars[0]) && ('1'..='8').contains(&chars[1])
+}
+
+// Removes check and annotation symbols, and writes castling with letters
+fn strip_suffixes(san: &str) -> String {
+    san.trim_end_matches(['+', '#', '!', '?']).replace('0', "O")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn san_of_simple_moves() {
+        let game = Game::new();
+        assert_eq!(to_san(&game, "G1", "F3"), Some(String::from("Nf3")));
+        assert_eq!(to_san(&game, "E2", "E4"), Some(String::from("e4")));
+        assert_eq!(to_san(&game, "E2", "E5"), None);
+    }
+
+    #[test]
+    fn san_of_captures_and_mate() {
+        let mut game = Game::new();
+        game.make_move(String::from("E2"), String::from("E4"));
+        game.make_move(String::from("D7"), String::from("D5"));
+        assert_eq!(to_san(&game, "E4", "D5"), Some(String::from("exd5")));
+
+        let mut game = Game::new();
+        game.make_move(String::from("F2"), String::from("F3"));
+        game.make_move(String::from("E7"), String::from("E5"));
+        game.make_move(String::from("G2"), String::from("G4"));
+        assert_eq!(to_san(&game, "D8", "H4"), Some(String::from("Qh4#")));
+    }
+
+    #[test]
+    fn san_disambiguates_pieces() {
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1").unwrap();
+        assert_eq!(to_san(&game, "A1", "D1"), Some(String::from("Rad1")));
+        let game = Game::from_fen("4k3/8/R7/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(to_san(&game, "A1", "A3"), Some(String::from("R1a3")));
+    }
+
+    #[test]
+    fn parse_san_and_coordinates() {
+        let game = Game::new();
+        assert_eq!(
+            parse_san(&game, "Nc3"),
+            Some((String::from("B1"), String::from("C3")))
+        );
+        assert_eq!(
+            parse_move(&game, "e2 e4"),
+            Some((String::from("E2"), String::from("E4")))
+        );
+        assert_eq!(
+            parse_move(&game, "d4"),
+            Some((String::from("D2"), String::from("D4")))
+        );
+        assert_eq!(parse_move(&game, "e2e5"), None);
+        assert_eq!(parse_move(&game, "Nd2"), None);
+        assert_eq!(parse_move(&game, "aé4"), None);
+    }
+
+    #[test]
+    fn pgn_of_game() {
+        let mut game = Game::new();
+        game.make_move(String::from("F2"), String::from("F3"));
+        game.make_move(String::from("E7"), String::from("E5"));
+        game.make_move(String::from("G2"), String::from("G4"));
+        game.make_move(String::from("D8"), String::from("H4"));
+
+        let pgn = to_pgn(&game, &[(String::from("White"), String::from("Fool"))]);
+        assert!(pgn.contains("[White \"Fool\"]\n"));
+        assert!(pgn.contains("[Result \"0-1\"]\n"));
+        assert!(pgn.ends_with("\n1. f3 e5 2. g4 Qh4# 0-1\n"));
+    }
+
+    #[test]
+    fn pgn_from_position_with_black_to_move() {
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap();
+        game.make_move(String::from("E8"), String::from("D7"));
+        let pgn = to_pgn(&game, &[]);
+        assert!(pgn.contains("[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]"));
+        assert!(pgn.ends_with("\n1... Kd7 *\n"));
+    }
+
+    #[test]
+    fn reads_pgn_main_line() {
+        let pgn = "[Event \"Casual\"]\n[White \"Fool\"]\n\n\
+                   1. f3 {A weak move} e5 2.g4?? (2. e4 Nc6) $4 Qh4# 0-1\n";
+        let (game, tags) = parse_pgn(pgn).unwrap();
+        assert_eq!(tags[1], (String::from("White"), String::from("Fool")));
+        assert_eq!(game.get_game_state(), GameState::WhiteCheckMate);
+        assert_eq!(parse_pgn(&to_pgn(&game, &tags)).unwrap().0.to_fen(), game.to_fen());
+        assert!(parse_pgn("1. e4 e4").is_none());
+
+        // Quotes and backslashes in tag values survive a round trip
+        let tags = vec![(String::from("White"), String::from("The \"C:\\\" engine"))];
+        let pgn = to_pgn(&game, &tags);
+        assert!(pgn.contains("[White \"The \\\"C:\\\\\\\" engine\"]"));
+        assert_eq!(parse_pgn(&pgn).unwrap().1[4], tags[0]);
+
+        // Newlines, bad tag names and results can't break the PGN
+        let tags = vec![
+            (String::from("Event"), String::from("Club\nnight")),
+            (String::from("Bad name]"), String::from("x")),
+            (String::from("Result"), String::from("0-1 1. e4")),
+        ];
+        let (_, read) = parse_pgn(&to_pgn(&game, &tags)).unwrap();
+        assert_eq!(read[0], (String::from("Event"), String::from("Club night")));
+        assert_eq!(read[6], (String::from("Result"), String::from("0-1")));
+        assert_eq!(read.len(), 7);
+    }
+
+    #[test]
+    fn san_of_castling_and_en_passant() {
+        let game = Game::from_fen("r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1").unwrap();
+        assert_eq!(to_san(&game, "E1", "G1"), Some(String::from("O-O")));
+        assert_eq!(
+            parse_san(&game, "0-0"),
+            Some((String::from("E1"), String::from("G1")))
+        );
+
+        let game = Game::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        assert_eq!(to_san(&game, "E5", "D6"), Some(String::from("exd6")));
+    }
+
+    #[test]
+    fn uci_castling_in_both_encodings() {
+        let game = Game::from_fen("r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1").unwrap();
+        assert_eq!(to_uci(&game, "E1", "G1", false), Some(String::from("e1g1")));
+        assert_eq!(to_uci(&game, "E1", "G1", true), Some(String::from("e1h1")));
+        assert_eq!(
+            parse_uci(&game, "e1h1", true),
+            Some((String::from("E1"), String::from("G1")))
+        );
+
+        let game = Game::new_chess960(518).unwrap();
+        assert_eq!(parse_uci(&game, "e1g1", false), None);
+        assert_eq!(parse_uci(&game, "aé4", false), None);
+        assert_eq!(
+            parse_uci(&game, "g1f3", false),
+            Some((String::from("G1"), String::from("F3")))
+        );
+    }
+}
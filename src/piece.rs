@@ -1,6 +1,7 @@
 use std::fmt;
 
 #[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Color {
     Black,
     White,
@@ -14,8 +15,16 @@ impl Color {
             -1
         }
     }
+
+    pub fn opposite(&self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
 }
 #[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Piece {
     color: Color,
     title: PieceType,
@@ -46,8 +55,44 @@ impl Piece {
     pub fn set_has_moved(&mut self) {
         self.has_moved = true;
     }
+
+    /// Returns the FEN letter of the piece, uppercase for white and lowercase for black.
+    pub fn letter(&self) -> char {
+        let letter = match self.title {
+            PieceType::King => 'k',
+            PieceType::Queen => 'q',
+            PieceType::Bishop => 'b',
+            PieceType::Knight => 'n',
+            PieceType::Rook => 'r',
+            PieceType::Pawn => 'p',
+        };
+        if self.color == Color::White {
+            letter.to_ascii_uppercase()
+        } else {
+            letter
+        }
+    }
+
+    /// Returns the Unicode chess glyph of the piece.
+    pub fn unicode(&self) -> char {
+        match (self.color, self.title) {
+            (Color::White, PieceType::King) => '♔',
+            (Color::White, PieceType::Queen) => '♕',
+            (Color::White, PieceType::Bishop) => '♗',
+            (Color::White, PieceType::Knight) => '♘',
+            (Color::White, PieceType::Rook) => '♖',
+            (Color::White, PieceType::Pawn) => '♙',
+            (Color::Black, PieceType::King) => '♚',
+            (Color::Black, PieceType::Queen) => '♛',
+            (Color::Black, PieceType::Bishop) => '♝',
+            (Color::Black, PieceType::Knight) => '♞',
+            (Color::Black, PieceType::Rook) => '♜',
+            (Color::Black, PieceType::Pawn) => '♟',
+        }
+    }
 }
 #[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PieceType {
     King,
     Queen,
@@ -160,4 +205,14 @@ mod tests {
             (-1, 0, true)
         );
     }
+
+    #[test]
+    fn piece_letters_and_glyphs() {
+        let knight = Piece::new(Color::White, PieceType::Knight, false);
+        assert_eq!(knight.letter(), 'N');
+        assert_eq!(knight.unicode(), '♘');
+        let queen = Piece::new(Color::Black, PieceType::Queen, false);
+        assert_eq!(queen.letter(), 'q');
+        assert_eq!(queen.unicode(), '♛');
+    }
 }
@@ -0,0 +1,213 @@
+use crate::game::*;
+
+/// The kinds of composed mate problems the solver knows.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ProblemKind {
+    /// The side to move mates against any defence.
+    DirectMate,
+    /// Both sides cooperate so that the side to move is mated, by the
+    /// opponent's last move.
+    HelpMate,
+    /// The side to move forces the opponent to mate it against any defence.
+    SelfMate,
+}
+
+/// The answer to a mate problem.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solution {
+    pub kind: ProblemKind,
+    /// The number of moves by the side to move.
+    pub moves: u32,
+    /// All first moves that solve the problem as (from, to) pairs. Empty
+    /// if the problem has no solution.
+    pub key_moves: Vec<(String, String)>,
+}
+
+impl Solution {
+    /// If the problem has at least one solution.
+    pub fn is_solved(&self) -> bool {
+        !self.key_moves.is_empty()
+    }
+
+    /// If the problem has more than one key move, which makes a
+    /// composed problem unsound.
+    pub fn is_cooked(&self) -> bool {
+        self.key_moves.len() > 1
+    }
+}
+
+/// Proves or refutes a problem of the given kind in `moves` moves by the
+/// side to move, and finds all its key moves. Solutions that are shorter
+/// than `moves` also count.
+///
+/// The search tries every line, so the time grows quickly with `moves`.
+pub fn solve(game: &Game, kind: ProblemKind, moves: u32) -> Solution {
+    let mut game = game.clone();
+    let mut key_moves = Vec::new();
+    if moves > 0 && !game.is_game_over() {
+        for (from, to) in game.get_all_possible_moves() {
+            game.make_move(from.clone(), to.clone());
+            let solves = match kind {
+                ProblemKind::DirectMate => defence_fails(&mut game, moves),
+                ProblemKind::HelpMate => help_reply_exists(&mut game, moves),
+                ProblemKind::SelfMate => opponent_must_mate(&mut game, moves),
+            };
+            game.undo_move();
+            if solves {
+                key_moves.push((from, to));
+            }
+        }
+    }
+    Solution {
+        kind,
+        moves,
+        key_moves,
+    }
+}
+
+// If the side to move is mated now, or mated in `moves` moves by the opponent
+// against any defence
+fn defence_fails(game: &mut Game, moves: u32) -> bool {
+    if is_mated(game) {
+        return true;
+    }
+    if game.is_game_over() || moves <= 1 {
+        return false;
+    }
+    for (from, to) in game.get_all_possible_moves() {
+        game.make_move(from, to);
+        let mated = forces_mate(game, moves - 1);
+        game.undo_move();
+        if !mated {
+            return false;
+        }
+    }
+    true
+}
+
+// If the side to move can mate in `moves` moves against any defence
+fn forces_mate(game: &mut Game, moves: u32) -> bool {
+    for (from, to) in game.get_all_possible_moves() {
+        game.make_move(from, to);
+        let mated = defence_fails(game, moves);
+        game.undo_move();
+        if mated {
+            return true;
+        }
+    }
+    false
+}
+
+// If the side to move has a reply that mates the opponent now, or that
+// lets the opponent be helped into mate in the remaining moves
+fn help_reply_exists(game: &mut Game, moves: u32) -> bool {
+    if game.is_game_over() {
+        return false;
+    }
+    for (from, to) in game.get_all_possible_moves() {
+        game.make_move(from, to);
+        let mated =
+            is_mated(game) || (moves > 1 && !game.is_game_over() && helps_mate(game, moves - 1));
+        game.undo_move();
+        if mated {
+            return true;
+        }
+    }
+    false
+}
+
+// If the side to move can be helped into mate in `moves` moves
+fn helps_mate(game: &mut Game, moves: u32) -> bool {
+    for (from, to) in game.get_all_possible_moves() {
+        game.make_move(from, to);
+        let mated = help_reply_exists(game, moves);
+        game.undo_move();
+        if mated {
+            return true;
+        }
+    }
+    false
+}
+
+// If every move of the side to move mates the opponent, or lets the
+// opponent force a selfmate in the remaining moves
+fn opponent_must_mate(game: &mut Game, moves: u32) -> bool {
+    if game.is_game_over() {
+        return false;
+    }
+    for (from, to) in game.get_all_possible_moves() {
+        game.make_move(from, to);
+        let mated = is_mated(game)
+            || (moves > 1 && !game.is_game_over() && forces_selfmate(game, moves - 1));
+        game.undo_move();
+        if !mated {
+            return false;
+        }
+    }
+    true
+}
+
+// If the side to move can force the opponent to mate it in `moves` moves
+fn forces_selfmate(game: &mut Game, moves: u32) -> bool {
+    for (from, to) in game.get_all_possible_moves() {
+        game.make_move(from, to);
+        let mated = opponent_must_mate(game, moves);
+        game.undo_move();
+        if mated {
+            return true;
+        }
+    }
+    false
+}
+
+fn is_mated(game: &Game) -> bool {
+    matches!(
+        game.get_game_state(),
+        GameState::WhiteCheckMate | GameState::BlackCheckMate
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(from: &str, to: &str) -> (String, String) {
+        (String::from(from), String::from(to))
+    }
+
+    #[test]
+    fn finds_unique_mate_in_one() {
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let solution = solve(&game, ProblemKind::DirectMate, 1);
+        assert_eq!(solution.key_moves, vec![key("A1", "A8")]);
+        assert!(solution.is_solved());
+        assert!(!solution.is_cooked());
+    }
+
+    #[test]
+    fn proves_and_refutes_mate_in_two() {
+        let game = Game::from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        assert!(!solve(&game, ProblemKind::DirectMate, 1).is_solved());
+        let solution = solve(&game, ProblemKind::DirectMate, 2);
+        assert!(solution.key_moves.contains(&key("C6", "B6")));
+        assert!(solution.is_cooked());
+    }
+
+    #[test]
+    fn solves_helpmate() {
+        let game = Game::from_fen("7k/8/6K1/8/8/8/8/R7 b - - 0 1").unwrap();
+        let solution = solve(&game, ProblemKind::HelpMate, 1);
+        assert!(solution.key_moves.contains(&key("H8", "G8")));
+        assert!(!solve(&game, ProblemKind::DirectMate, 1).is_solved());
+    }
+
+    #[test]
+    fn solves_selfmate() {
+        // Black's only moves are pawn captures on b2 that mate
+        let game = Game::from_fen("7k/5P1p/4N2P/8/8/p1p5/PPN5/KB2R3 w - - 0 1").unwrap();
+        let solution = solve(&game, ProblemKind::SelfMate, 1);
+        assert!(solution.key_moves.contains(&key("E1", "E2")));
+        // Moving the knight lets black push the pawn instead
+        assert!(!solution.key_moves.contains(&key("C2", "E3")));
+    }
+}
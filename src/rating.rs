@@ -0,0 +1,670 @@
+use crate::game::*;
+use crate::save::*;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Converts between Glicko and Glicko-2 scales
+const GLICKO2_SCALE: f64 = 173.7178;
+// How precisely the new volatility of Glicko-2 is found
+const VOLATILITY_PRECISION: f64 = 0.000_001;
+
+/// How ratings are calculated.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RatingSystem {
+    /// Elo, where ratings change by `k` times the difference between the
+    /// score and the expected score after every game.
+    Elo { k: f64, initial_rating: f64 },
+    /// Glicko-2, where ratings change at the end of each rating period
+    /// from all games of the period. `tau` limits how fast the volatility
+    /// changes, usually between 0.3 and 1.2.
+    Glicko2 {
+        initial_rating: f64,
+        initial_deviation: f64,
+        initial_volatility: f64,
+        tau: f64,
+    },
+}
+
+impl RatingSystem {
+    /// Elo with the given K and ratings starting at 1500.
+    pub fn elo(k: f64) -> RatingSystem {
+        RatingSystem::Elo {
+            k,
+            initial_rating: 1500.0,
+        }
+    }
+
+    /// Glicko-2 with the usual starting values: 1500 with a deviation of
+    /// 350 and a volatility of 0.06, and a tau of 0.5.
+    pub fn glicko2() -> RatingSystem {
+        RatingSystem::Glicko2 {
+            initial_rating: 1500.0,
+            initial_deviation: 350.0,
+            initial_volatility: 0.06,
+            tau: 0.5,
+        }
+    }
+
+    fn initial(&self) -> Rating {
+        match *self {
+            RatingSystem::Elo { initial_rating, .. } => Rating {
+                rating: initial_rating,
+                ..Rating::default()
+            },
+            RatingSystem::Glicko2 {
+                initial_rating,
+                initial_deviation,
+                initial_volatility,
+                ..
+            } => Rating {
+                rating: initial_rating,
+                deviation: initial_deviation,
+                volatility: initial_volatility,
+                ..Rating::default()
+            },
+        }
+    }
+}
+
+/// Why a game was drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DrawReason {
+    Agreement,
+    Stalemate,
+    Repetition,
+    FiftyMoves,
+    InsufficientMaterial,
+    /// A player ran out of time when the opponent couldn't mate.
+    Timeout,
+    /// An arbiter or a tournament runner ended the game.
+    Adjudication,
+}
+
+impl DrawReason {
+    const ALL: [DrawReason; 7] = [
+        DrawReason::Agreement,
+        DrawReason::Stalemate,
+        DrawReason::Repetition,
+        DrawReason::FiftyMoves,
+        DrawReason::InsufficientMaterial,
+        DrawReason::Timeout,
+        DrawReason::Adjudication,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            DrawReason::Agreement => "agreement",
+            DrawReason::Stalemate => "stalemate",
+            DrawReason::Repetition => "repetition",
+            DrawReason::FiftyMoves => "fifty-moves",
+            DrawReason::InsufficientMaterial => "insufficient-material",
+            DrawReason::Timeout => "timeout",
+            DrawReason::Adjudication => "adjudication",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<DrawReason> {
+        DrawReason::ALL
+            .iter()
+            .copied()
+            .find(|reason| reason.name() == name)
+    }
+}
+
+/// The result of a rated game.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw(DrawReason),
+}
+
+impl GameResult {
+    /// The result of a finished game, or None while it's in progress.
+    pub fn from_game(game: &Game) -> Option<GameResult> {
+        match game.get_game_state() {
+            GameState::WhiteCheckMate => Some(GameResult::BlackWins),
+            GameState::BlackCheckMate => Some(GameResult::WhiteWins),
+            GameState::Tie => Some(GameResult::Draw(DrawReason::Stalemate)),
+            _ => None,
+        }
+    }
+
+    fn white_score(self) -> f64 {
+        match self {
+            GameResult::WhiteWins => 1.0,
+            GameResult::BlackWins => 0.0,
+            GameResult::Draw(_) => 0.5,
+        }
+    }
+}
+
+/// The rating of a player with their rated games. The deviation and the
+/// volatility are only used by Glicko-2 and are 0 with Elo.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Rating {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    fn count(&mut self, score: f64) {
+        if score == 1.0 {
+            self.wins += 1;
+        } else if score == 0.0 {
+            self.losses += 1;
+        } else {
+            self.draws += 1;
+        }
+    }
+}
+
+/// Which players `Ratings::leaderboard` returns.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct LeaderboardQuery {
+    /// Players with fewer rated games are left out.
+    pub min_games: u32,
+    /// Players whose rating is less certain are left out, with Glicko-2.
+    pub max_deviation: Option<f64>,
+    /// The most players to return.
+    pub limit: Option<usize>,
+}
+
+/// A reason a game couldn't be rated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RatingError {
+    /// A player can't play themself.
+    SamePlayer,
+    /// Names can't be empty or contain tabs or line breaks.
+    InvalidName(String),
+}
+
+impl fmt::Display for RatingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RatingError::SamePlayer => write!(f, "a player can't play themself"),
+            RatingError::InvalidName(name) => write!(f, "{:?} isn't a valid name", name),
+        }
+    }
+}
+
+/// The ratings of the players of a club or server. Players are added with
+/// their first rated game. With Elo ratings change right after each game,
+/// with Glicko-2 the games are kept until `end_period`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ratings {
+    system: RatingSystem,
+    unrated_draws: Vec<DrawReason>,
+    players: BTreeMap<String, Rating>,
+    // White, black and the score of white of the games of the period
+    period: Vec<(String, String, f64)>,
+}
+
+impl Ratings {
+    /// Creates ratings without players.
+    pub fn new(system: RatingSystem) -> Ratings {
+        Ratings {
+            system,
+            unrated_draws: Vec::new(),
+            players: BTreeMap::new(),
+            period: Vec::new(),
+        }
+    }
+
+    pub fn system(&self) -> RatingSystem {
+        self.system
+    }
+
+    /// Leaves draws for the given reasons unrated, for example quick draws
+    /// by agreement. All draws are rated by default.
+    pub fn set_unrated_draws(&mut self, reasons: &[DrawReason]) {
+        self.unrated_draws = reasons.to_vec();
+    }
+
+    pub fn unrated_draws(&self) -> &[DrawReason] {
+        &self.unrated_draws
+    }
+
+    /// The rating of a player, or None if they haven't played a rated game.
+    pub fn rating(&self, player: &str) -> Option<&Rating> {
+        self.players.get(player)
+    }
+
+    /// Rates a game. Returns false if the game isn't rated because of the
+    /// reason it was drawn.
+    pub fn record_game(
+        &mut self,
+        white: &str,
+        black: &str,
+        result: GameResult,
+    ) -> Result<bool, RatingError> {
+        for name in &[white, black] {
+            if name.is_empty() || name.contains(['\t', '\n', '\r']) {
+                return Err(RatingError::InvalidName(name.to_string()));
+            }
+        }
+        if white == black {
+            return Err(RatingError::SamePlayer);
+        }
+        if let GameResult::Draw(reason) = result {
+            if self.unrated_draws.contains(&reason) {
+                return Ok(false);
+            }
+        }
+        let initial = self.system.initial();
+        for name in &[white, black] {
+            self.players.entry(name.to_string()).or_insert(initial);
+        }
+
+        let score = result.white_score();
+        match self.system {
+            RatingSystem::Elo { k, .. } => {
+                let expected =
+                    expected_score(self.players[white].rating - self.players[black].rating);
+                let change = k * (score - expected);
+                let white = self.players.get_mut(white).unwrap();
+                white.rating += change;
+                white.count(score);
+                let black = self.players.get_mut(black).unwrap();
+                black.rating -= change;
+                black.count(1.0 - score);
+            }
+            RatingSystem::Glicko2 { .. } => {
+                self.period
+                    .push((white.to_string(), black.to_string(), score));
+            }
+        }
+        Ok(true)
+    }
+
+    /// The number of games waiting for the end of the rating period.
+    pub fn period_games(&self) -> usize {
+        self.period.len()
+    }
+
+    /// Ends a Glicko-2 rating period: everyone who played gets a new rating
+    /// from their games against the ratings of their opponents at the start
+    /// of the period, and the deviation of everyone who didn't play grows.
+    /// Does nothing with Elo.
+    pub fn end_period(&mut self) {
+        let (initial_deviation, tau) = match self.system {
+            RatingSystem::Glicko2 {
+                initial_deviation,
+                tau,
+                ..
+            } => (initial_deviation, tau),
+            RatingSystem::Elo { .. } => return,
+        };
+        let period = std::mem::take(&mut self.period);
+        let mut games: BTreeMap<&str, Vec<(Rating, f64)>> = BTreeMap::new();
+        for (white, black, score) in &period {
+            games
+                .entry(white)
+                .or_default()
+                .push((self.players[black], *score));
+            games
+                .entry(black)
+                .or_default()
+                .push((self.players[white], 1.0 - score));
+        }
+
+        let mut updated = self.players.clone();
+        for (name, rating) in updated.iter_mut() {
+            match games.get(name.as_str()) {
+                Some(results) => {
+                    *rating = glicko2_update(rating, results, tau);
+                    for (_, score) in results {
+                        rating.count(*score);
+                    }
+                }
+                None => {
+                    let deviation = glicko2_inactive_deviation(rating);
+                    rating.deviation = deviation.min(initial_deviation);
+                }
+            }
+        }
+        self.players = updated;
+    }
+
+    /// The players ordered by rating, the best first, with ties by name.
+    pub fn leaderboard(&self, query: &LeaderboardQuery) -> Vec<(String, Rating)> {
+        let mut players: Vec<(String, Rating)> = self
+            .players
+            .iter()
+            .filter(|(_, rating)| rating.games() >= query.min_games)
+            .filter(|(_, rating)| {
+                query.max_deviation.is_none_or(|deviation| {
+                    matches!(self.system, RatingSystem::Elo { .. }) || rating.deviation <= deviation
+                })
+            })
+            .map(|(name, rating)| (name.clone(), *rating))
+            .collect();
+        players.sort_by(|(_, a), (_, b)| b.rating.total_cmp(&a.rating));
+        if let Some(limit) = query.limit {
+            players.truncate(limit);
+        }
+        players
+    }
+
+    /// Writes the ratings, with the games of the current period, to text
+    /// that `from_text` reads back.
+    pub fn to_text(&self) -> String {
+        let mut text = match self.system {
+            RatingSystem::Elo { k, initial_rating } => format!("elo\t{}\t{}\n", k, initial_rating),
+            RatingSystem::Glicko2 {
+                initial_rating,
+                initial_deviation,
+                initial_volatility,
+                tau,
+            } => format!(
+                "glicko2\t{}\t{}\t{}\t{}\n",
+                initial_rating, initial_deviation, initial_volatility, tau
+            ),
+        };
+        for reason in &self.unrated_draws {
+            text.push_str(&format!("unrated\t{}\n", reason.name()));
+        }
+        for (name, rating) in &self.players {
+            text.push_str(&format!(
+                "player\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                name,
+                rating.rating,
+                rating.deviation,
+                rating.volatility,
+                rating.wins,
+                rating.draws,
+                rating.losses
+            ));
+        }
+        for (white, black, score) in &self.period {
+            text.push_str(&format!("game\t{}\t{}\t{}\n", white, black, score));
+        }
+        text
+    }
+
+    /// Reads ratings written by `to_text`.
+    pub fn from_text(text: &str) -> Option<Ratings> {
+        let mut lines = text.lines().filter(|line| !line.is_empty());
+        let fields: Vec<&str> = lines.next()?.split('\t').collect();
+        let numbers = |fields: &[&str]| -> Option<Vec<f64>> {
+            fields.iter().map(|field| field.parse().ok()).collect()
+        };
+        let system = match (fields[0], numbers(&fields[1..])?.as_slice()) {
+            ("elo", &[k, initial_rating]) => RatingSystem::Elo { k, initial_rating },
+            ("glicko2", &[initial_rating, initial_deviation, initial_volatility, tau]) => {
+                RatingSystem::Glicko2 {
+                    initial_rating,
+                    initial_deviation,
+                    initial_volatility,
+                    tau,
+                }
+            }
+            _ => return None,
+        };
+
+        let mut ratings = Ratings::new(system);
+        for line in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["unrated", reason] => ratings.unrated_draws.push(DrawReason::from_name(reason)?),
+                ["player", name, numbers @ ..] if numbers.len() == 6 => {
+                    let rating = Rating {
+                        rating: numbers[0].parse().ok()?,
+                        deviation: numbers[1].parse().ok()?,
+                        volatility: numbers[2].parse().ok()?,
+                        wins: numbers[3].parse().ok()?,
+                        draws: numbers[4].parse().ok()?,
+                        losses: numbers[5].parse().ok()?,
+                    };
+                    ratings.players.insert(name.to_string(), rating);
+                }
+                ["game", white, black, score] => {
+                    if !ratings.players.contains_key(*white)
+                        || !ratings.players.contains_key(*black)
+                    {
+                        return None;
+                    }
+                    ratings.period.push((
+                        white.to_string(),
+                        black.to_string(),
+                        score.parse().ok()?,
+                    ));
+                }
+                _ => return None,
+            }
+        }
+        Some(ratings)
+    }
+
+    /// Saves the ratings to a file. The file is replaced in one step, so a
+    /// crash while saving leaves the previous save intact.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        write_atomically(path.as_ref(), &self.to_text())
+    }
+
+    /// Reads ratings saved with `save`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Ratings> {
+        let text = fs::read_to_string(path)?;
+        Ratings::from_text(&text)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid ratings"))
+    }
+}
+
+/// The expected score from 0 to 1 of a player the given Elo stronger.
+pub fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// The new Glicko-2 rating of a player from their results in a period,
+// each with the opponent's rating and the player's score, following
+// Glickman's "Example of the Glicko-2 system"
+fn glicko2_update(player: &Rating, results: &[(Rating, f64)], tau: f64) -> Rating {
+    let mu = (player.rating - 1500.0) / GLICKO2_SCALE;
+    let phi = player.deviation / GLICKO2_SCALE;
+    let g = |phi: f64| 1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt();
+
+    let mut inverse_variance = 0.0;
+    let mut improvement = 0.0;
+    for (opponent, score) in results {
+        let opponent_mu = (opponent.rating - 1500.0) / GLICKO2_SCALE;
+        let opponent_g = g(opponent.deviation / GLICKO2_SCALE);
+        let expected = 1.0 / (1.0 + (-opponent_g * (mu - opponent_mu)).exp());
+        inverse_variance += opponent_g * opponent_g * expected * (1.0 - expected);
+        improvement += opponent_g * (score - expected);
+    }
+    let variance = 1.0 / inverse_variance;
+    let delta = variance * improvement;
+    let volatility = glicko2_volatility(phi, player.volatility, variance, delta, tau);
+
+    let pre_period = (phi * phi + volatility * volatility).sqrt();
+    let new_phi = 1.0 / (1.0 / (pre_period * pre_period) + 1.0 / variance).sqrt();
+    Rating {
+        rating: 1500.0 + GLICKO2_SCALE * (mu + new_phi * new_phi * improvement),
+        deviation: GLICKO2_SCALE * new_phi,
+        volatility,
+        ..*player
+    }
+}
+
+// The new volatility, found with the Illinois algorithm
+fn glicko2_volatility(phi: f64, sigma: f64, variance: f64, delta: f64, tau: f64) -> f64 {
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let denominator = phi * phi + variance + ex;
+        ex * (delta * delta - phi * phi - variance - ex) / (2.0 * denominator * denominator)
+            - (x - a) / (tau * tau)
+    };
+
+    let mut low = a;
+    let mut high = if delta * delta > phi * phi + variance {
+        (delta * delta - phi * phi - variance).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * tau) < 0.0 {
+            k += 1.0;
+        }
+        a - k * tau
+    };
+    let (mut f_low, mut f_high) = (f(low), f(high));
+    while (high - low).abs() > VOLATILITY_PRECISION {
+        let middle = low + (low - high) * f_low / (f_high - f_low);
+        let f_middle = f(middle);
+        if f_middle * f_high <= 0.0 {
+            low = high;
+            f_low = f_high;
+        } else {
+            f_low /= 2.0;
+        }
+        high = middle;
+        f_high = f_middle;
+    }
+    (low / 2.0).exp()
+}
+
+// The deviation of a player who didn't play in a period
+fn glicko2_inactive_deviation(player: &Rating) -> f64 {
+    let phi = player.deviation / GLICKO2_SCALE;
+    GLICKO2_SCALE * (phi * phi + player.volatility * player.volatility).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn close(a: f64, b: f64, precision: f64) -> bool {
+        (a - b).abs() < precision
+    }
+
+    #[test]
+    fn rates_elo_games() {
+        let mut ratings = Ratings::new(RatingSystem::elo(32.0));
+        assert_eq!(
+            ratings.record_game("Ada", "Bo", GameResult::WhiteWins),
+            Ok(true)
+        );
+        assert_eq!(ratings.rating("Ada").unwrap().rating, 1516.0);
+        assert_eq!(ratings.rating("Bo").unwrap().rating, 1484.0);
+
+        // The favourite loses points with a draw
+        ratings.set_unrated_draws(&[DrawReason::Agreement]);
+        assert_eq!(
+            ratings.record_game("Bo", "Ada", GameResult::Draw(DrawReason::Agreement)),
+            Ok(false)
+        );
+        ratings
+            .record_game("Bo", "Ada", GameResult::Draw(DrawReason::Repetition))
+            .unwrap();
+        let ada = ratings.rating("Ada").unwrap();
+        assert!(ada.rating < 1516.0 && ada.rating > 1500.0);
+        assert_eq!((ada.wins, ada.draws, ada.losses), (1, 1, 0));
+
+        assert_eq!(
+            ratings.record_game("Ada", "Ada", GameResult::WhiteWins),
+            Err(RatingError::SamePlayer)
+        );
+        assert!(ratings
+            .record_game("Ada\tB", "Bo", GameResult::WhiteWins)
+            .is_err());
+
+        let mut game = Game::new();
+        for (from, to) in &[("F2", "F3"), ("E7", "E5"), ("G2", "G4"), ("D8", "H4")] {
+            game.make_move(from.to_string(), to.to_string());
+        }
+        assert_eq!(GameResult::from_game(&game), Some(GameResult::BlackWins));
+        assert_eq!(GameResult::from_game(&Game::new()), None);
+    }
+
+    #[test]
+    fn rates_glicko2_periods() {
+        // The example from Glickman's description of Glicko-2
+        let mut ratings = Ratings::new(RatingSystem::glicko2());
+        let players = [
+            ("Player", 1500.0, 200.0),
+            ("A", 1400.0, 30.0),
+            ("B", 1550.0, 100.0),
+            ("C", 1700.0, 300.0),
+            ("Idle", 1500.0, 200.0),
+        ];
+        for &(name, rating, deviation) in &players {
+            let rating = Rating {
+                rating,
+                deviation,
+                volatility: 0.06,
+                ..Rating::default()
+            };
+            ratings.players.insert(name.to_string(), rating);
+        }
+        ratings
+            .record_game("Player", "A", GameResult::WhiteWins)
+            .unwrap();
+        ratings
+            .record_game("B", "Player", GameResult::WhiteWins)
+            .unwrap();
+        ratings
+            .record_game("Player", "C", GameResult::BlackWins)
+            .unwrap();
+        assert_eq!(ratings.rating("Player").unwrap().rating, 1500.0);
+        assert_eq!(ratings.period_games(), 3);
+
+        ratings.end_period();
+        assert_eq!(ratings.period_games(), 0);
+        let player = ratings.rating("Player").unwrap();
+        assert!(close(player.rating, 1464.06, 0.01));
+        assert!(close(player.deviation, 151.52, 0.01));
+        assert!(close(player.volatility, 0.05999, 0.00001));
+        assert_eq!((player.wins, player.losses), (1, 2));
+        assert!(ratings.rating("Idle").unwrap().deviation > 200.0);
+
+        let query = LeaderboardQuery {
+            min_games: 1,
+            max_deviation: Some(250.0),
+            ..LeaderboardQuery::default()
+        };
+        let names: Vec<String> = ratings
+            .leaderboard(&query)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["B", "Player", "A"]);
+
+        // A broken rating doesn't stop the leaderboard
+        ratings.players.get_mut("A").unwrap().rating = f64::NAN;
+        assert_eq!(ratings.leaderboard(&query).len(), 3);
+    }
+
+    #[test]
+    fn saves_and_opens_ratings() {
+        let mut ratings = Ratings::new(RatingSystem::glicko2());
+        ratings.set_unrated_draws(&[DrawReason::Agreement, DrawReason::Adjudication]);
+        ratings
+            .record_game("Ada Lovelace", "Bo", GameResult::WhiteWins)
+            .unwrap();
+        ratings.end_period();
+        ratings
+            .record_game("Bo", "Cy", GameResult::Draw(DrawReason::Stalemate))
+            .unwrap();
+
+        let path = env::temp_dir().join(format!("ratings-test-{}.txt", std::process::id()));
+        ratings.save(&path).unwrap();
+        let opened = Ratings::open(&path).unwrap();
+        fs::write(&path, "glicko2\t1500\n").unwrap();
+        let error = Ratings::open(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(opened, ratings);
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
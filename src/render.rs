@@ -0,0 +1,251 @@
+use crate::game::*;
+use crate::piece::*;
+
+// ANSI escape codes for the square backgrounds and piece colours
+const LIGHT_SQUARE: &str = "\x1b[48;5;180m";
+const DARK_SQUARE: &str = "\x1b[48;5;137m";
+const LAST_MOVE_SQUARE: &str = "\x1b[48;5;143m";
+const TARGET_SQUARE: &str = "\x1b[48;5;108m";
+const CHECK_SQUARE: &str = "\x1b[48;5;167m";
+const WHITE_PIECE: &str = "\x1b[1;97m";
+const BLACK_PIECE: &str = "\x1b[1;30m";
+const RESET: &str = "\x1b[0m";
+
+/// Options for rendering a board as text in a terminal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderOptions {
+    /// Draw pieces with Unicode chess glyphs instead of FEN letters.
+    pub unicode: bool,
+    /// Colour the squares and pieces with ANSI escape codes.
+    pub colors: bool,
+    /// Write file letters and rank numbers on all four sides.
+    pub coordinates: bool,
+    /// Show the board from black's side.
+    pub flipped: bool,
+    /// Highlight the squares of the last move.
+    pub highlight_last_move: bool,
+    /// Highlight the king of the side in check.
+    pub highlight_check: bool,
+    /// Other squares to highlight, e.g. the legal targets of a piece.
+    /// Invalid squares are ignored.
+    pub targets: Vec<String>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            unicode: false,
+            colors: false,
+            coordinates: true,
+            flipped: false,
+            highlight_last_move: true,
+            highlight_check: true,
+            targets: Vec::new(),
+        }
+    }
+}
+
+// How a square is highlighted, most important last
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+enum Highlight {
+    None,
+    LastMove,
+    Target,
+    Check,
+}
+
+/// Renders the board of a game as text, one line per rank.
+///
+/// Without colours, highlighted squares are drawn in brackets.
+pub fn render(game: &Game, options: &RenderOptions) -> String {
+    let highlights = find_highlights(game, options);
+    let files: Vec<usize> = if options.flipped {
+        (0..8).rev().collect()
+    } else {
+        (0..8).collect()
+    };
+    let ranks: Vec<usize> = if options.flipped {
+        (0..8).collect()
+    } else {
+        (0..8).rev().collect()
+    };
+
+    let mut file_line = String::from("  ");
+    for &file in &files {
+        file_line.push_str(&format!(" {} ", (b'a' + file as u8) as char));
+    }
+    file_line.push('\n');
+
+    let mut output = String::new();
+    if options.coordinates {
+        output.push_str(&file_line);
+    }
+    for &rank in &ranks {
+        if options.coordinates {
+            output.push_str(&format!("{} ", rank + 1));
+        }
+        for &file in &files {
+            let square = file + rank * 8;
+            output.push_str(&render_square(
+                game.get_board()[square],
+                (file + rank) % 2 == 1,
+                highlights[square],
+                options,
+            ));
+        }
+        if options.coordinates {
+            output.push_str(&format!(" {}", rank + 1));
+        }
+        output.push('\n');
+    }
+    if options.coordinates {
+        output.push_str(&file_line);
+    }
+    output
+}
+
+// Draws one square, three characters wide
+fn render_square(
+    tile: Option<Piece>,
+    light: bool,
+    highlight: Highlight,
+    options: &RenderOptions,
+) -> String {
+    let symbol = match tile {
+        Some(piece) if options.unicode => piece.unicode(),
+        Some(piece) => piece.letter(),
+        None if options.unicode && !options.colors => '·',
+        None if options.colors => ' ',
+        None => '.',
+    };
+
+    if !options.colors {
+        return match highlight {
+            Highlight::None => format!(" {} ", symbol),
+            _ => format!("[{}]", symbol),
+        };
+    }
+
+    let background = match highlight {
+        Highlight::None if light => LIGHT_SQUARE,
+        Highlight::None => DARK_SQUARE,
+        Highlight::LastMove => LAST_MOVE_SQUARE,
+        Highlight::Target => TARGET_SQUARE,
+        Highlight::Check => CHECK_SQUARE,
+    };
+    let foreground = match tile {
+        Some(piece) if piece.color() == Color::White => WHITE_PIECE,
+        _ => BLACK_PIECE,
+    };
+    // Mark empty target squares so they are visible
+    let symbol = if tile.is_none() && highlight == Highlight::Target {
+        '•'
+    } else {
+        symbol
+    };
+    format!("{}{} {} {}", background, foreground, symbol, RESET)
+}
+
+fn find_highlights(game: &Game, options: &RenderOptions) -> [Highlight; 64] {
+    let mut highlights = [Highlight::None; 64];
+    let mut mark = |square: usize, highlight: Highlight| {
+        if highlight > highlights[square] {
+            highlights[square] = highlight;
+        }
+    };
+
+    if options.highlight_last_move {
+        if let Some((from, to)) = game.get_move_history().last() {
+            mark(as_coordinate(from), Highlight::LastMove);
+            mark(as_coordinate(to), Highlight::LastMove);
+        }
+    }
+    for target in options.targets.iter().filter_map(|target| parse_square(target)) {
+        mark(target, Highlight::Target);
+    }
+    if options.highlight_check {
+        let checked = match game.get_game_state() {
+            GameState::WhiteCheck | GameState::WhiteCheckMate => Some(Color::White),
+            GameState::BlackCheck | GameState::BlackCheckMate => Some(Color::Black),
+            _ => None,
+        };
+        for (square, tile) in game.get_board().iter().enumerate() {
+            if let Some(piece) = tile {
+                if piece.title() == PieceType::King && Some(piece.color()) == checked {
+                    mark(square, Highlight::Check);
+                }
+            }
+        }
+    }
+    highlights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_from_whites_side() {
+        let output = render(&Game::new(), &RenderOptions::default());
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "   a  b  c  d  e  f  g  h ");
+        assert_eq!(lines[1], "8  r  n  b  q  k  b  n  r  8");
+        assert_eq!(lines[8], "1  R  N  B  Q  K  B  N  R  1");
+        assert_eq!(lines[9], lines[0]);
+    }
+
+    #[test]
+    fn renders_from_blacks_side() {
+        let options = RenderOptions {
+            flipped: true,
+            coordinates: false,
+            ..RenderOptions::default()
+        };
+        let output = render(&Game::new(), &options);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], " R  N  B  K  Q  B  N  R ");
+        assert_eq!(lines[7], " r  n  b  k  q  b  n  r ");
+    }
+
+    #[test]
+    fn renders_unicode_glyphs() {
+        let options = RenderOptions {
+            unicode: true,
+            coordinates: false,
+            ..RenderOptions::default()
+        };
+        let output = render(&Game::new(), &options);
+        assert!(output.starts_with(" ♜  ♞  ♝  ♛  ♚  ♝  ♞  ♜ \n"));
+        assert!(output.contains(" ·  ·  · "));
+    }
+
+    #[test]
+    fn highlights_last_move_and_targets() {
+        let mut game = Game::new();
+        game.make_move(String::from("E2"), String::from("E4"));
+        let options = RenderOptions {
+            coordinates: false,
+            targets: vec![String::from("F6"), String::from("z9")],
+            ..RenderOptions::default()
+        };
+        let lines: Vec<String> = render(&game, &options).lines().map(String::from).collect();
+        assert_eq!(lines[2], " .  .  .  .  . [.] .  . ");
+        assert_eq!(lines[4], " .  .  .  . [P] .  .  . ");
+        assert_eq!(lines[6], " P  P  P  P [.] P  P  P ");
+    }
+
+    #[test]
+    fn highlights_checked_king_in_colour() {
+        let mut game = Game::new();
+        game.make_move(String::from("F2"), String::from("F3"));
+        game.make_move(String::from("E7"), String::from("E5"));
+        game.make_move(String::from("G2"), String::from("G4"));
+        game.make_move(String::from("D8"), String::from("H4"));
+        let options = RenderOptions {
+            colors: true,
+            ..RenderOptions::default()
+        };
+        let output = render(&game, &options);
+        assert!(output.contains(&format!("{}{} K {}", CHECK_SQUARE, WHITE_PIECE, RESET)));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::game::*;
+use crate::notation::*;
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// Writes a game to a file as PGN, with its starting position, moves and
+/// the given tags. The file is replaced in one step, so a crash while
+/// saving leaves the previous save intact. Tags that can't be read back,
+/// like names that aren't PGN identifiers, values with newlines or a
+/// `Result` that isn't a PGN result, are refused with `InvalidInput`
+/// before anything is written.
+pub fn save_game<P: AsRef<Path>>(
+    path: P,
+    game: &Game,
+    tags: &[(String, String)],
+) -> io::Result<()> {
+    for (name, value) in tags {
+        check_tag(name, value)?;
+    }
+    write_atomically(path.as_ref(), &to_pgn(game, tags))
+}
+
+// Checks that a tag is written to PGN as it is
+fn check_tag(name: &str, value: &str) -> io::Result<()> {
+    let message = if !is_tag_name(name) {
+        "tag names must be a letter followed by letters, digits and underscores"
+    } else if value.chars().any(char::is_control) {
+        "tag values can't contain control characters"
+    } else if name == "Result" && !PGN_RESULTS.contains(&value) {
+        "the result must be 1-0, 0-1, 1/2-1/2 or *"
+    } else {
+        return Ok(());
+    };
+    Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+// Writes a file through a temporary file next to it, so the old contents
+// stay intact until the new ones are complete
+pub(crate) fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)
+}
+
+/// Reads a game saved with `save_game`, or any PGN file, and returns it
+/// with its tags. The moves are replayed, so the game continues with the
+/// same position, clocks and history as when it was saved.
+pub fn load_game<P: AsRef<Path>>(path: P) -> io::Result<(Game, Vec<(String, String)>)> {
+    let pgn = fs::read_to_string(path)?;
+    parse_pgn(&pgn).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid PGN"))
+}
+
+/// A game that is kept in a file. With autosave, which is on by default,
+/// the file is written after every move and undo. All `Game` functions
+/// that don't change the game can be called on it directly.
+#[derive(Clone)]
+pub struct SavedGame {
+    game: Game,
+    tags: Vec<(String, String)>,
+    path: PathBuf,
+    autosave: bool,
+}
+
+impl SavedGame {
+    /// Keeps a game in the given file and saves it right away.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        game: Game,
+        tags: Vec<(String, String)>,
+    ) -> io::Result<SavedGame> {
+        let saved = SavedGame {
+            game,
+            tags,
+            path: path.as_ref().to_path_buf(),
+            autosave: true,
+        };
+        saved.save()?;
+        Ok(saved)
+    }
+
+    /// Continues a game from a file. A `Result` tag is only kept if the
+    /// game ended in another way than on the board, like a resignation.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<SavedGame> {
+        let (game, mut tags) = load_game(&path)?;
+        tags.retain(|(name, value)| name != "Result" || value != game_result(&game));
+        Ok(SavedGame {
+            game,
+            tags,
+            path: path.as_ref().to_path_buf(),
+            autosave: true,
+        })
+    }
+
+    /// Turns saving after every move and undo on or off.
+    pub fn set_autosave(&mut self, autosave: bool) {
+        self.autosave = autosave;
+    }
+
+    /// Get the game.
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Get the tags written with the game.
+    pub fn tags(&self) -> &[(String, String)] {
+        &self.tags
+    }
+
+    /// Sets a tag, replacing any tag with the same name. It's written with
+    /// the next save. Tags that `save_game` would refuse aren't set.
+    pub fn set_tag(&mut self, name: &str, value: &str) -> io::Result<()> {
+        check_tag(name, value)?;
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Makes a move like `Game::make_move` and saves the game if autosave
+    /// is on and the move was made.
+    pub fn make_move(&mut self, from: String, to: String) -> io::Result<Option<GameState>> {
+        let state = self.game.make_move(from, to);
+        if state.is_some() && self.autosave {
+            self.save()?;
+        }
+        Ok(state)
+    }
+
+    /// Takes back a move like `Game::undo_move` and saves the game if
+    /// autosave is on and a move was taken back.
+    pub fn undo_move(&mut self) -> io::Result<Option<GameState>> {
+        let state = self.game.undo_move();
+        if state.is_some() && self.autosave {
+            self.save()?;
+        }
+        Ok(state)
+    }
+
+    /// Writes the game to its file.
+    pub fn save(&self) -> io::Result<()> {
+        save_game(&self.path, &self.game, &self.tags)
+    }
+}
+
+impl Deref for SavedGame {
+    type Target = Game;
+
+    fn deref(&self) -> &Game {
+        &self.game
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn temporary_file(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("{}-{}.pgn", name, std::process::id()))
+    }
+
+    #[test]
+    fn saves_and_loads_games() {
+        let path = temporary_file("save-test");
+        let mut game = Game::from_fen("r3k3/8/8/8/8/8/4P3/4K2R w Kq - 3 20").unwrap();
+        game.make_move(String::from("E1"), String::from("G1"));
+        game.make_move(String::from("E8"), String::from("C8"));
+        let tags = vec![(String::from("White"), String::from("Ada"))];
+        save_game(&path, &game, &tags).unwrap();
+
+        let (loaded, loaded_tags) = load_game(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.to_fen(), game.to_fen());
+        assert_eq!(loaded.get_starting_fen(), game.get_starting_fen());
+        assert_eq!(loaded.get_move_history(), game.get_move_history());
+        assert!(loaded_tags.contains(&tags[0]));
+
+        // Saving a loaded game doesn't repeat the setup tags
+        let pgn = to_pgn(&loaded, &loaded_tags);
+        assert_eq!(pgn.matches("[FEN ").count(), 1);
+    }
+
+    #[test]
+    fn autosaves_after_moves() {
+        let path = temporary_file("autosave-test");
+        let mut saved = SavedGame::create(&path, Game::new(), Vec::new()).unwrap();
+        saved.set_tag("Event", "Club night").unwrap();
+        saved
+            .make_move(String::from("E2"), String::from("E4"))
+            .unwrap();
+        assert_eq!(
+            saved
+                .make_move(String::from("E2"), String::from("E4"))
+                .unwrap(),
+            None
+        );
+
+        let mut reopened = SavedGame::open(&path).unwrap();
+        assert_eq!(reopened.to_fen(), saved.to_fen());
+        assert!(!reopened.tags().iter().any(|(name, _)| name == "Result"));
+        assert!(reopened
+            .tags()
+            .contains(&(String::from("Event"), String::from("Club night"))));
+
+        reopened.set_autosave(false);
+        reopened
+            .make_move(String::from("E7"), String::from("E5"))
+            .unwrap();
+        assert_eq!(load_game(&path).unwrap().0.get_move_history().len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn refuses_invalid_files() {
+        let path = temporary_file("invalid-save-test");
+        fs::write(&path, "1. e4 e4").unwrap();
+        let error = load_game(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(load_game(&path).is_err());
+    }
+
+    #[test]
+    fn refuses_tags_that_cant_be_read_back() {
+        let path = temporary_file("invalid-tags-test");
+        let game = Game::new();
+        for (name, value) in [
+            ("Event", "Club\nnight"),
+            ("Bad name", "x"),
+            ("Result", "won on time"),
+        ] {
+            let tags = vec![(name.to_string(), value.to_string())];
+            let error = save_game(&path, &game, &tags).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert!(!path.exists());
+        }
+
+        let mut saved = SavedGame::create(&path, game, Vec::new()).unwrap();
+        assert!(saved.set_tag("Result", "1-0").is_ok());
+        assert!(saved.set_tag("Result", "2-0").is_err());
+        assert!(saved.set_tag("Site", "a\r\nb").is_err());
+        assert_eq!(
+            saved.tags().to_vec(),
+            vec![(String::from("Result"), String::from("1-0"))]
+        );
+        saved.save().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
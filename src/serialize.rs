@@ -0,0 +1,171 @@
+use crate::builder::*;
+use crate::game::*;
+use crate::piece::*;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The version of the schema a `Game` is serialized with. It's raised
+/// whenever the schema changes in a way older readers can't handle, and
+/// games with a newer version are refused.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// The serialized form of a game. The starting position and the moves are
+// enough to restore it with its history, the other fields describe the
+// current position for readers that don't replay the game
+#[derive(Serialize, Deserialize)]
+struct GameRecord {
+    version: u32,
+    starting_fen: String,
+    chess960: bool,
+    moves: Vec<MoveRecord>,
+    fen: String,
+    active_color: Color,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    state: GameState,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MoveRecord {
+    from: String,
+    to: String,
+}
+
+impl Serialize for Game {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        GameRecord {
+            version: SCHEMA_VERSION,
+            starting_fen: self.get_starting_fen(),
+            chess960: self.is_chess960(),
+            moves: self
+                .get_move_history()
+                .into_iter()
+                .map(|(from, to)| MoveRecord { from, to })
+                .collect(),
+            fen: self.to_fen(),
+            active_color: self.get_active_color(),
+            halfmove_clock: self.get_halfmove_clock(),
+            fullmove_number: self.get_fullmove_number(),
+            state: self.get_game_state(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Game {
+    // Replays the moves from the starting position, so the game can be
+    // continued and undone as before it was saved
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Game, D::Error> {
+        let record = GameRecord::deserialize(deserializer)?;
+        if record.version > SCHEMA_VERSION {
+            return Err(D::Error::custom(format!(
+                "unsupported game schema version {}",
+                record.version
+            )));
+        }
+        let mut game = GameBuilder::from_fen(&record.starting_fen)
+            .and_then(|builder| builder.chess960(record.chess960).build().ok())
+            .ok_or_else(|| D::Error::custom("invalid starting position"))?;
+        for MoveRecord { from, to } in record.moves {
+            if game.make_move(from.clone(), to.clone()).is_none() {
+                return Err(D::Error::custom(format!("illegal move {}-{}", from, to)));
+            }
+        }
+        if game.to_fen() != record.fen {
+            return Err(D::Error::custom(
+                "the moves don't lead to the saved position",
+            ));
+        }
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn round_trip(game: &Game) -> Game {
+        serde_json::from_str(&serde_json::to_string(game).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn writes_the_versioned_schema() {
+        let mut game = Game::new();
+        game.make_move(String::from("E2"), String::from("E4"));
+        let value = serde_json::to_value(&game).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "version": 1,
+                "starting_fen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "chess960": false,
+                "moves": [{ "from": "E2", "to": "E4" }],
+                "fen": "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                "active_color": "Black",
+                "halfmove_clock": 0,
+                "fullmove_number": 1,
+                "state": "InProgress",
+            })
+        );
+    }
+
+    #[test]
+    fn round_trips_games() {
+        let mut game = Game::new();
+        for (from, to) in &[("F2", "F3"), ("E7", "E5"), ("G2", "G4"), ("D8", "H4")] {
+            game.make_move(from.to_string(), to.to_string());
+        }
+        let mut restored = round_trip(&game);
+        assert_eq!(restored.to_fen(), game.to_fen());
+        assert_eq!(restored.get_move_history(), game.get_move_history());
+        assert_eq!(restored.get_game_state(), GameState::WhiteCheckMate);
+        restored.undo_move();
+        assert_eq!(restored.get_game_state(), GameState::InProgress);
+
+        let game = Game::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 12 40").unwrap();
+        assert_eq!(round_trip(&game).to_fen(), game.to_fen());
+
+        let mut game = Game::new_chess960(0).unwrap();
+        game.make_move(String::from("E2"), String::from("E4"));
+        let restored = round_trip(&game);
+        assert!(restored.is_chess960());
+        assert_eq!(restored.get_starting_fen(), game.get_starting_fen());
+    }
+
+    #[test]
+    fn round_trips_pieces_and_states() {
+        let piece = Piece::new(Color::White, PieceType::Knight, true);
+        let json = serde_json::to_string(&piece).unwrap();
+        assert_eq!(
+            json,
+            r#"{"color":"White","title":"Knight","has_moved":true}"#
+        );
+        assert_eq!(serde_json::from_str::<Piece>(&json).unwrap(), piece);
+        assert_eq!(
+            serde_json::to_string(&GameState::BlackCheckMate).unwrap(),
+            r#""BlackCheckMate""#
+        );
+        let rights = CastlingRights::standard();
+        let json = serde_json::to_string(&rights).unwrap();
+        assert_eq!(
+            serde_json::from_str::<CastlingRights>(&json).unwrap(),
+            rights
+        );
+    }
+
+    #[test]
+    fn refuses_broken_games() {
+        let mut value = serde_json::to_value(Game::new()).unwrap();
+        value["version"] = json!(2);
+        assert!(serde_json::from_value::<Game>(value).is_err());
+
+        let mut value = serde_json::to_value(Game::new()).unwrap();
+        value["moves"] = json!([{ "from": "E2", "to": "E5" }]);
+        assert!(serde_json::from_value::<Game>(value).is_err());
+
+        let mut value = serde_json::to_value(Game::new()).unwrap();
+        value["fen"] = json!("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert!(serde_json::from_value::<Game>(value).is_err());
+    }
+}
@@ -0,0 +1,1219 @@
+use crate::game::*;
+use crate::notation::*;
+use crate::piece::*;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often the clocks are checked for flags
+const TICK: Duration = Duration::from_millis(100);
+// The longest line or WebSocket message a client may send, in bytes
+const MAX_MESSAGE: usize = 64 * 1024;
+// The longest clock time or increment a game can be created with, a day
+const MAX_CLOCK_SECONDS: u64 = 24 * 60 * 60;
+
+/// The time each player has for the whole game, and the time added after
+/// each of their moves.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeControl {
+    pub initial_seconds: u64,
+    #[serde(default)]
+    pub increment_seconds: u64,
+}
+
+/// A message from a client. Each is a JSON object with a `type` field,
+/// e.g. `{"type":"move","game_id":1,"from":"E2","to":"E4"}`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Creates a game and takes a seat in it, white if no colour is given.
+    Create {
+        name: String,
+        #[serde(default)]
+        color: Option<Color>,
+        #[serde(default)]
+        time_control: Option<TimeControl>,
+    },
+    /// Takes the free seat of a game, or watches it as a spectator.
+    Join {
+        game_id: u64,
+        name: String,
+        #[serde(default)]
+        spectate: bool,
+    },
+    /// Takes back a seat after a lost connection with the token it was
+    /// given.
+    Reconnect { game_id: u64, token: String },
+    /// Makes a move in a game where it's the client's turn.
+    Move {
+        game_id: u64,
+        from: String,
+        to: String,
+    },
+    /// Resigns a game the client plays in.
+    Resign { game_id: u64 },
+    /// Lists the games on the server.
+    List,
+}
+
+/// A message from the server, written as JSON with a `type` field.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// The client has a seat in a game, or watches it if `color` is None.
+    /// The token is needed to reconnect to the seat.
+    Joined {
+        game_id: u64,
+        color: Option<Color>,
+        token: Option<String>,
+    },
+    /// The position of a game after it changed, sent to its players and
+    /// spectators. Clocks are in milliseconds.
+    Update {
+        game_id: u64,
+        fen: String,
+        state: GameState,
+        active_color: Color,
+        last_move: Option<String>,
+        white: Option<String>,
+        black: Option<String>,
+        white_clock: Option<u64>,
+        black_clock: Option<u64>,
+        result: Option<String>,
+        reason: Option<String>,
+    },
+    /// A player lost or regained their connection.
+    Presence {
+        game_id: u64,
+        color: Color,
+        connected: bool,
+    },
+    /// The games on the server.
+    Games { games: Vec<GameSummary> },
+    /// A message was refused.
+    Error { message: String },
+}
+
+/// A short description of a game in the list of games.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameSummary {
+    pub game_id: u64,
+    pub white: Option<String>,
+    pub black: Option<String>,
+    pub spectators: usize,
+    pub time_control: Option<TimeControl>,
+    pub result: Option<String>,
+}
+
+// A player's place in a game, kept while they are disconnected
+struct Seat {
+    name: String,
+    token: String,
+    client: Option<usize>,
+}
+
+// The time left for each colour and when the side to move started thinking
+struct Clock {
+    control: TimeControl,
+    remaining: [Duration; 2],
+    started: Option<Instant>,
+}
+
+impl Clock {
+    fn new(control: TimeControl) -> Clock {
+        let initial = Duration::from_secs(control.initial_seconds);
+        Clock {
+            control,
+            remaining: [initial, initial],
+            started: None,
+        }
+    }
+
+    fn remaining(&self, color: Color, active_color: Color, now: Instant) -> Duration {
+        let remaining = self.remaining[color_index(color)];
+        match self.started {
+            Some(started) if color == active_color => {
+                remaining.saturating_sub(now.saturating_duration_since(started))
+            }
+            _ => remaining,
+        }
+    }
+
+    // Stops the clock of the side that moved and starts the other one.
+    // Returns false if the side had no time left
+    fn press(&mut self, color: Color, now: Instant) -> bool {
+        let left = self.remaining(color, color, now);
+        if left == Duration::from_secs(0) {
+            return false;
+        }
+        self.remaining[color_index(color)] =
+            left.saturating_add(Duration::from_secs(self.control.increment_seconds));
+        self.started = Some(now);
+        true
+    }
+}
+
+struct HostedGame {
+    game: Game,
+    white: Option<Seat>,
+    black: Option<Seat>,
+    spectators: Vec<usize>,
+    clock: Option<Clock>,
+    last_move: Option<String>,
+    // Set when the game ends, also by resignation or on time
+    result: Option<(String, String)>,
+}
+
+impl HostedGame {
+    fn seat(&self, color: Color) -> &Option<Seat> {
+        match color {
+            Color::White => &self.white,
+            Color::Black => &self.black,
+        }
+    }
+
+    fn seat_mut(&mut self, color: Color) -> &mut Option<Seat> {
+        match color {
+            Color::White => &mut self.white,
+            Color::Black => &mut self.black,
+        }
+    }
+
+    // The colour a client plays in the game
+    fn color_of(&self, client: usize) -> Option<Color> {
+        [Color::White, Color::Black].iter().copied().find(|&color| {
+            self.seat(color)
+                .as_ref()
+                .is_some_and(|seat| seat.client == Some(client))
+        })
+    }
+
+    // The connected players and spectators
+    fn audience(&self) -> Vec<usize> {
+        let mut clients: Vec<usize> = [&self.white, &self.black]
+            .iter()
+            .filter_map(|seat| seat.as_ref().and_then(|seat| seat.client))
+            .collect();
+        clients.extend(&self.spectators);
+        clients
+    }
+
+    fn update(&self, game_id: u64, now: Instant) -> ServerMessage {
+        let active_color = self.game.get_active_color();
+        let clock = |color| {
+            self.clock
+                .as_ref()
+                .map(|clock| clock.remaining(color, active_color, now).as_millis() as u64)
+        };
+        let name = |seat: &Option<Seat>| seat.as_ref().map(|seat| seat.name.clone());
+        ServerMessage::Update {
+            game_id,
+            fen: self.game.to_fen(),
+            state: self.game.get_game_state(),
+            active_color,
+            last_move: self.last_move.clone(),
+            white: name(&self.white),
+            black: name(&self.black),
+            white_clock: clock(Color::White),
+            black_clock: clock(Color::Black),
+            result: self.result.as_ref().map(|(result, _)| result.clone()),
+            reason: self.result.as_ref().map(|(_, reason)| reason.clone()),
+        }
+    }
+
+    // Ends the game with a loss for a colour
+    fn lose(&mut self, color: Color, reason: &str) {
+        let result = match color {
+            Color::White => "0-1",
+            Color::Black => "1-0",
+        };
+        self.result = Some((result.to_string(), reason.to_string()));
+        if let Some(clock) = self.clock.as_mut() {
+            clock.started = None;
+        }
+    }
+}
+
+/// The games hosted by a server and the clients connected to it. Every
+/// message a client sends is passed to `handle`, and the replies are sent
+/// to the channels the clients connected with, so the lobby can be run
+/// without a network.
+pub struct Lobby {
+    games: BTreeMap<u64, HostedGame>,
+    clients: HashMap<usize, Sender<ServerMessage>>,
+    next_game: u64,
+    next_client: usize,
+    // Tokens are hashes of a counter with keys that are random per lobby
+    random: RandomState,
+    tokens: u64,
+}
+
+impl Default for Lobby {
+    fn default() -> Self {
+        Lobby::new()
+    }
+}
+
+impl Lobby {
+    /// Creates a lobby without games.
+    pub fn new() -> Lobby {
+        Lobby {
+            games: BTreeMap::new(),
+            clients: HashMap::new(),
+            next_game: 1,
+            next_client: 0,
+            random: RandomState::new(),
+            tokens: 0,
+        }
+    }
+
+    /// Adds a client that receives its messages on the given channel and
+    /// returns its id.
+    pub fn connect(&mut self, sender: Sender<ServerMessage>) -> usize {
+        let client = self.next_client;
+        self.next_client += 1;
+        self.clients.insert(client, sender);
+        client
+    }
+
+    /// Removes a client. Its seats are kept for a reconnection and the
+    /// opponents are told it's gone.
+    pub fn disconnect(&mut self, client: usize) {
+        self.clients.remove(&client);
+        let mut presence = Vec::new();
+        for (&game_id, hosted) in self.games.iter_mut() {
+            hosted.spectators.retain(|&other| other != client);
+            if let Some(color) = hosted.color_of(client) {
+                hosted.seat_mut(color).as_mut().unwrap().client = None;
+                presence.push((game_id, color));
+            }
+        }
+        for (game_id, color) in presence {
+            self.broadcast(
+                game_id,
+                ServerMessage::Presence {
+                    game_id,
+                    color,
+                    connected: false,
+                },
+            );
+        }
+    }
+
+    /// Handles a message from a client at the given time.
+    pub fn handle(&mut self, client: usize, message: ClientMessage, now: Instant) {
+        let result = match message {
+            ClientMessage::Create {
+                name,
+                color,
+                time_control,
+            } => self.create(
+                client,
+                name,
+                color.unwrap_or(Color::White),
+                time_control,
+                now,
+            ),
+            ClientMessage::Join {
+                game_id,
+                name,
+                spectate,
+            } => self.join(client, game_id, name, spectate, now),
+            ClientMessage::Reconnect { game_id, token } => {
+                self.reconnect(client, game_id, &token, now)
+            }
+            ClientMessage::Move { game_id, from, to } => {
+                self.make_move(client, game_id, from, to, now)
+            }
+            ClientMessage::Resign { game_id } => self.resign(client, game_id, now),
+            ClientMessage::List => {
+                let games = self.summaries();
+                self.send(client, ServerMessage::Games { games });
+                Ok(())
+            }
+        };
+        if let Err(message) = result {
+            self.send(
+                client,
+                ServerMessage::Error {
+                    message: message.to_string(),
+                },
+            );
+        }
+    }
+
+    /// Ends the games where the side to move has run out of time.
+    pub fn tick(&mut self, now: Instant) {
+        let mut flagged = Vec::new();
+        for (&game_id, hosted) in self.games.iter_mut() {
+            if hosted.result.is_some() {
+                continue;
+            }
+            let active_color = hosted.game.get_active_color();
+            let is_out_of_time = hosted.clock.as_ref().is_some_and(|clock| {
+                clock.started.is_some()
+                    && clock.remaining(active_color, active_color, now) == Duration::from_secs(0)
+            });
+            if is_out_of_time {
+                hosted.lose(active_color, "time");
+                flagged.push(game_id);
+            }
+        }
+        for game_id in flagged {
+            self.send_update(game_id, now);
+        }
+    }
+
+    /// A summary of every game on the server, by id.
+    pub fn summaries(&self) -> Vec<GameSummary> {
+        let name = |seat: &Option<Seat>| seat.as_ref().map(|seat| seat.name.clone());
+        self.games
+            .iter()
+            .map(|(&game_id, hosted)| GameSummary {
+                game_id,
+                white: name(&hosted.white),
+                black: name(&hosted.black),
+                spectators: hosted.spectators.len(),
+                time_control: hosted.clock.as_ref().map(|clock| clock.control),
+                result: hosted.result.as_ref().map(|(result, _)| result.clone()),
+            })
+            .collect()
+    }
+
+    fn create(
+        &mut self,
+        client: usize,
+        name: String,
+        color: Color,
+        time_control: Option<TimeControl>,
+        now: Instant,
+    ) -> Result<(), &'static str> {
+        if time_control.is_some_and(|control| {
+            control.initial_seconds > MAX_CLOCK_SECONDS
+                || control.increment_seconds > MAX_CLOCK_SECONDS
+        }) {
+            return Err("the time control is out of range");
+        }
+        let game_id = self.next_game;
+        self.next_game += 1;
+        let token = self.new_token();
+        let mut hosted = HostedGame {
+            game: Game::new(),
+            white: None,
+            black: None,
+            spectators: Vec::new(),
+            clock: time_control.map(Clock::new),
+            last_move: None,
+            result: None,
+        };
+        *hosted.seat_mut(color) = Some(Seat {
+            name,
+            token: token.clone(),
+            client: Some(client),
+        });
+        self.games.insert(game_id, hosted);
+        self.send(
+            client,
+            ServerMessage::Joined {
+                game_id,
+                color: Some(color),
+                token: Some(token),
+            },
+        );
+        self.send_update(game_id, now);
+        Ok(())
+    }
+
+    fn join(
+        &mut self,
+        client: usize,
+        game_id: u64,
+        name: String,
+        spectate: bool,
+        now: Instant,
+    ) -> Result<(), &'static str> {
+        let token = self.new_token();
+        let hosted = self.games.get_mut(&game_id).ok_or("no such game")?;
+        if !spectate && hosted.color_of(client).is_some() {
+            return Err("you already play in this game");
+        }
+        let free = [Color::White, Color::Black]
+            .iter()
+            .copied()
+            .find(|&color| hosted.seat(color).is_none());
+        let joined = match free {
+            Some(color) if !spectate => {
+                *hosted.seat_mut(color) = Some(Seat {
+                    name,
+                    token: token.clone(),
+                    client: Some(client),
+                });
+                // The clock starts when both players are seated
+                if let Some(clock) = hosted.clock.as_mut() {
+                    clock.started = Some(now);
+                }
+                ServerMessage::Joined {
+                    game_id,
+                    color: Some(color),
+                    token: Some(token),
+                }
+            }
+            None if !spectate => return Err("the game is full"),
+            _ => {
+                if !hosted.spectators.contains(&client) {
+                    hosted.spectators.push(client);
+                }
+                ServerMessage::Joined {
+                    game_id,
+                    color: None,
+                    token: None,
+                }
+            }
+        };
+        self.send(client, joined);
+        self.send_update(game_id, now);
+        Ok(())
+    }
+
+    fn reconnect(
+        &mut self,
+        client: usize,
+        game_id: u64,
+        token: &str,
+        now: Instant,
+    ) -> Result<(), &'static str> {
+        let hosted = self.games.get_mut(&game_id).ok_or("no such game")?;
+        let color = [Color::White, Color::Black]
+            .iter()
+            .copied()
+            .find(|&color| {
+                hosted
+                    .seat(color)
+                    .as_ref()
+                    .is_some_and(|seat| seat.token == token)
+            })
+            .ok_or("invalid token")?;
+        hosted.seat_mut(color).as_mut().unwrap().client = Some(client);
+        self.send(
+            client,
+            ServerMessage::Joined {
+                game_id,
+                color: Some(color),
+                token: Some(token.to_string()),
+            },
+        );
+        self.broadcast(
+            game_id,
+            ServerMessage::Presence {
+                game_id,
+                color,
+                connected: true,
+            },
+        );
+        self.send_update(game_id, now);
+        Ok(())
+    }
+
+    fn make_move(
+        &mut self,
+        client: usize,
+        game_id: u64,
+        from: String,
+        to: String,
+        now: Instant,
+    ) -> Result<(), &'static str> {
+        let hosted = self.games.get_mut(&game_id).ok_or("no such game")?;
+        let color = hosted
+            .color_of(client)
+            .ok_or("you don't play in this game")?;
+        if hosted.result.is_some() {
+            return Err("the game is over");
+        }
+        if hosted.white.is_none() || hosted.black.is_none() {
+            return Err("waiting for an opponent");
+        }
+        if color != hosted.game.get_active_color() {
+            return Err("it's not your turn");
+        }
+        let (from, to) = (from.to_uppercase(), to.to_uppercase());
+        let san = to_san(&hosted.game, &from, &to).ok_or("illegal move")?;
+        if let Some(clock) = hosted.clock.as_mut() {
+            if !clock.press(color, now) {
+                hosted.lose(color, "time");
+                self.send_update(game_id, now);
+                return Err("out of time");
+            }
+        }
+        hosted.game.make_move(from, to);
+        hosted.last_move = Some(san);
+        if hosted.game.is_game_over() {
+            let reason = if hosted.game.get_game_state() == GameState::Tie {
+                "stalemate"
+            } else {
+                "checkmate"
+            };
+            hosted.result = Some((game_result(&hosted.game).to_string(), reason.to_string()));
+            if let Some(clock) = hosted.clock.as_mut() {
+                clock.started = None;
+            }
+        }
+        self.send_update(game_id, now);
+        Ok(())
+    }
+
+    fn resign(&mut self, client: usize, game_id: u64, now: Instant) -> Result<(), &'static str> {
+        let hosted = self.games.get_mut(&game_id).ok_or("no such game")?;
+        let color = hosted
+            .color_of(client)
+            .ok_or("you don't play in this game")?;
+        if hosted.result.is_some() {
+            return Err("the game is over");
+        }
+        hosted.lose(color, "resignation");
+        self.send_update(game_id, now);
+        Ok(())
+    }
+
+    fn new_token(&mut self) -> String {
+        self.tokens += 1;
+        let mut hasher = self.random.build_hasher();
+        hasher.write_u64(self.tokens);
+        format!("{:016x}", hasher.finish())
+    }
+
+    fn send_update(&self, game_id: u64, now: Instant) {
+        if let Some(hosted) = self.games.get(&game_id) {
+            self.broadcast(game_id, hosted.update(game_id, now));
+        }
+    }
+
+    fn broadcast(&self, game_id: u64, message: ServerMessage) {
+        if let Some(hosted) = self.games.get(&game_id) {
+            for client in hosted.audience() {
+                self.send(client, message.clone());
+            }
+        }
+    }
+
+    // Clients that have gone away are removed by their connection
+    fn send(&self, client: usize, message: ServerMessage) {
+        if let Some(sender) = self.clients.get(&client) {
+            let _ = sender.send(message);
+        }
+    }
+}
+
+/// Serves clients on a listener until it fails. Clients either send one
+/// JSON message per line, or open a WebSocket connection and send one
+/// message per text frame. Replies are sent the same way.
+pub fn serve(listener: TcpListener) -> io::Result<()> {
+    let lobby = Arc::new(Mutex::new(Lobby::new()));
+    let ticking = Arc::clone(&lobby);
+    thread::spawn(move || loop {
+        thread::sleep(TICK);
+        ticking.lock().unwrap().tick(Instant::now());
+    });
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let lobby = Arc::clone(&lobby);
+        thread::spawn(move || {
+            let _ = handle_connection(stream, lobby);
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, lobby: Arc<Mutex<Lobby>>) -> io::Result<()> {
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    let mut reader = BufReader::new(stream);
+    let mut first = String::new();
+    if read_line(&mut reader, &mut first)? == 0 {
+        return Ok(());
+    }
+    let websocket = first.starts_with("GET ");
+    if websocket {
+        accept_websocket(&mut reader, &mut *writer.lock().unwrap())?;
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let client = lobby.lock().unwrap().connect(sender.clone());
+    let sending = Arc::clone(&writer);
+    let send_thread = thread::spawn(move || {
+        for message in receiver {
+            let json = serde_json::to_string(&message).unwrap();
+            let mut stream = sending.lock().unwrap();
+            let written = if websocket {
+                write_frame(&mut *stream, 1, json.as_bytes())
+            } else {
+                writeln!(stream, "{}", json)
+            };
+            if written.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut line = if websocket { None } else { Some(first) };
+    loop {
+        let text = match line.take() {
+            Some(text) => text,
+            None if websocket => match read_message(&mut reader, &writer)? {
+                Some(text) => text,
+                None => break,
+            },
+            None => {
+                let mut text = String::new();
+                if read_line(&mut reader, &mut text)? == 0 {
+                    break;
+                }
+                text
+            }
+        };
+        if text.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&text) {
+            Ok(message) => lobby
+                .lock()
+                .unwrap()
+                .handle(client, message, Instant::now()),
+            Err(error) => {
+                let _ = sender.send(ServerMessage::Error {
+                    message: format!("invalid message: {}", error),
+                });
+            }
+        }
+    }
+
+    lobby.lock().unwrap().disconnect(client);
+    drop(sender);
+    let _ = send_thread.join();
+    Ok(())
+}
+
+// Reads a line of at most MAX_MESSAGE bytes, failing on longer ones
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> io::Result<usize> {
+    let read = reader
+        .by_ref()
+        .take(MAX_MESSAGE as u64 + 1)
+        .read_line(line)?;
+    if read > MAX_MESSAGE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+    }
+    Ok(read)
+}
+
+// Reads the rest of a WebSocket opening request and accepts it
+fn accept_websocket<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<()> {
+    let mut key = None;
+    loop {
+        let mut header = String::new();
+        if read_line(reader, &mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let key = key.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no WebSocket key"))?;
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        websocket_accept(&key)
+    )
+}
+
+// The accept header for a key, defined by RFC 6455
+fn websocket_accept(key: &str) -> String {
+    let mut input = key.as_bytes().to_vec();
+    input.extend_from_slice(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+    base64(&sha1(&input))
+}
+
+// Reads frames until a whole text message arrives, answering pings.
+// Returns None when the connection is closed. Messages longer than
+// MAX_MESSAGE bytes close the connection
+fn read_message<R: Read>(reader: &mut R, writer: &Mutex<TcpStream>) -> io::Result<Option<String>> {
+    let mut message = Vec::new();
+    loop {
+        let mut header = [0; 2];
+        if reader.read_exact(&mut header).is_err() {
+            return Ok(None);
+        }
+        let is_final = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0f;
+        let length = match header[1] & 0x7f {
+            126 => {
+                let mut bytes = [0; 2];
+                reader.read_exact(&mut bytes)?;
+                u16::from_be_bytes(bytes) as u64
+            }
+            127 => {
+                let mut bytes = [0; 8];
+                reader.read_exact(&mut bytes)?;
+                u64::from_be_bytes(bytes)
+            }
+            length => length as u64,
+        };
+        if length > (MAX_MESSAGE - message.len()) as u64 {
+            // The status code 1009 means the message is too big
+            let _ = write_frame(&mut *writer.lock().unwrap(), 8, &1009u16.to_be_bytes());
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message too long",
+            ));
+        }
+        let mut mask = [0; 4];
+        if header[1] & 0x80 != 0 {
+            reader.read_exact(&mut mask)?;
+        }
+        let mut payload = Vec::new();
+        reader.take(length).read_to_end(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        match opcode {
+            0 | 1 => {
+                message.extend(payload);
+                if is_final {
+                    return String::from_utf8(message)
+                        .map(Some)
+                        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid UTF-8"));
+                }
+            }
+            8 => {
+                let _ = write_frame(&mut *writer.lock().unwrap(), 8, &[]);
+                return Ok(None);
+            }
+            9 => write_frame(&mut *writer.lock().unwrap(), 10, &payload)?,
+            _ => (),
+        }
+    }
+}
+
+// Writes an unmasked frame, as servers do
+fn write_frame<W: Write>(writer: &mut W, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for i in 0..16 {
+            words[i] = u32::from_be_bytes([
+                block[i * 4],
+                block[i * 4 + 1],
+                block[i * 4 + 2],
+                block[i * 4 + 3],
+            ]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, new) in state.iter_mut().zip(&[a, b, c, d, e]) {
+            *value = value.wrapping_add(*new);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (i, value) in state.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::Receiver;
+
+    fn client(lobby: &mut Lobby) -> (usize, Receiver<ServerMessage>) {
+        let (sender, receiver) = mpsc::channel();
+        (lobby.connect(sender), receiver)
+    }
+
+    fn move_message(game_id: u64, from: &str, to: &str) -> ClientMessage {
+        ClientMessage::Move {
+            game_id,
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    fn join_message(game_id: u64, name: &str, spectate: bool) -> ClientMessage {
+        ClientMessage::Join {
+            game_id,
+            name: name.to_string(),
+            spectate,
+        }
+    }
+
+    fn last_update(receiver: &Receiver<ServerMessage>) -> Option<ServerMessage> {
+        receiver
+            .try_iter()
+            .filter(|message| matches!(message, ServerMessage::Update { .. }))
+            .last()
+    }
+
+    fn token_of(receiver: &Receiver<ServerMessage>) -> String {
+        receiver
+            .try_iter()
+            .find_map(|message| match message {
+                ServerMessage::Joined { token, .. } => token,
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn enforces_turn_order_and_updates_spectators() {
+        let mut lobby = Lobby::new();
+        let now = Instant::now();
+        let (white, white_messages) = client(&mut lobby);
+        let (black, black_messages) = client(&mut lobby);
+        let (spectator, spectator_messages) = client(&mut lobby);
+        lobby.handle(
+            white,
+            ClientMessage::Create {
+                name: String::from("Ada"),
+                color: None,
+                time_control: None,
+            },
+            now,
+        );
+        lobby.handle(white, move_message(1, "E2", "E4"), now);
+        assert!(white_messages.try_iter().any(|message| message
+            == ServerMessage::Error {
+                message: String::from("waiting for an opponent")
+            }));
+
+        lobby.handle(black, join_message(1, "Bo", false), now);
+        lobby.handle(spectator, join_message(1, "Cy", false), now);
+        assert!(matches!(
+            spectator_messages.try_recv(),
+            Ok(ServerMessage::Error { .. })
+        ));
+        lobby.handle(spectator, join_message(1, "Cy", true), now);
+        lobby.handle(black, move_message(1, "E7", "E5"), now);
+        lobby.handle(spectator, move_message(1, "E2", "E4"), now);
+        lobby.handle(white, move_message(1, "e2", "e4"), now);
+
+        match last_update(&spectator_messages) {
+            Some(ServerMessage::Update {
+                last_move,
+                active_color,
+                white,
+                black,
+                ..
+            }) => {
+                assert_eq!(last_move.as_deref(), Some("e4"));
+                assert_eq!(active_color, Color::Black);
+                assert_eq!(white.as_deref(), Some("Ada"));
+                assert_eq!(black.as_deref(), Some("Bo"));
+            }
+            other => panic!("expected an update, got {:?}", other),
+        }
+        let errors = black_messages
+            .try_iter()
+            .filter(|message| matches!(message, ServerMessage::Error { .. }))
+            .count();
+        assert_eq!(errors, 1);
+        assert_eq!(lobby.summaries()[0].spectators, 1);
+    }
+
+    #[test]
+    fn reconnects_and_flags_on_time() {
+        let mut lobby = Lobby::new();
+        let start = Instant::now();
+        let (white, white_messages) = client(&mut lobby);
+        let (black, black_messages) = client(&mut lobby);
+        lobby.handle(
+            white,
+            ClientMessage::Create {
+                name: String::from("Ada"),
+                color: Some(Color::White),
+                time_control: Some(TimeControl {
+                    initial_seconds: 60,
+                    increment_seconds: 2,
+                }),
+            },
+            start,
+        );
+        let token = token_of(&white_messages);
+        lobby.handle(black, join_message(1, "Bo", false), start);
+        lobby.handle(
+            white,
+            move_message(1, "E2", "E4"),
+            start + Duration::from_secs(10),
+        );
+
+        lobby.disconnect(white);
+        assert!(black_messages.try_iter().any(|message| message
+            == ServerMessage::Presence {
+                game_id: 1,
+                color: Color::White,
+                connected: false,
+            }));
+        let (returned, returned_messages) = client(&mut lobby);
+        lobby.handle(
+            returned,
+            ClientMessage::Reconnect {
+                game_id: 1,
+                token: String::from("wrong"),
+            },
+            start,
+        );
+        lobby.handle(
+            returned,
+            ClientMessage::Reconnect { game_id: 1, token },
+            start + Duration::from_secs(20),
+        );
+        match last_update(&returned_messages) {
+            Some(ServerMessage::Update {
+                white_clock,
+                black_clock,
+                ..
+            }) => {
+                assert_eq!(white_clock, Some(52_000));
+                assert_eq!(black_clock, Some(50_000));
+            }
+            other => panic!("expected an update, got {:?}", other),
+        }
+
+        // Black doesn't move in the minute left
+        lobby.tick(start + Duration::from_secs(69));
+        assert_eq!(lobby.summaries()[0].result, None);
+        lobby.tick(start + Duration::from_secs(71));
+        match last_update(&black_messages) {
+            Some(ServerMessage::Update { result, reason, .. }) => {
+                assert_eq!(result.as_deref(), Some("1-0"));
+                assert_eq!(reason.as_deref(), Some("time"));
+            }
+            other => panic!("expected an update, got {:?}", other),
+        }
+        lobby.handle(black, move_message(1, "E7", "E5"), start);
+        assert!(matches!(
+            black_messages.try_recv(),
+            Ok(ServerMessage::Error { .. })
+        ));
+    }
+
+    #[test]
+    fn accepts_websocket_keys() {
+        assert_eq!(
+            websocket_accept("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+    }
+
+    #[test]
+    fn plays_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener));
+
+        let connect = || {
+            let stream = TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            (stream.try_clone().unwrap(), BufReader::new(stream))
+        };
+        let read = |reader: &mut BufReader<TcpStream>| {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            serde_json::from_str::<ServerMessage>(&line).unwrap()
+        };
+
+        let (mut white, mut white_reader) = connect();
+        writeln!(white, r#"{{"type":"create","name":"Ada"}}"#).unwrap();
+        assert!(matches!(
+            read(&mut white_reader),
+            ServerMessage::Joined { game_id: 1, .. }
+        ));
+        read(&mut white_reader);
+
+        let (mut black, mut black_reader) = connect();
+        writeln!(black, r#"{{"type":"join","game_id":1,"name":"Bo"}}"#).unwrap();
+        assert!(matches!(
+            read(&mut black_reader),
+            ServerMessage::Joined {
+                color: Some(Color::Black),
+                ..
+            }
+        ));
+        read(&mut black_reader);
+        writeln!(white, "not json").unwrap();
+        read(&mut white_reader);
+        assert!(matches!(
+            read(&mut white_reader),
+            ServerMessage::Error { .. }
+        ));
+
+        writeln!(
+            white,
+            r#"{{"type":"move","game_id":1,"from":"G1","to":"F3"}}"#
+        )
+        .unwrap();
+        match read(&mut black_reader) {
+            ServerMessage::Update { last_move, fen, .. } => {
+                assert_eq!(last_move.as_deref(), Some("Nf3"));
+                assert!(fen.starts_with("rnbqkbnr/pppppppp/8/8/8/5N2/"));
+            }
+            other => panic!("expected an update, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn closes_connections_with_oversized_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener));
+        let connect = || {
+            let stream = TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            stream
+        };
+
+        // A line without an end
+        let mut stream = connect();
+        stream.write_all(&vec![b' '; MAX_MESSAGE + 1]).unwrap();
+        let mut rest = Vec::new();
+        assert!(matches!(stream.read_to_end(&mut rest), Ok(0) | Err(_)));
+
+        // A WebSocket frame claiming a terabyte
+        let mut stream = connect();
+        write!(
+            stream,
+            "GET / HTTP/1.1\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n"
+        )
+        .unwrap();
+        stream.write_all(&[0x81, 0xff]).unwrap();
+        stream.write_all(&(1u64 << 40).to_be_bytes()).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        let mut close = [0; 4];
+        reader.read_exact(&mut close).unwrap();
+        assert_eq!(close, [0x88, 2, 0x03, 0xf1]);
+    }
+
+    #[test]
+    fn rejects_huge_clocks_and_joining_twice() {
+        let mut lobby = Lobby::new();
+        let start = Instant::now();
+        let (white, white_messages) = client(&mut lobby);
+        lobby.handle(
+            white,
+            ClientMessage::Create {
+                name: String::from("Ada"),
+                color: Some(Color::White),
+                time_control: Some(TimeControl {
+                    initial_seconds: 60,
+                    increment_seconds: u64::MAX,
+                }),
+            },
+            start,
+        );
+        assert!(matches!(
+            white_messages.try_recv(),
+            Ok(ServerMessage::Error { .. })
+        ));
+        assert!(lobby.summaries().is_empty());
+
+        lobby.handle(
+            white,
+            ClientMessage::Create {
+                name: String::from("Ada"),
+                color: Some(Color::White),
+                time_control: None,
+            },
+            start,
+        );
+        lobby.handle(white, join_message(1, "Ada", false), start);
+        assert!(white_messages
+            .try_iter()
+            .any(|message| matches!(message, ServerMessage::Error { .. })));
+        assert_eq!(lobby.summaries()[0].black, None);
+    }
+}
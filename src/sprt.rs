@@ -0,0 +1,167 @@
+use crate::rating::expected_score;
+use crate::tournament::Score;
+
+/// The hypotheses and error rates of a sequential probability ratio test.
+/// H0 is that the first player is `elo0` stronger than the second, H1
+/// that it's `elo1` stronger.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SprtOptions {
+    pub elo0: f64,
+    pub elo1: f64,
+    /// The chance of accepting H1 when H0 is true.
+    pub alpha: f64,
+    /// The chance of accepting H0 when H1 is true.
+    pub beta: f64,
+}
+
+impl Default for SprtOptions {
+    fn default() -> Self {
+        SprtOptions {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+/// What a test has shown so far.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// The log-likelihood ratio fell to the lower bound: the first player
+    /// is more likely `elo0` than `elo1` stronger, so a change fails.
+    AcceptH0,
+    /// The log-likelihood ratio rose to the upper bound: the first player
+    /// is more likely `elo1` stronger, so a change passes.
+    AcceptH1,
+    /// More games are needed.
+    Continue,
+}
+
+/// The log-likelihood ratio of a test with its bounds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SprtStatus {
+    pub llr: f64,
+    pub lower: f64,
+    pub upper: f64,
+    pub decision: Decision,
+}
+
+/// Tests a score against the hypotheses. The pentanomial statistics of
+/// game pairs are used when there are finished pairs, since games from
+/// the same opening aren't independent, and the wins, draws and losses
+/// otherwise. The log-likelihood ratio is the usual normal approximation
+/// of the generalized SPRT with logistic Elo.
+pub fn sprt(score: &Score, options: &SprtOptions) -> SprtStatus {
+    let lower = (options.beta / (1.0 - options.alpha)).ln();
+    let upper = ((1.0 - options.beta) / options.alpha).ln();
+    let samples: Vec<(f64, u32)> = if score.pairs.iter().any(|&count| count > 0) {
+        score
+            .pairs
+            .iter()
+            .enumerate()
+            .map(|(points, &count)| (points as f64 / 4.0, count))
+            .collect()
+    } else {
+        vec![(0.0, score.losses), (0.5, score.draws), (1.0, score.wins)]
+    };
+    let llr = log_likelihood_ratio(
+        &samples,
+        expected_score(options.elo0),
+        expected_score(options.elo1),
+    );
+    let decision = if llr >= upper {
+        Decision::AcceptH1
+    } else if llr <= lower {
+        Decision::AcceptH0
+    } else {
+        Decision::Continue
+    };
+    SprtStatus {
+        llr,
+        lower,
+        upper,
+        decision,
+    }
+}
+
+// The log-likelihood ratio of mean scores s1 against s0 for samples of
+// scores from 0 to 1 with their counts, 0 while the variance is unknown
+fn log_likelihood_ratio(samples: &[(f64, u32)], s0: f64, s1: f64) -> f64 {
+    let count: u32 = samples.iter().map(|&(_, count)| count).sum();
+    if count == 0 {
+        return 0.0;
+    }
+    let count = count as f64;
+    let mean = samples
+        .iter()
+        .map(|&(score, times)| score * times as f64)
+        .sum::<f64>()
+        / count;
+    let variance = samples
+        .iter()
+        .map(|&(score, times)| (score - mean).powi(2) * times as f64)
+        .sum::<f64>()
+        / count;
+    if variance <= 0.0 {
+        return 0.0;
+    }
+    count * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Only the pairs are tested when there are any
+    fn pairs(pairs: [u32; 5]) -> Score {
+        Score {
+            pairs,
+            ..Score::default()
+        }
+    }
+
+    #[test]
+    fn finds_bounds_and_decisions() {
+        let options = SprtOptions::default();
+        let status = sprt(&Score::default(), &options);
+        assert!((status.upper - 2.944).abs() < 0.001);
+        assert!((status.lower + 2.944).abs() < 0.001);
+        assert_eq!(status.llr, 0.0);
+        assert_eq!(status.decision, Decision::Continue);
+
+        // Clearly better and clearly worse
+        let better = sprt(&pairs([100, 400, 1000, 600, 300]), &options);
+        assert_eq!(better.decision, Decision::AcceptH1);
+        let worse = sprt(&pairs([300, 600, 1000, 400, 100]), &options);
+        assert_eq!(worse.decision, Decision::AcceptH0);
+
+        let even = sprt(&pairs([10, 40, 100, 40, 10]), &options);
+        assert_eq!(even.decision, Decision::Continue);
+    }
+
+    #[test]
+    fn uses_trinomial_statistics_without_pairs() {
+        let options = SprtOptions {
+            elo0: 0.0,
+            elo1: 50.0,
+            ..SprtOptions::default()
+        };
+        let score = Score {
+            wins: 300,
+            draws: 200,
+            losses: 100,
+            pairs: [0; 5],
+        };
+        let status = sprt(&score, &options);
+        assert!(status.llr > status.upper);
+        assert_eq!(status.decision, Decision::AcceptH1);
+
+        // A mean score halfway between the hypotheses supports neither
+        let (s0, s1) = (expected_score(0.0), expected_score(50.0));
+        assert!((s0 - 0.5).abs() < 1e-12);
+        let middle = (s0 + s1) / 2.0;
+        let llr = log_likelihood_ratio(&[(middle - 0.25, 10), (middle + 0.25, 10)], s0, s1);
+        assert!(llr.abs() < 1e-9);
+    }
+}
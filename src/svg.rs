@@ -0,0 +1,257 @@
+use crate::game::*;
+use crate::piece::*;
+
+/// Options for drawing a board as an SVG image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvgOptions {
+    /// Width and height of the image in pixels.
+    pub size: u32,
+    pub light_color: String,
+    pub dark_color: String,
+    /// Write file letters and rank numbers around the board.
+    pub coordinates: bool,
+    /// Show the board from black's side.
+    pub flipped: bool,
+    /// Arrows to draw as (from, to) squares, e.g. ("E2", "E4"). Arrows
+    /// with invalid squares are left out.
+    pub arrows: Vec<(String, String)>,
+    pub arrow_color: String,
+    /// Squares to highlight, e.g. the last move or the squares of a motif.
+    /// Invalid squares are ignored.
+    pub highlights: Vec<String>,
+    pub highlight_color: String,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            size: 400,
+            light_color: String::from("#f0d9b5"),
+            dark_color: String::from("#b58863"),
+            coordinates: true,
+            flipped: false,
+            arrows: Vec::new(),
+            arrow_color: String::from("#15781b"),
+            highlights: Vec::new(),
+            highlight_color: String::from("#cdd26a"),
+        }
+    }
+}
+
+/// Draws the board of a game as a standalone SVG image.
+///
+/// Pieces are drawn with the Unicode chess glyphs, so the image needs no
+/// external resources.
+pub fn to_svg(game: &Game, options: &SvgOptions) -> String {
+    let size = options.size as f64;
+    let margin = if options.coordinates {
+        size / 20.0
+    } else {
+        0.0
+    };
+    let square = (size - 2.0 * margin) / 8.0;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">\n",
+        options.size
+    );
+    svg.push_str(&format!(
+        "<defs><marker id=\"arrowhead\" markerWidth=\"4\" markerHeight=\"4\" refX=\"2\" refY=\"2\" orient=\"auto\"><path d=\"M0,0 L4,2 L0,4 z\" fill=\"{}\"/></marker></defs>\n",
+        escape_attribute(&options.arrow_color)
+    ));
+    svg.push_str(&format!(
+        "<rect width=\"{0}\" height=\"{0}\" fill=\"#ffffff\"/>\n",
+        options.size
+    ));
+
+    // Squares and pieces
+    let highlights: Vec<usize> = options
+        .highlights
+        .iter()
+        .filter_map(|square| parse_square(square))
+        .collect();
+    for (index, tile) in game.get_board().iter().enumerate() {
+        let (x, y) = square_origin(index, margin, square, options.flipped);
+        let color = if highlights.contains(&index) {
+            &options.highlight_color
+        } else if (index % 8 + index / 8) % 2 == 1 {
+            &options.light_color
+        } else {
+            &options.dark_color
+        };
+        svg.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"/>\n",
+            x,
+            y,
+            square,
+            square,
+            escape_attribute(color)
+        ));
+        if let Some(piece) = tile {
+            svg.push_str(&piece_text(*piece, x, y, square));
+        }
+    }
+
+    if options.coordinates {
+        for i in 0..8 {
+            let file = if options.flipped { 7 - i } else { i };
+            let rank = if options.flipped { i } else { 7 - i };
+            let along = margin + square * (i as f64 + 0.5);
+            for y in &[margin / 2.0, size - margin / 2.0] {
+                svg.push_str(&coordinate_text(
+                    along,
+                    *y,
+                    margin,
+                    (b'a' + file as u8) as char,
+                ));
+            }
+            for x in &[margin / 2.0, size - margin / 2.0] {
+                svg.push_str(&coordinate_text(
+                    *x,
+                    along,
+                    margin,
+                    (b'1' + rank as u8) as char,
+                ));
+            }
+        }
+    }
+
+    let arrows = options
+        .arrows
+        .iter()
+        .filter_map(|(from, to)| Some((parse_square(from)?, parse_square(to)?)));
+    for (from, to) in arrows {
+        let (x1, y1) = square_centre(from, margin, square, options.flipped);
+        let (x2, y2) = square_centre(to, margin, square, options.flipped);
+        // Stop the line before the centre so the head ends on the square
+        let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+        let shorten = if length > 0.0 {
+            square * 0.3 / length
+        } else {
+            0.0
+        };
+        svg.push_str(&format!(
+            "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"{:.1}\" stroke-opacity=\"0.8\" stroke-linecap=\"round\" marker-end=\"url(#arrowhead)\"/>\n",
+            x1,
+            y1,
+            x2 - (x2 - x1) * shorten,
+            y2 - (y2 - y1) * shorten,
+            escape_attribute(&options.arrow_color),
+            square / 6.0
+        ));
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// The top left corner of a square on the image
+fn square_origin(index: usize, margin: f64, square: f64, flipped: bool) -> (f64, f64) {
+    let (file, rank) = (index % 8, index / 8);
+    let column = if flipped { 7 - file } else { file };
+    let row = if flipped { rank } else { 7 - rank };
+    (
+        margin + column as f64 * square,
+        margin + row as f64 * square,
+    )
+}
+
+fn square_centre(index: usize, margin: f64, square: f64, flipped: bool) -> (f64, f64) {
+    let (x, y) = square_origin(index, margin, square, flipped);
+    (x + square / 2.0, y + square / 2.0)
+}
+
+// Escapes a value written inside a double quoted attribute
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+fn piece_text(piece: Piece, x: f64, y: f64, square: f64) -> String {
+    // The solid glyphs are used for both colours and filled in
+    let glyph = Piece::new(Color::Black, piece.title(), false).unicode();
+    let (fill, stroke) = match piece.color() {
+        Color::White => ("#ffffff", "#000000"),
+        Color::Black => ("#000000", "#000000"),
+    };
+    format!(
+        "<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"{:.1}\" text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"{}\" stroke=\"{}\" stroke-width=\"{:.1}\">{}</text>\n",
+        x + square / 2.0,
+        y + square / 2.0,
+        square * 0.8,
+        fill,
+        stroke,
+        square / 40.0,
+        glyph
+    )
+}
+
+fn coordinate_text(x: f64, y: f64, margin: f64, label: char) -> String {
+    format!(
+        "<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"{:.1}\" font-family=\"sans-serif\" text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"#333333\">{}</text>\n",
+        x,
+        y,
+        margin * 0.7,
+        label
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_squares_and_pieces() {
+        let svg = to_svg(&Game::new(), &SvgOptions::default());
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"400\""));
+        assert!(svg.ends_with("</svg>\n"));
+        // The background and 64 squares
+        assert_eq!(svg.matches("<rect ").count(), 65);
+        assert_eq!(svg.matches("stroke=\"#000000\" stroke-width").count(), 32);
+    }
+
+    #[test]
+    fn a8_is_in_the_top_left_corner() {
+        let options = SvgOptions {
+            coordinates: false,
+            ..SvgOptions::default()
+        };
+        assert_eq!(square_origin(56, 0.0, 50.0, false), (0.0, 0.0));
+        assert_eq!(square_origin(56, 0.0, 50.0, true), (350.0, 350.0));
+        let svg = to_svg(&Game::new(), &options);
+        // The black rook on a8, drawn with the solid glyph
+        assert!(svg.contains("<text x=\"25.0\" y=\"25.0\""));
+        assert!(svg.contains("fill=\"#000000\" stroke=\"#000000\" stroke-width=\"1.2\">♜</text>"));
+    }
+
+    #[test]
+    fn draws_arrows_and_highlights() {
+        let options = SvgOptions {
+            size: 800,
+            coordinates: false,
+            arrows: vec![
+                (String::from("e2"), String::from("e4")),
+                (String::from("e2"), String::from("e9")),
+            ],
+            arrow_color: String::from("red\" onload=\"alert(1)"),
+            highlights: vec![String::from("e4"), String::from("z9")],
+            ..SvgOptions::default()
+        };
+        let svg = to_svg(&Game::new(), &options);
+        assert!(svg.contains("<line x1=\"450.0\" y1=\"650.0\" x2=\"450.0\""));
+        assert_eq!(svg.matches("<line ").count(), 1);
+        assert!(svg.contains("stroke=\"red&quot; onload=&quot;alert(1)\""));
+        assert!(svg.contains(
+            "<rect x=\"400.0\" y=\"400.0\" width=\"100.0\" height=\"100.0\" fill=\"#cdd26a\"/>"
+        ));
+    }
+
+    #[test]
+    fn writes_coordinates_on_all_sides() {
+        let svg = to_svg(&Game::new(), &SvgOptions::default());
+        assert_eq!(svg.matches(">a</text>").count(), 2);
+        assert_eq!(svg.matches(">8</text>").count(), 2);
+    }
+}
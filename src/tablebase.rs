@@ -0,0 +1,2171 @@
+use crate::game::*;
+use crate::piece::*;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+// The first bytes of Syzygy WDL and DTZ files
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+// The flags of a table, the DTZ ones tell which side to move is stored,
+// if the values are mapped and if they count half moves or moves
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+/// The result of a position with perfect play, from the view of the side
+/// to move. Cursed wins and blessed losses are wins and losses that the
+/// 50 move rule turns into draws.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    // The result for the other side
+    fn opposite(self) -> Wdl {
+        match self {
+            Wdl::Loss => Wdl::Win,
+            Wdl::BlessedLoss => Wdl::CursedWin,
+            Wdl::Draw => Wdl::Draw,
+            Wdl::CursedWin => Wdl::BlessedLoss,
+            Wdl::Win => Wdl::Loss,
+        }
+    }
+
+    // Tables store the results from 0 for a loss to 4 for a win
+    fn from_value(value: u16) -> Option<Wdl> {
+        match value {
+            0 => Some(Wdl::Loss),
+            1 => Some(Wdl::BlessedLoss),
+            2 => Some(Wdl::Draw),
+            3 => Some(Wdl::CursedWin),
+            4 => Some(Wdl::Win),
+            _ => None,
+        }
+    }
+
+    fn sign(self) -> i32 {
+        match self {
+            Wdl::Loss | Wdl::BlessedLoss => -1,
+            Wdl::Draw => 0,
+            Wdl::CursedWin | Wdl::Win => 1,
+        }
+    }
+}
+
+/// A directory of Syzygy endgame tablebase files (`.rtbw` for WDL and
+/// `.rtbz` for DTZ).
+///
+/// Tables are read into memory the first time they are probed. Like the
+/// Syzygy probing code, probes search captures (and pawn moves for DTZ)
+/// down to smaller tables, so the tables of the endgames reachable by
+/// captures are needed as well.
+#[derive(Clone, Debug, Default)]
+pub struct Tablebase {
+    // Material keys like "KRvK" to their files
+    wdl: HashMap<String, PathBuf>,
+    dtz: HashMap<String, PathBuf>,
+    max_pieces: usize,
+    // The tables read so far, shared between clones
+    cache: Cache,
+}
+
+// Tables by file, None for files that couldn't be decoded
+#[derive(Clone, Default)]
+struct Cache(Arc<Mutex<HashMap<PathBuf, Option<Arc<Table>>>>>);
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cache").finish_non_exhaustive()
+    }
+}
+
+impl Tablebase {
+    /// Finds the tablebase files in a directory. Files without the Syzygy
+    /// header are skipped.
+    pub fn open<P: AsRef<Path>>(directory: P) -> io::Result<Tablebase> {
+        let mut tablebase = Tablebase::default();
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            let (name, extension) = match (path.file_stem(), path.extension()) {
+                (Some(name), Some(extension)) => (
+                    name.to_string_lossy().to_string(),
+                    extension.to_string_lossy().to_string(),
+                ),
+                _ => continue,
+            };
+            let (tables, magic) = match extension.as_str() {
+                "rtbw" => (&mut tablebase.wdl, WDL_MAGIC),
+                "rtbz" => (&mut tablebase.dtz, DTZ_MAGIC),
+                _ => continue,
+            };
+            if !is_material_key(&name) || !has_magic(&path, magic) {
+                continue;
+            }
+            // The name has one letter for each piece and the 'v'
+            tablebase.max_pieces = tablebase.max_pieces.max(name.len() - 1);
+            tables.insert(name, path);
+        }
+        Ok(tablebase)
+    }
+
+    /// Get the largest number of pieces, kings included, that tables were
+    /// found for.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// If a WDL table was found for the material of the game.
+    pub fn has_wdl_table(&self, game: &Game) -> bool {
+        find_table(&self.wdl, game.get_board()).is_some()
+    }
+
+    /// If a DTZ table was found for the material of the game.
+    pub fn has_dtz_table(&self, game: &Game) -> bool {
+        find_table(&self.dtz, game.get_board()).is_some()
+    }
+
+    /// Probes the win, draw or loss result of the current position for the
+    /// side to move. Returns None if the position can't be probed, e.g.
+    /// because it has castling rights or a table is missing.
+    pub fn probe_wdl(&self, game: &Game) -> Option<Wdl> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        self.search(game, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Probes the distance to zeroing, the number of half moves to the
+    /// next capture or pawn move with the best play, positive when the
+    /// side to move wins and negative when it loses. Drawn positions and
+    /// finished games give 0, and cursed wins and blessed losses are 100
+    /// further from 0 than the distance.
+    pub fn probe_dtz(&self, game: &Game) -> Option<i32> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        if game.is_game_over() {
+            return Some(0);
+        }
+        self.dtz(game)
+    }
+
+    /// Probes the DTZ after each legal move of the position, from the view
+    /// of the side to move: winning moves are positive and the smallest one
+    /// wins quickest, losing moves are negative and the largest one defends
+    /// longest. A move that mates gives 1. Returns None if the position
+    /// can't be probed, or if a move promotes since `Game` can't promote
+    /// pawns yet.
+    pub fn probe_root(&self, game: &Game) -> Option<Vec<(String, String, i32)>> {
+        if !self.can_probe(game) || game.is_game_over() {
+            return None;
+        }
+        let mut moves = Vec::new();
+        for (from, to) in game.get_all_possible_moves() {
+            let (capture, pawn, promotion) = move_kind(game.get_board(), &from, &to)?;
+            if promotion {
+                return None;
+            }
+            let mut child = game.clone();
+            child.make_move(from.clone(), to.clone())?;
+            let dtz = if capture || pawn {
+                dtz_before_zeroing(self.search(&child, false)?.0.opposite())
+            } else if is_checkmate(&child) {
+                1
+            } else {
+                let dtz = -self.dtz(&child)?;
+                dtz + dtz.signum()
+            };
+            moves.push((from, to, dtz));
+        }
+        Some(moves)
+    }
+
+    // Positions with more pieces than the tables are skipped right away
+    fn can_probe(&self, game: &Game) -> bool {
+        let board = game.get_board();
+        is_probeable(game)
+            && (board.iter().flatten().count() <= self.max_pieces || is_drawn_material(board))
+    }
+
+    // The result of a position, searching captures (and pawn moves when
+    // `zeroing` is set) before the table. Also tells if the result is
+    // reached by one of those moves.
+    fn search(&self, game: &Game, zeroing: bool) -> Option<(Wdl, bool)> {
+        match game.get_game_state() {
+            GameState::WhiteCheckMate | GameState::BlackCheckMate => {
+                return Some((Wdl::Loss, false))
+            }
+            GameState::Tie => return Some((Wdl::Draw, false)),
+            _ => (),
+        }
+        let (children, total) = successors(game, |capture, pawn| capture || (zeroing && pawn))?;
+        let searched = children.len();
+        let mut best = Wdl::Loss;
+        for (child, _) in children {
+            let wdl = self.search(&child, false)?.0.opposite();
+            if wdl > best {
+                best = wdl;
+                if wdl == Wdl::Win {
+                    return Some((wdl, true));
+                }
+            }
+        }
+        // The table isn't needed if every move has been searched
+        let all_searched = searched > 0 && searched == total;
+        let wdl = if all_searched {
+            best
+        } else {
+            self.probe_table(game)?
+        };
+        if best >= wdl {
+            return Some((best, best > Wdl::Draw || all_searched));
+        }
+        Some((wdl, false))
+    }
+
+    fn probe_table(&self, game: &Game) -> Option<Wdl> {
+        let board = game.get_board();
+        if is_drawn_material(board) {
+            return Some(Wdl::Draw);
+        }
+        let table = self.table(board, true)?;
+        let black_to_move = game.get_active_color() == Color::Black;
+        let (_, value) = table.value(board, black_to_move)??;
+        Wdl::from_value(value)
+    }
+
+    // The DTZ of a position that isn't drawn by the rules, -1 for checkmate
+    fn dtz(&self, game: &Game) -> Option<i32> {
+        if is_checkmate(game) {
+            return Some(-1);
+        }
+        let (wdl, zeroing) = self.search(game, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        let board = game.get_board();
+        let table = self.table(board, false)?;
+        if let Some((file, value)) = table.value(board, game.get_active_color() == Color::Black)? {
+            let dtz = table.map_score(file, value, wdl)?;
+            let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+            return Some((dtz + if cursed { 100 } else { 0 }) * wdl.sign());
+        }
+
+        // The table is for the other side to move, so look one move ahead
+        let (children, _) = successors(game, |_, _| true)?;
+        let mut best = i32::MAX;
+        for (child, zeroing) in children {
+            let mut dtz = if zeroing {
+                -dtz_before_zeroing(self.search(&child, false)?.0)
+            } else {
+                -self.dtz(&child)?
+            };
+            if dtz == 1 && is_checkmate(&child) {
+                best = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < best && dtz.signum() == wdl.sign() {
+                best = dtz;
+            }
+        }
+        Some(if best == i32::MAX { -1 } else { best })
+    }
+
+    fn table(&self, board: &[Option<Piece>; 64], wdl: bool) -> Option<Arc<Table>> {
+        let tables = if wdl { &self.wdl } else { &self.dtz };
+        let (name, path) = find_table(tables, board)?;
+        let mut cache = self
+            .cache
+            .0
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        cache
+            .entry(path.clone())
+            .or_insert_with(|| {
+                fs::read(path)
+                    .ok()
+                    .and_then(|bytes| Table::read(bytes, name, wdl))
+                    .map(Arc::new)
+            })
+            .clone()
+    }
+}
+
+/// Writes the material of a board as a Syzygy table name, white's pieces
+/// first, e.g. `KRvK`.
+pub fn material_key(board: &[Option<Piece>; 64]) -> String {
+    format!(
+        "{}v{}",
+        side_key(board, Color::White),
+        side_key(board, Color::Black)
+    )
+}
+
+// The pieces of one side from the king to the pawns
+fn side_key(board: &[Option<Piece>; 64], color: Color) -> String {
+    let order = [
+        PieceType::King,
+        PieceType::Queen,
+        PieceType::Rook,
+        PieceType::Bishop,
+        PieceType::Knight,
+        PieceType::Pawn,
+    ];
+    let mut titles: Vec<PieceType> = board
+        .iter()
+        .flatten()
+        .filter(|piece| piece.color() == color)
+        .map(|piece| piece.title())
+        .collect();
+    titles.sort_by_key(|title| order.iter().position(|other| other == title));
+    titles
+        .into_iter()
+        .map(|title| Piece::new(Color::White, title, false).letter())
+        .collect()
+}
+
+// Tables are named with the stronger side first, so try both orders
+fn find_table<'a>(
+    tables: &'a HashMap<String, PathBuf>,
+    board: &[Option<Piece>; 64],
+) -> Option<(&'a String, &'a PathBuf)> {
+    let key = material_key(board);
+    let (white, black) = key.split_once('v')?;
+    tables
+        .get_key_value(&key)
+        .or_else(|| tables.get_key_value(&format!("{}v{}", black, white)))
+}
+
+// Tables don't cover castling, so positions with castling rights can't be probed
+fn is_probeable(game: &Game) -> bool {
+    game.get_castling_rights() == CastlingRights::default()
+}
+
+// Neither side can mate with a lone king, or a king and a single minor piece
+pub(crate) fn is_drawn_material(board: &[Option<Piece>; 64]) -> bool {
+    let pieces: Vec<Piece> = board.iter().flatten().copied().collect();
+    let minors = pieces
+        .iter()
+        .filter(|piece| matches!(piece.title(), PieceType::Bishop | PieceType::Knight))
+        .count();
+    pieces.len() - minors == 2 && minors <= 1
+}
+
+fn is_material_key(name: &str) -> bool {
+    let sides: Vec<&str> = name.split('v').collect();
+    sides.len() == 2
+        && sides
+            .iter()
+            .all(|side| side.starts_with('K') && side.chars().all(|c| "KQRBNP".contains(c)))
+}
+
+// Only reads the header, tables can be gigabytes large
+fn has_magic(path: &Path, magic: [u8; 4]) -> bool {
+    let mut header = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok()
+        && header == magic
+}
+
+fn is_checkmate(game: &Game) -> bool {
+    matches!(
+        game.get_game_state(),
+        GameState::WhiteCheckMate | GameState::BlackCheckMate
+    )
+}
+
+// The DTZ of a position where the best move captures or moves a pawn
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Loss => -1,
+        Wdl::BlessedLoss => -101,
+        Wdl::Draw => 0,
+        Wdl::CursedWin => 101,
+        Wdl::Win => 1,
+    }
+}
+
+// If a legal move captures, if it moves a pawn and if it promotes
+fn move_kind(board: &[Option<Piece>; 64], from: &str, to: &str) -> Option<(bool, bool, bool)> {
+    let (from, to) = (parse_square(from)?, parse_square(to)?);
+    let pawn = board[from]?.title() == PieceType::Pawn;
+    // Pawns only change file when capturing, en passant included
+    let capture = board[to].is_some() || (pawn && from % 8 != to % 8);
+    Some((capture, pawn, pawn && (to / 8 == 0 || to / 8 == 7)))
+}
+
+// The positions after the legal moves that are wanted by if they capture
+// and if they move a pawn, with if they do either, and the number of
+// legal moves. Pawns reaching the last rank promote to each piece.
+fn successors<F>(game: &Game, wanted: F) -> Option<(Vec<(Game, bool)>, usize)>
+where
+    F: Fn(bool, bool) -> bool,
+{
+    let board = game.get_board();
+    let mut children = Vec::new();
+    let mut total = 0;
+    for (from, to) in game.get_all_possible_moves() {
+        let (capture, pawn, promotion) = move_kind(board, &from, &to)?;
+        let to_index = parse_square(&to)?;
+        total += if promotion { 4 } else { 1 };
+        if !wanted(capture, pawn) {
+            continue;
+        }
+        let mut child = game.clone();
+        child.make_move(from, to)?;
+        if !promotion {
+            children.push((child, capture || pawn));
+            continue;
+        }
+        for &title in &[
+            PieceType::Queen,
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+        ] {
+            let mut board = *child.get_board();
+            board[to_index] = Some(Piece::new(game.get_active_color(), title, true));
+            let promoted = Game::from_parts(
+                board,
+                child.get_active_color(),
+                CastlingRights::default(),
+                None,
+                0,
+                1,
+                false,
+            );
+            children.push((promoted, true));
+        }
+    }
+    Some((children, total))
+}
+
+// A table read from a file. WDL tables store both sides to move unless the
+// material is symmetric, DTZ tables only one. Tables with pawns have a part
+// for each file of the leading pawn from a to d.
+struct Table {
+    bytes: Vec<u8>,
+    // The pieces of the side named first in the file name, e.g. "KR"
+    white: String,
+    wdl: bool,
+    symmetric: bool,
+    has_pawns: bool,
+    // The pawns of the side with the leading pawns and of the other side
+    pawn_count: [usize; 2],
+    has_unique_pieces: bool,
+    // Indexed by side to move and file
+    parts: Vec<Vec<Part>>,
+    // Where the DTZ value maps start
+    map: usize,
+}
+
+// The compressed values of a table for one side to move and file, with
+// the offsets of its data in the file
+#[derive(Clone, Debug, Default)]
+struct Part {
+    flags: u8,
+    // Pieces in the order they're encoded, 1 to 6 for white's pawn to king
+    // and 9 to 14 for black's
+    pieces: Vec<u8>,
+    // The pieces are encoded in groups of the leading pieces and then of
+    // the same pieces. The index of a group is multiplied by its factor,
+    // and the last factor is the number of values.
+    group_len: Vec<usize>,
+    group_idx: Vec<u64>,
+    block_size: usize,
+    span: u64,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    block_lengths: usize,
+    block_length_size: usize,
+    blocks: usize,
+    // The value of single value parts
+    min_sym_len: u8,
+    lowest_sym: usize,
+    // The smallest code of each length, shifted to the top
+    base64: Vec<u64>,
+    // The number of values a symbol stands for, minus one
+    symlen: Vec<usize>,
+    btree: usize,
+    data: usize,
+    map_idx: [usize; 4],
+}
+
+impl Table {
+    fn read(bytes: Vec<u8>, name: &str, wdl: bool) -> Option<Table> {
+        let (white, black) = name.split_once('v')?;
+        let pawns = |side: &str| side.matches('P').count();
+        // The leading pawns are white's unless black has fewer
+        let pawn_count = if pawns(black) == 0 || (pawns(white) > 0 && pawns(black) >= pawns(white))
+        {
+            [pawns(white), pawns(black)]
+        } else {
+            [pawns(black), pawns(white)]
+        };
+        let mut table = Table {
+            bytes,
+            white: white.to_string(),
+            wdl,
+            symmetric: white == black,
+            has_pawns: pawn_count[0] > 0,
+            pawn_count,
+            has_unique_pieces: [white, black]
+                .iter()
+                .any(|side| "QRBNP".chars().any(|c| side.matches(c).count() == 1)),
+            parts: Vec::new(),
+            map: 0,
+        };
+        let bytes = &table.bytes;
+
+        let flags = *bytes.get(4)?;
+        if (flags & 1 == 0) != table.symmetric || (flags & 2 != 0) != table.has_pawns {
+            return None;
+        }
+        let sides = if wdl && !table.symmetric { 2 } else { 1 };
+        let files = if table.has_pawns { 4 } else { 1 };
+        let pp = table.has_pawns && pawn_count[1] > 0;
+        let mut parts = vec![vec![Part::default(); files]; sides];
+        let mut at = 5;
+        for file in 0..files {
+            let first = *bytes.get(at)?;
+            let second = if pp { *bytes.get(at + 1)? } else { 0xff };
+            let order = [[first & 0xf, second & 0xf], [first >> 4, second >> 4]];
+            at += 1 + pp as usize;
+            for _ in 0..name.len() - 1 {
+                let byte = *bytes.get(at)?;
+                for (side, side_parts) in parts.iter_mut().enumerate() {
+                    side_parts[file]
+                        .pieces
+                        .push(if side == 0 { byte & 0xf } else { byte >> 4 });
+                }
+                at += 1;
+            }
+            for (side, side_parts) in parts.iter_mut().enumerate() {
+                table.set_groups(&mut side_parts[file], order[side], file)?;
+            }
+        }
+        at += at & 1;
+
+        for file in 0..files {
+            for side_parts in parts.iter_mut() {
+                at = side_parts[file].set_sizes(bytes, at)?;
+            }
+        }
+        let map = at;
+        if !wdl {
+            for part in parts[0].iter_mut() {
+                at = part.set_dtz_map(bytes, map, at)?;
+            }
+            at += at & 1;
+        }
+        for file in 0..files {
+            for side_parts in parts.iter_mut() {
+                side_parts[file].sparse_index = at;
+                at += side_parts[file].sparse_index_size * 6;
+            }
+        }
+        for file in 0..files {
+            for side_parts in parts.iter_mut() {
+                side_parts[file].block_lengths = at;
+                at += side_parts[file].block_length_size * 2;
+            }
+        }
+        for file in 0..files {
+            for side_parts in parts.iter_mut() {
+                let part = &mut side_parts[file];
+                at = (at + 63) & !63;
+                part.data = at;
+                at += part.blocks * part.block_size;
+            }
+        }
+        if at > bytes.len() {
+            return None;
+        }
+        table.parts = parts;
+        table.map = map;
+        Some(table)
+    }
+
+    // Splits the pieces of a part into groups and finds the factor of each
+    // group, in the order given by the file
+    fn set_groups(&self, part: &mut Part, order: [u8; 2], file: usize) -> Option<()> {
+        let encoding = encoding();
+        let pieces = &part.pieces;
+        let mut first_len: i32 = if self.has_pawns {
+            0
+        } else if self.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        let mut group_len = vec![1];
+        for i in 1..pieces.len() {
+            first_len -= 1;
+            if first_len > 0 || pieces[i] == pieces[i - 1] {
+                *group_len.last_mut()? += 1;
+            } else {
+                group_len.push(1);
+            }
+        }
+
+        let pp = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = if pp { 2 } else { 1 };
+        let mut free =
+            64usize.checked_sub(group_len[0] + if pp { *group_len.get(1)? } else { 0 })?;
+        let mut idx: u64 = 1;
+        let mut group_idx = vec![0; group_len.len() + 1];
+        let mut k = 0;
+        while next < group_len.len() || k == order[0] || k == order[1] {
+            let factor = if k == order[0] {
+                group_idx[0] = idx;
+                if self.has_pawns {
+                    *encoding.lead_pawns_size.get(group_len[0])?.get(file)?
+                } else if self.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                }
+            } else if k == order[1] {
+                group_idx[1] = idx;
+                encoding.binomial(group_len[1], 48usize.checked_sub(group_len[0])?)?
+            } else {
+                let len = *group_len.get(next)?;
+                group_idx[next] = idx;
+                let factor = encoding.binomial(len, free)?;
+                free = free.checked_sub(len)?;
+                next += 1;
+                factor
+            };
+            idx = idx.checked_mul(factor)?;
+            k += 1;
+        }
+        *group_idx.last_mut()? = idx;
+        part.group_len = group_len;
+        part.group_idx = group_idx;
+        Some(())
+    }
+
+    // Finds the part of the table and the value of a position in it. The
+    // inner None means that a DTZ table stores the other side to move.
+    fn value(
+        &self,
+        board: &[Option<Piece>; 64],
+        black_to_move: bool,
+    ) -> Option<Option<(usize, u16)>> {
+        let (file, part, index) = match self.index(board, black_to_move)? {
+            Some(found) => found,
+            None => return Some(None),
+        };
+        Some(Some((file, part.decompress(&self.bytes, index)?)))
+    }
+
+    // Finds the part of the table and the index of a position in it
+    fn index(
+        &self,
+        board: &[Option<Piece>; 64],
+        black_to_move: bool,
+    ) -> Option<Option<(usize, &Part, u64)>> {
+        let encoding = encoding();
+        // Tables are stored with the side named first as white, so flip
+        // the colours and the board if black is that side. Symmetric
+        // tables are stored with white to move.
+        let black_stronger = !self.symmetric && side_key(board, Color::White) != self.white;
+        let flip = black_stronger || (self.symmetric && black_to_move);
+        let side = (flip != black_to_move) as usize;
+        let flip_square = |square: usize| if flip { square ^ 56 } else { square };
+        let flip_color = if flip { 8 } else { 0 };
+
+        let mut squares = Vec::new();
+        let mut pieces = Vec::new();
+        let mut file = 0;
+        let lead = *self.parts.first()?.first()?.pieces.first()?;
+        if self.has_pawns {
+            for (square, piece) in board.iter().enumerate() {
+                if piece.map(piece_code) == Some(lead ^ flip_color) {
+                    squares.push(flip_square(square));
+                    pieces.push(lead);
+                }
+            }
+            // Encoded from the leading pawn furthest from the edge and front
+            let first = (0..squares.len()).max_by_key(|&i| encoding.map_pawns[squares[i]])?;
+            squares.swap(0, first);
+            file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+        let lead_count = squares.len();
+        if !self.wdl && !self.stores_side(side, file) {
+            return Some(None);
+        }
+        for (square, piece) in board.iter().enumerate() {
+            if let Some(piece) = piece {
+                let code = piece_code(*piece) ^ flip_color;
+                if self.has_pawns && code == lead {
+                    continue;
+                }
+                // Pawns that haven't promoted can't be encoded
+                if piece.title() == PieceType::Pawn && (square / 8 == 0 || square / 8 == 7) {
+                    return None;
+                }
+                squares.push(flip_square(square));
+                pieces.push(code);
+            }
+        }
+        let part = self.parts.get(side % self.parts.len())?.get(file)?;
+        if pieces.len() != part.pieces.len() {
+            return None;
+        }
+
+        // Put the pieces in the order of the table
+        for i in lead_count..pieces.len().saturating_sub(1) {
+            for j in i + 1..pieces.len() {
+                if part.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // Mirror the board so the first piece is on the a to d files, and
+        // without pawns on ranks 1 to 4 below the a1-h8 diagonal
+        if squares[0] % 8 > 3 {
+            for square in squares.iter_mut() {
+                *square ^= 7;
+            }
+        }
+        let mut index;
+        if self.has_pawns {
+            index = *encoding.lead_pawn_idx.get(lead_count)?.get(squares[0])?;
+            squares[1..lead_count].sort_by_key(|&square| encoding.map_pawns[square]);
+            for (i, &square) in squares.iter().enumerate().take(lead_count).skip(1) {
+                index += encoding.binomial(i, encoding.map_pawns[square] as usize)?;
+            }
+        } else {
+            if squares[0] / 8 > 3 {
+                for square in squares.iter_mut() {
+                    *square ^= 56;
+                }
+            }
+            for i in 0..part.group_len[0] {
+                let off = off_diagonal(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in squares[i..].iter_mut() {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+            if self.has_unique_pieces {
+                let (s0, s1, s2) = (squares[0] as u64, squares[1] as u64, squares[2] as u64);
+                let (r0, r1, r2) = (s0 / 8, s1 / 8, s2 / 8);
+                let adjust1 = (s1 > s0) as u64;
+                let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+                index = if off_diagonal(squares[0]) != 0 {
+                    (encoding.map_a1d1d4[squares[0]] * 63 + (s1 - adjust1)) * 62 + s2 - adjust2
+                } else if off_diagonal(squares[1]) != 0 {
+                    (6 * 63 + r0 * 28 + encoding.map_b1h1h7[squares[1]]) * 62 + s2 - adjust2
+                } else if off_diagonal(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + r0 * 7 * 28
+                        + (r1 - adjust1) * 28
+                        + encoding.map_b1h1h7[squares[2]]
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + r0 * 7 * 6
+                        + (r1 - adjust1) * 6
+                        + (r2 - adjust2)
+                };
+            } else {
+                index = encoding.map_kk[encoding.map_a1d1d4[squares[0]] as usize][squares[1]];
+                if index == u64::MAX {
+                    return None;
+                }
+            }
+        }
+
+        // The other groups are encoded by their squares in ascending order,
+        // without the squares taken by the groups before them
+        index = index.checked_mul(part.group_idx[0])?;
+        let mut start = part.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        for next in 1..part.group_len.len() {
+            let len = part.group_len[next];
+            squares[start..start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| s < square).count();
+                let offset = if remaining_pawns { 8 } else { 0 };
+                n += encoding.binomial(i + 1, square.checked_sub(adjust + offset)?)?;
+            }
+            remaining_pawns = false;
+            index = index.checked_add(n.checked_mul(part.group_idx[next])?)?;
+            start += len;
+        }
+        if index >= *part.group_idx.last()? {
+            return None;
+        }
+        Some(Some((file, part, index)))
+    }
+
+    // DTZ tables only store one side to move
+    fn stores_side(&self, side: usize, file: usize) -> bool {
+        self.parts[0]
+            .get(file)
+            .is_some_and(|part| (part.flags & STM) as usize == side)
+            || (self.symmetric && !self.has_pawns)
+    }
+
+    // Turns a value of a DTZ table into half moves
+    fn map_score(&self, file: usize, value: u16, wdl: Wdl) -> Option<i32> {
+        let part = self.parts[0].get(file)?;
+        let mut value = value as usize;
+        if part.flags & MAPPED != 0 {
+            // The maps are stored for wins, losses, cursed wins and blessed losses
+            let idx = part.map_idx[[1, 3, 0, 2, 0][wdl as usize]] + value;
+            value = if part.flags & WIDE != 0 {
+                u16_le(&self.bytes, self.map + 2 * idx)? as usize
+            } else {
+                *self.bytes.get(self.map + idx)? as usize
+            };
+        }
+        let plies = match wdl {
+            Wdl::Win => part.flags & WIN_PLIES != 0,
+            Wdl::Loss => part.flags & LOSS_PLIES != 0,
+            _ => false,
+        };
+        if !plies {
+            value *= 2;
+        }
+        Some(value as i32 + 1)
+    }
+}
+
+impl Part {
+    // Reads the Huffman code and the symbols of a part
+    fn set_sizes(&mut self, bytes: &[u8], at: usize) -> Option<usize> {
+        self.flags = *bytes.get(at)?;
+        if self.flags & SINGLE_VALUE != 0 {
+            self.min_sym_len = *bytes.get(at + 1)?;
+            return Some(at + 2);
+        }
+        let size = *self.group_idx.last()?;
+        self.block_size = 1usize.checked_shl(*bytes.get(at + 1)? as u32)?;
+        self.span = 1u64.checked_shl(*bytes.get(at + 2)? as u32)?;
+        self.sparse_index_size = size.div_ceil(self.span) as usize;
+        let padding = *bytes.get(at + 3)? as usize;
+        self.blocks = u32_le(bytes, at + 4)? as usize;
+        self.block_length_size = self.blocks + padding;
+        let max_sym_len = *bytes.get(at + 8)?;
+        self.min_sym_len = *bytes.get(at + 9)?;
+        if self.min_sym_len == 0 || max_sym_len < self.min_sym_len || max_sym_len > 64 {
+            return None;
+        }
+        self.lowest_sym = at + 10;
+
+        // Codes of a length start at half the next longer codes after them
+        let lengths = (max_sym_len - self.min_sym_len) as usize + 1;
+        let lowest_sym = |i: usize| u16_le(bytes, self.lowest_sym + 2 * i).map(u64::from);
+        let mut base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            base64[i] = (base64[i + 1] + lowest_sym(i)?).checked_sub(lowest_sym(i + 1)?)? / 2;
+        }
+        for (i, base) in base64.iter_mut().enumerate() {
+            *base <<= 64 - i - self.min_sym_len as usize;
+        }
+        self.base64 = base64;
+
+        let at = self.lowest_sym + 2 * lengths;
+        let symbols = u16_le(bytes, at)? as usize;
+        self.btree = at + 2;
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                self.set_symlen(bytes, symbol, &mut visited)?;
+            }
+        }
+        Some(self.btree + symbols * 3 + (symbols & 1))
+    }
+
+    // Symbols either stand for a value or for a pair of other symbols
+    fn set_symlen(&mut self, bytes: &[u8], symbol: usize, visited: &mut [bool]) -> Option<()> {
+        visited[symbol] = true;
+        let (left, right) = self.children(bytes, symbol)?;
+        if right != 0xfff {
+            for child in [left, right] {
+                if child >= self.symlen.len() {
+                    return None;
+                }
+                if !visited[child] {
+                    self.set_symlen(bytes, child, visited)?;
+                }
+            }
+            self.symlen[symbol] = self.symlen[left] + self.symlen[right] + 1;
+        }
+        Some(())
+    }
+
+    fn children(&self, bytes: &[u8], symbol: usize) -> Option<(usize, usize)> {
+        let lr = bytes.get(self.btree + 3 * symbol..self.btree + 3 * symbol + 3)?;
+        let (lr0, lr1, lr2) = (lr[0] as usize, lr[1] as usize, lr[2] as usize);
+        Some((((lr1 & 0xf) << 8) | lr0, (lr2 << 4) | (lr1 >> 4)))
+    }
+
+    // The offsets of the DTZ value maps of the four results
+    fn set_dtz_map(&mut self, bytes: &[u8], map: usize, mut at: usize) -> Option<usize> {
+        if self.flags & MAPPED != 0 {
+            for i in 0..4 {
+                if self.flags & WIDE != 0 {
+                    at += at & 1;
+                    self.map_idx[i] = (at - map) / 2 + 1;
+                    at += 2 * u16_le(bytes, at)? as usize + 2;
+                } else {
+                    self.map_idx[i] = at - map + 1;
+                    at += *bytes.get(at)? as usize + 1;
+                }
+            }
+        }
+        Some(at)
+    }
+
+    fn block_length(&self, bytes: &[u8], block: usize) -> Option<i64> {
+        if block >= self.block_length_size {
+            return None;
+        }
+        u16_le(bytes, self.block_lengths + 2 * block).map(i64::from)
+    }
+
+    // Finds the block holding the value from the sparse index, and decodes
+    // symbols from its start until the one holding the value
+    fn decompress(&self, bytes: &[u8], index: u64) -> Option<u16> {
+        if self.flags & SINGLE_VALUE != 0 {
+            return Some(self.min_sym_len as u16);
+        }
+        let entry = self.sparse_index + 6 * (index / self.span) as usize;
+        let mut block = u32_le(bytes, entry)? as usize;
+        let mut offset =
+            u16_le(bytes, entry + 4)? as i64 + (index % self.span) as i64 - (self.span / 2) as i64;
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += self.block_length(bytes, block)? + 1;
+        }
+        while offset > self.block_length(bytes, block)? {
+            offset -= self.block_length(bytes, block)? + 1;
+            block += 1;
+        }
+        let mut offset = offset as usize;
+
+        let mut at = self.data + block * self.block_size;
+        let mut buffer = big_endian(bytes, at, 8);
+        let mut buffer_size: usize = 64;
+        at += 8;
+        let mut symbol = loop {
+            let mut len = 0;
+            while buffer < self.base64[len] {
+                len += 1;
+                if len == self.base64.len() {
+                    return None;
+                }
+            }
+            let bits = len + self.min_sym_len as usize;
+            let symbol = ((buffer - self.base64[len]) >> (64 - bits)) as usize
+                + u16_le(bytes, self.lowest_sym + 2 * len)? as usize;
+            let symlen = *self.symlen.get(symbol)?;
+            if offset <= symlen {
+                break symbol;
+            }
+            offset -= symlen + 1;
+            buffer = buffer.checked_shl(bits as u32).unwrap_or(0);
+            buffer_size = buffer_size.checked_sub(bits)?;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= big_endian(bytes, at, 4) << (64 - buffer_size);
+                at += 4;
+            }
+        };
+
+        // Walk down the pairs to the value
+        while self.symlen[symbol] > 0 {
+            let (left, right) = self.children(bytes, symbol)?;
+            if offset <= self.symlen[left] {
+                symbol = left;
+            } else {
+                offset -= self.symlen[left] + 1;
+                symbol = right;
+            }
+        }
+        Some(self.children(bytes, symbol)?.0 as u16)
+    }
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+// The decoder reads ahead, past the end of the last block
+fn big_endian(bytes: &[u8], at: usize, len: usize) -> u64 {
+    (at..at + len).fold(0, |value, i| {
+        value << 8 | *bytes.get(i).unwrap_or(&0) as u64
+    })
+}
+
+fn piece_code(piece: Piece) -> u8 {
+    let code = match piece.title() {
+        PieceType::Pawn => 1,
+        PieceType::Knight => 2,
+        PieceType::Bishop => 3,
+        PieceType::Rook => 4,
+        PieceType::Queen => 5,
+        PieceType::King => 6,
+    };
+    if piece.color() == Color::Black {
+        code + 8
+    } else {
+        code
+    }
+}
+
+// How far above the a1-h8 diagonal a square is
+fn off_diagonal(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+// The tables used to turn positions into indexes
+struct Encoding {
+    // Squares below the a1-h8 diagonal
+    map_b1h1h7: [u64; 64],
+    // Squares in the a1-d1-d4 triangle, the diagonal last
+    map_a1d1d4: [u64; 64],
+    // Two kings with the first in the triangle, u64::MAX if impossible
+    map_kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; 7],
+    // Pawn squares, ordered from the front and the edges of the a to d files
+    map_pawns: [u64; 64],
+    lead_pawn_idx: [[u64; 64]; 7],
+    lead_pawns_size: [[u64; 4]; 7],
+}
+
+impl Encoding {
+    fn new() -> Encoding {
+        let mut encoding = Encoding {
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[u64::MAX; 64]; 10],
+            binomial: [[0; 64]; 7],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; 7],
+            lead_pawns_size: [[0; 4]; 7],
+        };
+
+        let mut code = 0;
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                encoding.map_b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        let mut diagonal = Vec::new();
+        code = 0;
+        for square in 0..28 {
+            if square % 8 > 3 {
+                continue;
+            }
+            if off_diagonal(square) < 0 {
+                encoding.map_a1d1d4[square] = code;
+                code += 1;
+            } else if off_diagonal(square) == 0 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            encoding.map_a1d1d4[square] = code;
+            code += 1;
+        }
+
+        // Both kings on the diagonal are encoded last
+        let mut both_on_diagonal = Vec::new();
+        code = 0;
+        for idx in 0..10 {
+            for first in 0..28 {
+                // Squares outside the triangle map to 0 as well, b1 is the one
+                if encoding.map_a1d1d4[first] != idx as u64 || (idx == 0 && first != 1) {
+                    continue;
+                }
+                for second in 0..64 {
+                    let distance = (first / 8)
+                        .abs_diff(second / 8)
+                        .max((first % 8).abs_diff(second % 8));
+                    if distance <= 1 || (off_diagonal(first) == 0 && off_diagonal(second) > 0) {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                        both_on_diagonal.push((idx, second));
+                    } else {
+                        encoding.map_kk[idx][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, second) in both_on_diagonal {
+            encoding.map_kk[idx][second] = code;
+            code += 1;
+        }
+
+        encoding.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..7.min(n + 1) {
+                encoding.binomial[k][n] = if k > 0 {
+                    encoding.binomial[k - 1][n - 1]
+                } else {
+                    0
+                } + if k < n {
+                    encoding.binomial[k][n - 1]
+                } else {
+                    0
+                };
+            }
+        }
+
+        let mut available = 47;
+        for lead in 1..7 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if lead == 1 {
+                        encoding.map_pawns[square] = available;
+                        encoding.map_pawns[square ^ 7] = available.saturating_sub(1);
+                        available = available.saturating_sub(2);
+                    }
+                    encoding.lead_pawn_idx[lead][square] = idx;
+                    idx += encoding.binomial[lead - 1][encoding.map_pawns[square] as usize];
+                }
+                encoding.lead_pawns_size[lead][file] = idx;
+            }
+        }
+        encoding
+    }
+
+    fn binomial(&self, k: usize, n: usize) -> Option<u64> {
+        self.binomial.get(k)?.get(n).copied()
+    }
+}
+
+fn encoding() -> &'static Encoding {
+    static ENCODING: OnceLock<Encoding> = OnceLock::new();
+    ENCODING.get_or_init(Encoding::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::Krk;
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn writes_material_keys() {
+        let game = Game::from_fen("8/8/8/4k3/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(material_key(game.get_board()), "KRvK");
+        assert_eq!(
+            material_key(Game::new().get_board()),
+            "KQRRBBNNPPPPPPPPvKQRRBBNNPPPPPPPP"
+        );
+    }
+
+    #[test]
+    fn finds_tables_in_a_directory() {
+        let directory = env::temp_dir().join(format!("syzygy-test-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let mut wdl = WDL_MAGIC.to_vec();
+        wdl.extend_from_slice(&[0; 12]);
+        fs::write(directory.join("KRvK.rtbw"), &wdl).unwrap();
+        fs::write(directory.join("KQvK.rtbz"), [0u8; 16]).unwrap();
+
+        let tablebase = Tablebase::open(&directory).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(tablebase.max_pieces(), 3);
+        let game = Game::from_fen("8/8/8/4k3/8/8/8/r3K3 w - - 0 1").unwrap();
+        assert!(tablebase.has_wdl_table(&game));
+        assert!(!tablebase.has_dtz_table(&game));
+    }
+
+    #[test]
+    fn probes_positions_drawn_without_tables() {
+        let tablebase = Tablebase::default();
+        let game = Game::from_fen("8/8/8/4k3/8/8/8/2B1K3 w - - 0 1").unwrap();
+        assert_eq!(tablebase.probe_wdl(&game), Some(Wdl::Draw));
+        assert_eq!(tablebase.probe_dtz(&game), Some(0));
+        let game = Game::from_fen("8/8/8/4k3/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(tablebase.probe_wdl(&game), None);
+        assert_eq!(tablebase.probe_wdl(&Game::new()), None);
+    }
+
+    #[test]
+    fn builds_the_encoding_tables() {
+        let encoding = encoding();
+        let kings = encoding.map_kk.iter().flatten();
+        assert_eq!(kings.filter(|&&code| code != u64::MAX).count(), 462);
+        assert_eq!(encoding.map_a1d1d4.iter().max(), Some(&9));
+        assert_eq!(encoding.map_pawns[8], 47);
+        assert_eq!(encoding.binomial[3][10], 120);
+    }
+
+    #[test]
+    fn decodes_generated_krvk_tables() {
+        let directory = env::temp_dir().join(format!("syzygy-krvk-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        testing::write_krvk_tables(&directory);
+        let tablebase = Tablebase::open(&directory).unwrap();
+
+        // Every index is decoded once, and every position is checked by its index
+        let solution = testing::solve();
+        let board = testing::krvk_board(4, 0, 36);
+        let wdl = tablebase.table(&board, true).unwrap();
+        let dtz = tablebase.table(&board, false).unwrap();
+        let decode = |table: &Table, side: usize| -> Vec<u16> {
+            let part = &table.parts[side][0];
+            (0..*part.group_idx.last().unwrap())
+                .map(|index| part.decompress(&table.bytes, index).unwrap())
+                .collect()
+        };
+        let results = [decode(&wdl, 0), decode(&wdl, 1)];
+        let distances = decode(&dtz, 0);
+        for p in 0..solution.white.len() {
+            let board = testing::krvk_board(p / 4096, p / 64 % 64, p % 64);
+            for (black_to_move, result) in [(false, solution.white[p]), (true, solution.black[p])] {
+                let expected = match result {
+                    Krk::Illegal => continue,
+                    Krk::Draw => Wdl::Draw,
+                    Krk::Mate(_) if black_to_move => Wdl::Loss,
+                    Krk::Mate(_) => Wdl::Win,
+                };
+                let (_, _, index) = wdl.index(&board, black_to_move).unwrap().unwrap();
+                let value = results[black_to_move as usize][index as usize];
+                assert_eq!(Wdl::from_value(value), Some(expected));
+                match (black_to_move, result) {
+                    (false, Krk::Mate(plies)) => {
+                        let (file, _, index) = dtz.index(&board, false).unwrap().unwrap();
+                        let value = distances[index as usize];
+                        assert_eq!(dtz.map_score(file, value, Wdl::Win), Some(plies as i32));
+                    }
+                    (true, _) => assert!(dtz.index(&board, true).unwrap().is_none()),
+                    _ => (),
+                }
+            }
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn probes_generated_krvk_tables() {
+        let directory = env::temp_dir().join(format!("syzygy-probe-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        testing::write_krvk_tables(&directory);
+        let tablebase = Tablebase::open(&directory).unwrap();
+
+        // The same position with the colours swapped is found in KRvK too
+        let solution = testing::solve();
+        let plies = match solution.white[testing::position(4, 0, 36)] {
+            Krk::Mate(plies) => plies as i32,
+            result => panic!("{:?}", result),
+        };
+        for fen in &[
+            "8/8/8/4k3/8/8/8/R3K3 w - - 0 1",
+            "r3k3/8/8/8/4K3/8/8/8 b - - 0 1",
+        ] {
+            let game = Game::from_fen(fen).unwrap();
+            assert_eq!(tablebase.probe_wdl(&game), Some(Wdl::Win));
+            assert_eq!(tablebase.probe_dtz(&game), Some(plies));
+        }
+
+        // Black to move isn't stored in the DTZ table, so it looks one move ahead
+        for p in (0..solution.black.len()).step_by(4099) {
+            let (white_king, rook, black_king) = (p / 4096, p / 64 % 64, p % 64);
+            let expected = match solution.black[p] {
+                Krk::Illegal => continue,
+                Krk::Draw => (Wdl::Draw, 0),
+                Krk::Mate(plies) => (Wdl::Loss, -(plies as i32)),
+            };
+            let game = testing::krvk_game(white_king, rook, black_king, Color::Black);
+            let probed = (tablebase.probe_wdl(&game), tablebase.probe_dtz(&game));
+            assert_eq!(probed, (Some(expected.0), Some(expected.1)));
+        }
+
+        // Black can take the rook, or is stalemated
+        for fen in &[
+            "8/8/8/8/8/2k5/1R6/7K b - - 0 1",
+            "k7/8/K7/8/8/8/8/1R6 b - - 0 1",
+        ] {
+            let game = Game::from_fen(fen).unwrap();
+            assert_eq!(tablebase.probe_wdl(&game), Some(Wdl::Draw));
+            assert_eq!(tablebase.probe_dtz(&game), Some(0));
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn decodes_generated_kpvk_tables() {
+        let directory = env::temp_dir().join(format!("syzygy-kpvk-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        testing::write_kpvk_table(&directory);
+        let tablebase = Tablebase::open(&directory).unwrap();
+
+        // Every part of every file is decoded once
+        let solution = testing::solve_kpvk();
+        let table = tablebase
+            .table(&testing::kpvk_board(4, 12, 60), true)
+            .unwrap();
+        let decoded: Vec<Vec<Vec<u16>>> = table
+            .parts
+            .iter()
+            .map(|files| {
+                files
+                    .iter()
+                    .map(|part| {
+                        (0..*part.group_idx.last().unwrap())
+                            .map(|index| part.decompress(&table.bytes, index).unwrap())
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        for p in 0..solution.white.len() {
+            let board = testing::kpvk_board(p / 4096, p / 64 % 64, p % 64);
+            for (black_to_move, result) in [(false, solution.white[p]), (true, solution.black[p])] {
+                let expected = match (result, black_to_move) {
+                    (None, _) => continue,
+                    (Some(false), _) => Wdl::Draw,
+                    (Some(true), false) => Wdl::Win,
+                    (Some(true), true) => Wdl::Loss,
+                };
+                let (file, _, index) = table.index(&board, black_to_move).unwrap().unwrap();
+                let value = decoded[black_to_move as usize][file][index as usize];
+                assert_eq!(Wdl::from_value(value), Some(expected));
+            }
+        }
+
+        // Black's pawn is found in KPvK with the board flipped
+        for p in (0..solution.white.len()).step_by(4099) {
+            let (white_king, pawn, black_king) = (p / 4096, p / 64 % 64, p % 64);
+            let expected = match solution.white[p] {
+                None => continue,
+                Some(true) => Wdl::Win,
+                Some(false) => Wdl::Draw,
+            };
+            let mut board = [None; 64];
+            board[black_king ^ 56] = Some(Piece::new(Color::White, PieceType::King, true));
+            board[pawn ^ 56] = Some(Piece::new(Color::Black, PieceType::Pawn, true));
+            board[white_king ^ 56] = Some(Piece::new(Color::Black, PieceType::King, true));
+            let game = Game::from_parts(
+                board,
+                Color::Black,
+                CastlingRights::default(),
+                None,
+                0,
+                1,
+                false,
+            );
+            assert_eq!(tablebase.probe_wdl(&game), Some(expected));
+        }
+
+        probes_pawn_endgames(&tablebase);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    // Run with the real KRvK and KPvK tables from the Syzygy tablebases in
+    // a directory, e.g. `SYZYGY_PATH=~/syzygy cargo test -- --ignored`
+    #[test]
+    #[ignore = "needs the Syzygy KRvK and KPvK tables in SYZYGY_PATH"]
+    fn probes_syzygy_tables() {
+        let tablebase = Tablebase::open(env::var("SYZYGY_PATH").unwrap()).unwrap();
+        probes_pawn_endgames(&tablebase);
+
+        // Tables that store moves instead of half moves can be one over
+        let solution = testing::solve();
+        for p in (0..solution.white.len()).step_by(4099) {
+            let (white_king, rook, black_king) = (p / 4096, p / 64 % 64, p % 64);
+            let game = testing::krvk_game(white_king, rook, black_king, Color::White);
+            match solution.white[p] {
+                Krk::Illegal => continue,
+                Krk::Draw => assert_eq!(tablebase.probe_wdl(&game), Some(Wdl::Draw)),
+                Krk::Mate(plies) => {
+                    assert_eq!(tablebase.probe_wdl(&game), Some(Wdl::Win));
+                    let dtz = tablebase.probe_dtz(&game).unwrap();
+                    assert!((plies as i32..=plies as i32 + 1).contains(&dtz));
+                }
+            }
+        }
+    }
+
+    // Textbook king and pawn endgames
+    fn probes_pawn_endgames(tablebase: &Tablebase) {
+        for (fen, expected) in &[
+            // The king on the sixth rank in front of the pawn always wins
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Wdl::Win),
+            ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", Wdl::Loss),
+            // The pawn is outside the square of the king, on either wing
+            ("7k/8/8/8/P7/8/8/7K w - - 0 1", Wdl::Win),
+            ("k7/8/8/8/7P/8/8/K7 w - - 0 1", Wdl::Win),
+            ("7k/8/8/p7/8/8/8/7K b - - 0 1", Wdl::Win),
+            // The king in front of a rook pawn holds the draw
+            ("k7/8/8/8/8/8/P7/K7 w - - 0 1", Wdl::Draw),
+            ("k7/8/8/8/8/8/P7/K7 b - - 0 1", Wdl::Draw),
+            // Stalemate
+            ("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1", Wdl::Draw),
+        ] {
+            let game = Game::from_fen(fen).unwrap();
+            assert_eq!(tablebase.probe_wdl(&game), Some(*expected), "{}", fen);
+        }
+    }
+}
+
+// Solves KRvK by retrograde analysis and writes the results as Syzygy
+// tables, so probing can be tested without downloading tables
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // The pieces as the tables are encoded: white's king and rook, black's king
+    const PIECES: [u8; 3] = [6, 4, 14];
+    const SIZE: usize = 31332;
+
+    /// The result of a KRvK position with perfect play.
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub(crate) enum Krk {
+        Illegal,
+        Draw,
+        // White mates in this many half moves
+        Mate(u8),
+    }
+
+    /// The results with white and with black to move, indexed by the squares
+    /// of the white king, the rook and the black king.
+    pub(crate) struct Solution {
+        pub white: Vec<Krk>,
+        pub black: Vec<Krk>,
+    }
+
+    pub(crate) fn position(white_king: usize, rook: usize, black_king: usize) -> usize {
+        white_king * 4096 + rook * 64 + black_king
+    }
+
+    pub(crate) fn krvk_game(
+        white_king: usize,
+        rook: usize,
+        black_king: usize,
+        color: Color,
+    ) -> Game {
+        Game::from_parts(
+            krvk_board(white_king, rook, black_king),
+            color,
+            CastlingRights::default(),
+            None,
+            0,
+            1,
+            false,
+        )
+    }
+
+    pub(crate) fn krvk_board(
+        white_king: usize,
+        rook: usize,
+        black_king: usize,
+    ) -> [Option<Piece>; 64] {
+        let mut board = [None; 64];
+        board[white_king] = Some(Piece::new(Color::White, PieceType::King, true));
+        board[rook] = Some(Piece::new(Color::White, PieceType::Rook, true));
+        board[black_king] = Some(Piece::new(Color::Black, PieceType::King, true));
+        board
+    }
+
+    fn adjacent(a: usize, b: usize) -> bool {
+        a != b && (a / 8).abs_diff(b / 8) <= 1 && (a % 8).abs_diff(b % 8) <= 1
+    }
+
+    // If the rook attacks the target, with the white king the only blocker
+    fn rook_attacks(rook: usize, target: usize, blocker: usize) -> bool {
+        let between = |a: usize, b: usize, c: usize| a.min(c) < b && b < a.max(c);
+        if rook == target {
+            false
+        } else if rook / 8 == target / 8 {
+            !(blocker / 8 == rook / 8 && between(rook % 8, blocker % 8, target % 8))
+        } else if rook % 8 == target % 8 {
+            !(blocker % 8 == rook % 8 && between(rook / 8, blocker / 8, target / 8))
+        } else {
+            false
+        }
+    }
+
+    // The squares the rook can have come from, without passing the kings
+    fn rook_origins(rook: usize, kings: [usize; 2]) -> Vec<usize> {
+        let mut origins = Vec::new();
+        for (file_step, rank_step) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let (mut file, mut rank) = ((rook % 8) as i32, (rook / 8) as i32);
+            loop {
+                file += file_step;
+                rank += rank_step;
+                let square = (rank * 8 + file) as usize;
+                if !(0..8).contains(&file) || !(0..8).contains(&rank) || kings.contains(&square) {
+                    break;
+                }
+                origins.push(square);
+            }
+        }
+        origins
+    }
+
+    /// Solves every KRvK position, going back from the mates.
+    pub(crate) fn solve() -> &'static Solution {
+        static SOLUTION: OnceLock<Solution> = OnceLock::new();
+        SOLUTION.get_or_init(|| {
+            let neighbours: Vec<Vec<usize>> = (0..64)
+                .map(|square| (0..64).filter(|&other| adjacent(square, other)).collect())
+                .collect();
+            let mut white = vec![Krk::Illegal; 64 * 64 * 64];
+            let mut black = white.clone();
+            // The black moves that aren't known to lose yet, u8::MAX when
+            // black can take the rook
+            let mut moves_left = vec![0u8; white.len()];
+            let mut queue = VecDeque::new();
+            for (white_king, rook, black_king) in
+                (0..64 * 64 * 64).map(|p| (p / 4096, p / 64 % 64, p % 64))
+            {
+                if white_king == rook
+                    || white_king == black_king
+                    || rook == black_king
+                    || adjacent(white_king, black_king)
+                {
+                    continue;
+                }
+                let p = position(white_king, rook, black_king);
+                let check = rook_attacks(rook, black_king, white_king);
+                if !check {
+                    white[p] = Krk::Draw;
+                }
+                black[p] = Krk::Draw;
+                let mut moves = 0;
+                for &to in &neighbours[black_king] {
+                    if to == white_king || adjacent(to, white_king) {
+                        continue;
+                    }
+                    if to == rook {
+                        if !adjacent(rook, white_king) {
+                            moves = u8::MAX;
+                            break;
+                        }
+                    } else if !rook_attacks(rook, to, white_king) {
+                        moves += 1;
+                    }
+                }
+                if moves == 0 && check {
+                    black[p] = Krk::Mate(0);
+                    queue.push_back((p, true));
+                }
+                moves_left[p] = moves;
+            }
+
+            while let Some((p, black_to_move)) = queue.pop_front() {
+                let (white_king, rook, black_king) = (p / 4096, p / 64 % 64, p % 64);
+                if black_to_move {
+                    let plies = match black[p] {
+                        Krk::Mate(plies) => plies + 1,
+                        _ => unreachable!(),
+                    };
+                    let mut previous: Vec<usize> = rook_origins(rook, [white_king, black_king])
+                        .into_iter()
+                        .map(|from| position(white_king, from, black_king))
+                        .collect();
+                    for &from in &neighbours[white_king] {
+                        if from != rook && from != black_king && !adjacent(from, black_king) {
+                            previous.push(position(from, rook, black_king));
+                        }
+                    }
+                    for q in previous {
+                        if white[q] == Krk::Draw {
+                            white[q] = Krk::Mate(plies);
+                            queue.push_back((q, false));
+                        }
+                    }
+                } else {
+                    let plies = match white[p] {
+                        Krk::Mate(plies) => plies + 1,
+                        _ => unreachable!(),
+                    };
+                    for &from in &neighbours[black_king] {
+                        if from == white_king || from == rook || adjacent(from, white_king) {
+                            continue;
+                        }
+                        let q = position(white_king, rook, from);
+                        if black[q] != Krk::Draw || moves_left[q] == u8::MAX {
+                            continue;
+                        }
+                        moves_left[q] -= 1;
+                        if moves_left[q] == 0 {
+                            black[q] = Krk::Mate(plies);
+                            queue.push_back((q, true));
+                        }
+                    }
+                }
+            }
+            Solution { white, black }
+        })
+    }
+
+    /// Writes `KRvK.rtbw` and `KRvK.rtbz` into a directory.
+    pub(crate) fn write_krvk_tables(directory: &Path) {
+        let solution = solve();
+        let wdl_table = empty_table(true);
+        let dtz_table = empty_table(false);
+        let mut wdl = [vec![None; SIZE], vec![None; SIZE]];
+        let mut dtz = vec![None; SIZE];
+        for p in 0..solution.white.len() {
+            let board = krvk_board(p / 4096, p / 64 % 64, p % 64);
+            for (black_to_move, result) in [(false, solution.white[p]), (true, solution.black[p])] {
+                let value = match result {
+                    Krk::Illegal => continue,
+                    Krk::Draw => 2,
+                    Krk::Mate(_) if black_to_move => 0,
+                    Krk::Mate(_) => 4,
+                };
+                let (_, _, index) = wdl_table.index(&board, black_to_move).unwrap().unwrap();
+                store(&mut wdl[black_to_move as usize][index as usize], value);
+                if let (false, Krk::Mate(plies)) = (black_to_move, result) {
+                    let (_, _, index) = dtz_table.index(&board, false).unwrap().unwrap();
+                    store(&mut dtz[index as usize], plies as u16);
+                }
+            }
+        }
+
+        // DTZ values are stored as indexes into a map of the distances
+        let mut distances: Vec<u16> = dtz.iter().flatten().map(|plies| plies - 1).collect();
+        distances.sort_unstable();
+        distances.dedup();
+        let dtz: Vec<Option<u16>> = dtz
+            .iter()
+            .map(|plies| plies.map(|plies| distances.binary_search(&(plies - 1)).unwrap() as u16))
+            .collect();
+        let mut map = vec![distances.len() as u8];
+        map.extend(distances.iter().map(|&distance| distance as u8));
+        map.extend_from_slice(&[0, 0, 0]);
+
+        // Split (not symmetric) and without pawns, with both sides' pieces
+        // in the same order
+        let mut pieces = vec![1, 0];
+        pieces.extend(PIECES.iter().map(|&piece| piece | piece << 4));
+        let wdl_parts = [compress(0, &wdl[0]), compress(0, &wdl[1])];
+        let dtz_parts = [compress(MAPPED | WIN_PLIES | LOSS_PLIES, &dtz)];
+        fs::write(
+            directory.join("KRvK.rtbw"),
+            table_file(WDL_MAGIC, &pieces, &wdl_parts, None),
+        )
+        .unwrap();
+        fs::write(
+            directory.join("KRvK.rtbz"),
+            table_file(DTZ_MAGIC, &pieces, &dtz_parts, Some(&map)),
+        )
+        .unwrap();
+    }
+
+    /// The results of KPvK positions with white and with black to move,
+    /// indexed like KRvK with the pawn in place of the rook. None for
+    /// illegal positions, otherwise if white wins.
+    pub(crate) struct KpkSolution {
+        pub white: Vec<Option<bool>>,
+        pub black: Vec<Option<bool>>,
+    }
+
+    pub(crate) fn kpvk_board(
+        white_king: usize,
+        pawn: usize,
+        black_king: usize,
+    ) -> [Option<Piece>; 64] {
+        let mut board = [None; 64];
+        board[white_king] = Some(Piece::new(Color::White, PieceType::King, true));
+        board[pawn] = Some(Piece::new(Color::White, PieceType::Pawn, true));
+        board[black_king] = Some(Piece::new(Color::Black, PieceType::King, true));
+        board
+    }
+
+    // If a queen attacks the target, with the white king the only blocker
+    fn queen_attacks(queen: usize, target: usize, blocker: usize) -> bool {
+        let (file, rank) = ((queen % 8) as i32, (queen / 8) as i32);
+        let (file_step, rank_step) = ((target % 8) as i32 - file, (target / 8) as i32 - rank);
+        if queen == target
+            || !(file_step == 0 || rank_step == 0 || file_step.abs() == rank_step.abs())
+        {
+            return false;
+        }
+        let (file_step, rank_step) = (file_step.signum(), rank_step.signum());
+        let mut square = queen;
+        loop {
+            square = (square as i32 + rank_step * 8 + file_step) as usize;
+            if square == target {
+                return true;
+            }
+            if square == blocker {
+                return false;
+            }
+        }
+    }
+
+    // If white wins after promoting on the square with black to move. A
+    // rook or a queen wins unless black takes it or is stalemated.
+    fn promotion_wins(white_king: usize, square: usize, black_king: usize) -> bool {
+        if let Krk::Mate(_) = solve().black[position(white_king, square, black_king)] {
+            return true;
+        }
+        if adjacent(square, black_king) && !adjacent(square, white_king) {
+            return false;
+        }
+        let escapes = (0..64).any(|to| {
+            adjacent(to, black_king)
+                && !adjacent(to, white_king)
+                && to != square
+                && !queen_attacks(square, to, white_king)
+        });
+        escapes || queen_attacks(square, black_king, white_king)
+    }
+
+    /// Solves every KPvK position. Pawns only move forwards, so the pawn
+    /// squares are solved from the seventh rank back to the second, each
+    /// going back from the mates and the positions won by pawn moves.
+    pub(crate) fn solve_kpvk() -> &'static KpkSolution {
+        static SOLUTION: OnceLock<KpkSolution> = OnceLock::new();
+        SOLUTION.get_or_init(|| {
+            let neighbours: Vec<Vec<usize>> = (0..64)
+                .map(|square| (0..64).filter(|&other| adjacent(square, other)).collect())
+                .collect();
+            let mut white = vec![None; 64 * 64 * 64];
+            let mut black = white.clone();
+            for pawn in (8..56).rev() {
+                let attacked = |square: usize| {
+                    square / 8 == pawn / 8 + 1 && (square % 8).abs_diff(pawn % 8) == 1
+                };
+                // The black moves that aren't known to lose yet, u8::MAX when
+                // black can take the pawn
+                let mut moves_left = vec![0u8; 64 * 64];
+                let mut queue = VecDeque::new();
+                for (white_king, black_king) in (0..64 * 64).map(|k| (k / 64, k % 64)) {
+                    if white_king == pawn
+                        || black_king == pawn
+                        || white_king == black_king
+                        || adjacent(white_king, black_king)
+                    {
+                        continue;
+                    }
+                    let p = position(white_king, pawn, black_king);
+                    let mut moves = 0;
+                    for &to in &neighbours[black_king] {
+                        if adjacent(to, white_king) || attacked(to) {
+                            continue;
+                        }
+                        if to == pawn {
+                            moves = u8::MAX;
+                            break;
+                        }
+                        moves += 1;
+                    }
+                    let check = attacked(black_king);
+                    black[p] = Some(moves == 0 && check);
+                    if moves == 0 && check {
+                        queue.push_back((p, true));
+                    }
+                    moves_left[white_king * 64 + black_king] = moves;
+                    if check {
+                        continue;
+                    }
+
+                    let free = |square: usize| square != white_king && square != black_king;
+                    let forward = pawn + 8;
+                    let wins = free(forward)
+                        && if forward >= 56 {
+                            promotion_wins(white_king, forward, black_king)
+                        } else {
+                            black[position(white_king, forward, black_king)] == Some(true)
+                                || (pawn < 16
+                                    && free(forward + 8)
+                                    && black[position(white_king, forward + 8, black_king)]
+                                        == Some(true))
+                        };
+                    white[p] = Some(wins);
+                    if wins {
+                        queue.push_back((p, false));
+                    }
+                }
+
+                while let Some((p, black_to_move)) = queue.pop_front() {
+                    let (white_king, black_king) = (p / 4096, p % 64);
+                    if black_to_move {
+                        for &from in &neighbours[white_king] {
+                            if from == pawn || adjacent(from, black_king) || attacked(black_king) {
+                                continue;
+                            }
+                            let q = position(from, pawn, black_king);
+                            if white[q] == Some(false) {
+                                white[q] = Some(true);
+                                queue.push_back((q, false));
+                            }
+                        }
+                    } else {
+                        for &from in &neighbours[black_king] {
+                            if from == white_king || from == pawn || adjacent(from, white_king) {
+                                continue;
+                            }
+                            let q = position(white_king, pawn, from);
+                            let left = &mut moves_left[white_king * 64 + from];
+                            if black[q] != Some(false) || *left == u8::MAX {
+                                continue;
+                            }
+                            *left -= 1;
+                            if *left == 0 {
+                                black[q] = Some(true);
+                                queue.push_back((q, true));
+                            }
+                        }
+                    }
+                }
+            }
+            KpkSolution { white, black }
+        })
+    }
+
+    /// Writes `KPvK.rtbw` into a directory. The indexes are worked out here
+    /// for these three pieces as the Syzygy format describes them, rather
+    /// than with `Table::index`, so the decoder is checked against them.
+    pub(crate) fn write_kpvk_table(directory: &Path) {
+        let solution = solve_kpvk();
+        // The pawn, then 63 squares for one king and 62 for the other
+        const FILE_SIZE: usize = 6 * 63 * 62;
+        let mut wdl = vec![vec![None; FILE_SIZE]; 8];
+        for p in 0..solution.white.len() {
+            let squares = [p / 64 % 64, p / 4096, p % 64];
+            for (black_to_move, result) in [(false, solution.white[p]), (true, solution.black[p])] {
+                let value = match (result, black_to_move) {
+                    (None, _) => continue,
+                    (Some(false), _) => 2,
+                    (Some(true), false) => 4,
+                    (Some(true), true) => 0,
+                };
+                let (file, index) = kpvk_index(squares, black_to_move);
+                store(
+                    &mut wdl[file * 2 + black_to_move as usize][index as usize],
+                    value,
+                );
+            }
+        }
+
+        // Split with pawns. With white to move the pieces are encoded as the
+        // pawn, white's king and black's king, and the pawn's index is the
+        // first factor. With black to move the kings are swapped and the
+        // pawn's index is the last factor.
+        let mut pieces = vec![1 | 2];
+        for _ in 0..4 {
+            pieces.extend_from_slice(&[0x20, 1 | 1 << 4, 6 | 14 << 4, 14 | 6 << 4]);
+        }
+        let parts: Vec<Compressed> = wdl.iter().map(|values| compress(0, values)).collect();
+        fs::write(
+            directory.join("KPvK.rtbw"),
+            table_file(WDL_MAGIC, &pieces, &parts, None),
+        )
+        .unwrap();
+    }
+
+    // The file of the pawn and the index of a position from the squares of
+    // the pawn, white's king and black's king
+    fn kpvk_index(mut squares: [usize; 3], black_to_move: bool) -> (usize, u64) {
+        if squares[0] % 8 > 3 {
+            for square in squares.iter_mut() {
+                *square ^= 7;
+            }
+        }
+        let [pawn, white_king, black_king] = squares;
+        let (first, second) = if black_to_move {
+            (black_king, white_king)
+        } else {
+            (white_king, black_king)
+        };
+        // The kings skip the squares of the pieces before them
+        let first_index = (first - (pawn < first) as usize) as u64;
+        let second_index = (second - (pawn < second) as usize - (first < second) as usize) as u64;
+        let rank = (pawn / 8 - 1) as u64;
+        let index = if black_to_move {
+            rank * 63 * 62 + first_index + 63 * second_index
+        } else {
+            rank + 6 * first_index + 6 * 63 * second_index
+        };
+        (pawn % 8, index)
+    }
+
+    // Positions mirroring each other share an index, so they must agree
+    fn store(slot: &mut Option<u16>, value: u16) {
+        assert!(
+            slot.is_none_or(|stored| stored == value),
+            "{:?} and {}",
+            slot,
+            value
+        );
+        *slot = Some(value);
+    }
+
+    // A KRvK table without values, to find the indexes of positions
+    fn empty_table(wdl: bool) -> Table {
+        let mut table = Table {
+            bytes: Vec::new(),
+            white: String::from("KR"),
+            wdl,
+            symmetric: false,
+            has_pawns: false,
+            pawn_count: [0, 0],
+            has_unique_pieces: true,
+            parts: Vec::new(),
+            map: 0,
+        };
+        let mut part = Part {
+            pieces: PIECES.to_vec(),
+            ..Part::default()
+        };
+        table.set_groups(&mut part, [0, 0xf], 0).unwrap();
+        assert_eq!(part.group_idx, [1, SIZE as u64]);
+        table.parts = vec![vec![part]; if wdl { 2 } else { 1 }];
+        table
+    }
+
+    // A compressed part: its header from the flags, the sparse index, the
+    // block lengths and the blocks
+    struct Compressed {
+        header: Vec<u8>,
+        sparse: Vec<u8>,
+        lengths: Vec<u8>,
+        data: Vec<u8>,
+    }
+
+    enum Symbol {
+        Value(u16),
+        Pair(usize, usize),
+    }
+
+    // Compresses values with a Huffman code of the values and of runs of
+    // two and four of the most common value, into blocks of 32 bytes.
+    // Values that don't matter get the most common value.
+    fn compress(flags: u8, values: &[Option<u16>]) -> Compressed {
+        const BLOCK_BITS: usize = 5;
+        const SPAN_BITS: usize = 6;
+        let mut counts = HashMap::new();
+        for value in values.iter().flatten() {
+            *counts.entry(*value).or_insert(0) += 1;
+        }
+        let common = *counts
+            .iter()
+            .max_by_key(|(&value, &count)| (count, value))
+            .unwrap()
+            .0;
+        let values: Vec<u16> = values.iter().map(|value| value.unwrap_or(common)).collect();
+
+        let mut distinct: Vec<u16> = counts.keys().copied().collect();
+        distinct.sort_unstable();
+        let mut symbols: Vec<Symbol> = distinct.iter().map(|&value| Symbol::Value(value)).collect();
+        let leaf = distinct.binary_search(&common).unwrap();
+        symbols.push(Symbol::Pair(leaf, leaf));
+        symbols.push(Symbol::Pair(symbols.len() - 1, symbols.len() - 1));
+
+        // Symbols and how many values they stand for
+        let mut tokens = Vec::new();
+        let mut at = 0;
+        while at < values.len() {
+            let run = values[at..]
+                .iter()
+                .take(4)
+                .take_while(|&&value| value == common)
+                .count();
+            let token = match run {
+                4 => (symbols.len() - 1, 4),
+                2 | 3 => (symbols.len() - 2, 2),
+                _ => (distinct.binary_search(&values[at]).unwrap(), 1),
+            };
+            tokens.push(token);
+            at += token.1;
+        }
+
+        // Huffman code lengths by merging the rarest symbols
+        let mut frequency = vec![0; symbols.len()];
+        for &(symbol, _) in &tokens {
+            frequency[symbol] += 1;
+        }
+        let mut lengths = vec![0usize; symbols.len()];
+        let mut nodes: Vec<(usize, Vec<usize>)> = (0..symbols.len())
+            .filter(|&symbol| frequency[symbol] > 0)
+            .map(|symbol| (frequency[symbol], vec![symbol]))
+            .collect();
+        if nodes.len() == 1 {
+            lengths[nodes[0].1[0]] = 1;
+        }
+        while nodes.len() > 1 {
+            nodes.sort_by_key(|node| std::cmp::Reverse(node.0));
+            let (first, mut merged) = nodes.pop().unwrap();
+            let (second, more) = nodes.pop().unwrap();
+            merged.extend(more);
+            for &symbol in &merged {
+                lengths[symbol] += 1;
+            }
+            nodes.push((first + second, merged));
+        }
+
+        // Symbols are numbered from the longest codes, unused ones last
+        let mut order: Vec<usize> = (0..symbols.len()).collect();
+        order.sort_by_key(|&symbol| (lengths[symbol] == 0, std::cmp::Reverse(lengths[symbol])));
+        let mut number = vec![0; symbols.len()];
+        for (n, &symbol) in order.iter().enumerate() {
+            number[symbol] = n;
+        }
+        let used: Vec<usize> = lengths.iter().copied().filter(|&len| len > 0).collect();
+        let (min, max) = (*used.iter().min().unwrap(), *used.iter().max().unwrap());
+        let count = |len: usize| used.iter().filter(|&&other| other == len).count();
+        let mut lowest = vec![0; max - min + 1];
+        let mut base = vec![0; max - min + 1];
+        for i in (0..max - min).rev() {
+            lowest[i] = lowest[i + 1] + count(min + i + 1);
+            base[i] = (base[i + 1] + count(min + i + 1)) / 2;
+        }
+
+        // Fill the blocks with the codes, most significant bit first
+        let block_size = 1 << BLOCK_BITS;
+        let mut data = Vec::new();
+        let mut block_values = Vec::new();
+        let mut bits = 0;
+        let mut in_block = 0;
+        let mut block = vec![0u8; block_size];
+        for &(symbol, count) in &tokens {
+            let len = lengths[symbol];
+            if bits + len > 8 * block_size {
+                data.extend_from_slice(&block);
+                block = vec![0u8; block_size];
+                block_values.push(in_block);
+                bits = 0;
+                in_block = 0;
+            }
+            let i = len - min;
+            let code = base[i] + number[symbol] - lowest[i];
+            for bit in (0..len).rev() {
+                if code >> bit & 1 == 1 {
+                    block[bits / 8] |= 0x80 >> (bits % 8);
+                }
+                bits += 1;
+            }
+            in_block += count;
+        }
+        data.extend_from_slice(&block);
+        block_values.push(in_block);
+
+        let mut lengths_bytes = Vec::new();
+        let mut starts = Vec::new();
+        let mut start = 0;
+        for &values in &block_values {
+            lengths_bytes.extend_from_slice(&(values as u16 - 1).to_le_bytes());
+            starts.push(start);
+            start += values;
+        }
+        // Each sparse entry points to the middle of its span
+        let span = 1 << SPAN_BITS;
+        let mut sparse = Vec::new();
+        for k in 0..values.len().div_ceil(span) {
+            let middle = k * span + span / 2;
+            let block = starts.iter().rposition(|&start| start <= middle).unwrap();
+            sparse.extend_from_slice(&(block as u32).to_le_bytes());
+            sparse.extend_from_slice(&((middle - starts[block]) as u16).to_le_bytes());
+        }
+
+        let mut header = vec![flags, BLOCK_BITS as u8, SPAN_BITS as u8, 0];
+        header.extend_from_slice(&(block_values.len() as u32).to_le_bytes());
+        header.extend_from_slice(&[max as u8, min as u8]);
+        for lowest in lowest {
+            header.extend_from_slice(&(lowest as u16).to_le_bytes());
+        }
+        header.extend_from_slice(&(symbols.len() as u16).to_le_bytes());
+        for &symbol in &order {
+            let (left, right) = match symbols[symbol] {
+                Symbol::Value(value) => (value as usize, 0xfff),
+                Symbol::Pair(left, right) => (number[left], number[right]),
+            };
+            header.extend_from_slice(&[
+                left as u8,
+                (left >> 8 | (right & 0xf) << 4) as u8,
+                (right >> 4) as u8,
+            ]);
+        }
+        if symbols.len() % 2 == 1 {
+            header.push(0);
+        }
+        Compressed {
+            header,
+            sparse,
+            lengths: lengths_bytes,
+            data,
+        }
+    }
+
+    // A table file from the flags and pieces after the magic, and the
+    // parts in the order of the file
+    fn table_file(
+        magic: [u8; 4],
+        pieces: &[u8],
+        parts: &[Compressed],
+        map: Option<&[u8]>,
+    ) -> Vec<u8> {
+        let mut bytes = magic.to_vec();
+        bytes.extend_from_slice(pieces);
+        let align = |bytes: &mut Vec<u8>, to: usize| bytes.resize(bytes.len().div_ceil(to) * to, 0);
+        align(&mut bytes, 2);
+        for part in parts {
+            bytes.extend_from_slice(&part.header);
+        }
+        if let Some(map) = map {
+            bytes.extend_from_slice(map);
+            align(&mut bytes, 2);
+        }
+        for part in parts {
+            bytes.extend_from_slice(&part.sparse);
+        }
+        for part in parts {
+            bytes.extend_from_slice(&part.lengths);
+        }
+        for part in parts {
+            align(&mut bytes, 64);
+            bytes.extend_from_slice(&part.data);
+        }
+        bytes
+    }
+}
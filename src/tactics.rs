@@ -0,0 +1,307 @@
+use crate::attacks::*;
+use crate::engine::*;
+use crate::game::*;
+use crate::piece::*;
+
+/// A tactical pattern found on the board. Squares are written like `E4`,
+/// and the side a motif works for is the colour of its attacking piece.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Motif {
+    /// A piece attacks two or more enemy pieces that are either the king,
+    /// worth more than the attacker or undefended.
+    Fork {
+        attacker: String,
+        targets: Vec<String>,
+    },
+    /// A piece can't leave the line to the more valuable piece or king
+    /// behind it without exposing it.
+    Pin {
+        pinner: String,
+        pinned: String,
+        behind: String,
+    },
+    /// A valuable piece or king is attacked along a line, and moving it
+    /// away exposes the less valuable piece behind it.
+    Skewer {
+        attacker: String,
+        front: String,
+        behind: String,
+    },
+    /// Moving a piece uncovered an attack by a rook, bishop or queen.
+    /// Only found for moves.
+    DiscoveredAttack {
+        moved: String,
+        attacker: String,
+        target: String,
+    },
+    /// The king is checked by two pieces at once, so it has to move.
+    DoubleCheck { king: String, checkers: Vec<String> },
+    /// The king is stuck on its back rank behind its own pieces while the
+    /// opponent has a rook or queen.
+    BackRankWeakness { king: String },
+    /// A piece can be captured with a gain of material.
+    HangingPiece { square: String },
+}
+
+/// Finds the motifs in the current position of a game, for both sides.
+pub fn find_motifs(game: &Game) -> Vec<Motif> {
+    let board = game.get_board();
+    let mut motifs = Vec::new();
+    for color in &[Color::White, Color::Black] {
+        motifs.extend(forks(game, *color));
+        motifs.extend(lines(board, *color));
+        motifs.extend(double_check(board, *color));
+        motifs.extend(back_rank_weakness(board, *color));
+        motifs.extend(hanging_pieces(game, *color));
+    }
+    motifs
+}
+
+/// Finds the motifs a legal move creates: the ones in the position after
+/// the move that weren't there before, and the attacks it discovers.
+/// Returns None if the move isn't legal.
+pub fn find_motifs_of_move(game: &Game, from: &str, to: &str) -> Option<Vec<Motif>> {
+    let mut after = game.clone();
+    after.make_move(from.to_string(), to.to_string())?;
+    let before = find_motifs(game);
+    let mut motifs: Vec<Motif> = find_motifs(&after)
+        .into_iter()
+        .filter(|motif| !before.contains(motif))
+        .collect();
+
+    // Attacks that are new and not made by the moved piece were uncovered by it
+    let color = game.get_active_color();
+    let (old, new) = (game.get_board(), after.get_board());
+    let moved = as_coordinate(to);
+    for target in 0..64 {
+        if !new[target].is_some_and(|piece| piece.color() != color) {
+            continue;
+        }
+        for attacker in attackers_of(new, target, color) {
+            if attacker != moved && !attackers_of(old, target, color).contains(&attacker) {
+                motifs.push(Motif::DiscoveredAttack {
+                    moved: to.to_string(),
+                    attacker: as_standard_notation(&attacker),
+                    target: as_standard_notation(&target),
+                });
+            }
+        }
+    }
+    Some(motifs)
+}
+
+// Pieces of a colour attacking several valuable or undefended enemy pieces
+fn forks(game: &Game, color: Color) -> Vec<Motif> {
+    let board = game.get_board();
+    let mut motifs = Vec::new();
+    for attacker in pieces_of(board, color) {
+        let value = motif_value(board[attacker].unwrap().title());
+        let targets: Vec<usize> = pieces_of(board, color.opposite())
+            .into_iter()
+            .filter(|&target| {
+                attackers_of(board, target, color).contains(&attacker)
+                    && (motif_value(board[target].unwrap().title()) > value
+                        || !is_square_attacked(board, target, color.opposite()))
+            })
+            .collect();
+        if targets.len() >= 2 {
+            motifs.push(Motif::Fork {
+                attacker: as_standard_notation(&attacker),
+                targets: targets.iter().map(as_standard_notation).collect(),
+            });
+        }
+    }
+    motifs
+}
+
+// Pins and skewers by the rooks, bishops and queens of a colour
+fn lines(board: &[Option<Piece>; 64], color: Color) -> Vec<Motif> {
+    let mut motifs = Vec::new();
+    for attacker in pieces_of(board, color) {
+        let title = board[attacker].unwrap().title();
+        if !matches!(
+            title,
+            PieceType::Queen | PieceType::Rook | PieceType::Bishop
+        ) {
+            continue;
+        }
+        for (file_move, rank_move, _) in title.directions() {
+            // The first two pieces along the line
+            let mut found = (1..8)
+                .map_while(|i| offset(attacker, file_move * i, rank_move * i))
+                .filter(|&square| board[square].is_some());
+            let (front, behind) = match (found.next(), found.next()) {
+                (Some(front), Some(behind)) => (front, behind),
+                _ => continue,
+            };
+            let (front_piece, behind_piece) = (board[front].unwrap(), board[behind].unwrap());
+            if front_piece.color() == color || behind_piece.color() == color {
+                continue;
+            }
+            let (front_value, behind_value) = (
+                motif_value(front_piece.title()),
+                motif_value(behind_piece.title()),
+            );
+            let (attacker, front, behind) = (
+                as_standard_notation(&attacker),
+                as_standard_notation(&front),
+                as_standard_notation(&behind),
+            );
+            if behind_value > front_value {
+                motifs.push(Motif::Pin {
+                    pinner: attacker,
+                    pinned: front,
+                    behind,
+                });
+            } else if front_value > behind_value {
+                motifs.push(Motif::Skewer {
+                    attacker,
+                    front,
+                    behind,
+                });
+            }
+        }
+    }
+    motifs
+}
+
+// The king of a colour checked by two pieces
+fn double_check(board: &[Option<Piece>; 64], color: Color) -> Option<Motif> {
+    let king = find_king(board, color)?;
+    let checkers = attackers_of(board, king, color.opposite());
+    if checkers.len() < 2 {
+        return None;
+    }
+    Some(Motif::DoubleCheck {
+        king: as_standard_notation(&king),
+        checkers: checkers.iter().map(as_standard_notation).collect(),
+    })
+}
+
+// The king of a colour on its back rank with every square in front of it
+// taken by its own pieces or attacked
+fn back_rank_weakness(board: &[Option<Piece>; 64], color: Color) -> Option<Motif> {
+    let king = find_king(board, color)?;
+    let back_rank = if color == Color::White { 0 } else { 7 };
+    let has_heavy_piece = pieces_of(board, color.opposite()).iter().any(|&square| {
+        matches!(
+            board[square].unwrap().title(),
+            PieceType::Queen | PieceType::Rook
+        )
+    });
+    if king / 8 != back_rank || !has_heavy_piece {
+        return None;
+    }
+    let is_stuck = (-1..=1)
+        .filter_map(|file_move| offset(king, file_move, color.forward()))
+        .all(|square| {
+            board[square].is_some_and(|piece| piece.color() == color)
+                || is_square_attacked(board, square, color.opposite())
+        });
+    if is_stuck {
+        Some(Motif::BackRankWeakness {
+            king: as_standard_notation(&king),
+        })
+    } else {
+        None
+    }
+}
+
+// Pieces of a colour that the opponent can capture and win material
+fn hanging_pieces(game: &Game, color: Color) -> Vec<Motif> {
+    let board = game.get_board();
+    pieces_of(board, color)
+        .into_iter()
+        .filter(|&square| board[square].unwrap().title() != PieceType::King)
+        .filter(|&square| {
+            let target = as_standard_notation(&square);
+            attackers_of(board, square, color.opposite())
+                .iter()
+                .any(|attacker| see(game, &as_standard_notation(attacker), &target) > 0)
+        })
+        .map(|square| Motif::HangingPiece {
+            square: as_standard_notation(&square),
+        })
+        .collect()
+}
+
+fn pieces_of(board: &[Option<Piece>; 64], color: Color) -> Vec<usize> {
+    (0..64)
+        .filter(|&square| board[square].is_some_and(|piece| piece.color() == color))
+        .collect()
+}
+
+// Piece values with the king worth the most
+fn motif_value(title: PieceType) -> i32 {
+    match title {
+        PieceType::King => MATE_SCORE,
+        _ => piece_value(title),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn squares(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn finds_knight_fork() {
+        let game = Game::from_fen("r3k3/8/8/1N6/8/8/8/4K3 w - - 0 1").unwrap();
+        let motifs = find_motifs_of_move(&game, "B5", "C7").unwrap();
+        assert!(motifs.contains(&Motif::Fork {
+            attacker: String::from("C7"),
+            targets: squares(&["A8", "E8"]),
+        }));
+    }
+
+    #[test]
+    fn finds_pins_and_skewers() {
+        // The bishop pins the knight to the king and the rook skewers the
+        // king to the queen
+        let game = Game::from_fen("3q4/8/3k4/8/1n6/B7/8/3RK3 b - - 0 1").unwrap();
+        let motifs = find_motifs(&game);
+        assert!(motifs.contains(&Motif::Skewer {
+            attacker: String::from("D1"),
+            front: String::from("D6"),
+            behind: String::from("D8"),
+        }));
+        assert!(motifs.contains(&Motif::Pin {
+            pinner: String::from("A3"),
+            pinned: String::from("B4"),
+            behind: String::from("D6"),
+        }));
+    }
+
+    #[test]
+    fn finds_discovered_and_double_check() {
+        let game = Game::from_fen("4k3/8/8/8/8/8/4B3/K3R3 w - - 0 1").unwrap();
+        let motifs = find_motifs_of_move(&game, "E2", "B5").unwrap();
+        assert!(motifs.contains(&Motif::DiscoveredAttack {
+            moved: String::from("B5"),
+            attacker: String::from("E1"),
+            target: String::from("E8"),
+        }));
+        assert!(motifs.contains(&Motif::DoubleCheck {
+            king: String::from("E8"),
+            checkers: squares(&["E1", "B5"]),
+        }));
+    }
+
+    #[test]
+    fn finds_back_rank_weakness_and_hanging_pieces() {
+        let game = Game::from_fen("6k1/5ppp/8/8/2n5/8/3R4/6K1 w - - 0 1").unwrap();
+        let motifs = find_motifs(&game);
+        assert!(motifs.contains(&Motif::BackRankWeakness {
+            king: String::from("G8"),
+        }));
+        assert!(!motifs.contains(&Motif::BackRankWeakness {
+            king: String::from("G1"),
+        }));
+        assert!(motifs.contains(&Motif::HangingPiece {
+            square: String::from("D2"),
+        }));
+    }
+}
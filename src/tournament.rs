@@ -0,0 +1,1083 @@
+use crate::builder::*;
+use crate::engine::*;
+use crate::game::*;
+use crate::notation::*;
+use crate::piece::*;
+use crate::tablebase::*;
+use crate::zobrist::*;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Time an engine may take beyond its limit before it's stopped
+const TIME_MARGIN: Duration = Duration::from_secs(1);
+// How long an engine may take to start or get ready
+const STARTUP_TIME: Duration = Duration::from_secs(10);
+
+/// How long the players may think.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TimeControl {
+    /// Search every move to a fixed depth, without a clock.
+    Depth(u32),
+    /// A fixed time for every move.
+    MoveTime(Duration),
+    /// A clock for the whole game with a time added after every move.
+    Clock { base: Duration, increment: Duration },
+}
+
+/// A move chosen by a player, with its score in centipawns from the view
+/// of the side to move if the player gave one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineMove {
+    pub from: String,
+    pub to: String,
+    pub score: Option<i32>,
+}
+
+/// A player in a match, usually an engine.
+pub trait Player {
+    /// The name written in the PGN and the results.
+    fn name(&self) -> String;
+
+    /// Prepares for a new game.
+    fn new_game(&mut self) -> io::Result<()>;
+
+    /// Chooses a move for the side to move. `clocks` is the time left for
+    /// white and black. Returns None if the player gives no move.
+    fn go(
+        &mut self,
+        game: &Game,
+        control: &TimeControl,
+        clocks: [Duration; 2],
+    ) -> io::Result<Option<EngineMove>>;
+}
+
+/// The search in `engine` as a player.
+#[derive(Clone, Debug)]
+pub struct BuiltinEngine {
+    depth: u32,
+    tablebase: Option<Tablebase>,
+}
+
+impl BuiltinEngine {
+    /// Creates a player that searches up to a depth, or until its time for
+    /// the move runs out.
+    pub fn new(depth: u32) -> BuiltinEngine {
+        BuiltinEngine {
+            depth,
+            tablebase: None,
+        }
+    }
+
+    /// Plays positions the tablebase can probe from its tables.
+    pub fn set_tablebase(&mut self, tablebase: Tablebase) {
+        self.tablebase = Some(tablebase);
+    }
+}
+
+impl Player for BuiltinEngine {
+    fn name(&self) -> String {
+        format!("isaklar-chess depth {}", self.depth)
+    }
+
+    fn new_game(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn go(
+        &mut self,
+        game: &Game,
+        control: &TimeControl,
+        clocks: [Duration; 2],
+    ) -> io::Result<Option<EngineMove>> {
+        let limits = match *control {
+            TimeControl::Depth(depth) => SearchLimits { depth, time: None },
+            TimeControl::MoveTime(time) => SearchLimits {
+                depth: self.depth,
+                time: Some(time),
+            },
+            TimeControl::Clock { increment, .. } => {
+                // A share of the time left, with most of the increment
+                let left = clocks[color_index(game.get_active_color())];
+                SearchLimits {
+                    depth: self.depth,
+                    time: Some((left / 30).saturating_add(increment - increment / 4)),
+                }
+            }
+        };
+        let result = match &self.tablebase {
+            Some(tablebase) => search_with_tablebase(game, limits, tablebase),
+            None => search(game, limits),
+        };
+        Ok(result.map(|result| EngineMove {
+            from: result.from,
+            to: result.to,
+            score: Some(result.score),
+        }))
+    }
+}
+
+/// An engine speaking the Universal Chess Interface, run as a child
+/// process.
+pub struct UciEngine {
+    name: String,
+    child: Child,
+    input: ChildStdin,
+    // Lines from the engine, read on a separate thread so reads can time out
+    lines: Receiver<String>,
+    chess960: bool,
+}
+
+impl UciEngine {
+    /// Starts an engine and waits until it's ready. The name is the one the
+    /// engine gives, or the command.
+    pub fn start(command: &str, args: &[String]) -> io::Result<UciEngine> {
+        let mut child = Command::new(command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let input = child.stdin.take().unwrap();
+        let output = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(output).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = UciEngine {
+            name: command.to_string(),
+            child,
+            input,
+            lines,
+            chess960: false,
+        };
+        engine.send("uci")?;
+        let deadline = Instant::now() + STARTUP_TIME;
+        loop {
+            let line = engine.receive(Some(deadline))?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            } else if line.trim() == "uciok" {
+                break;
+            }
+        }
+        engine.wait_until_ready()?;
+        Ok(engine)
+    }
+
+    /// Sets an option of the engine, like `Hash` or `Threads`.
+    pub fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.input, "{}", command)?;
+        self.input.flush()
+    }
+
+    fn receive(&mut self, deadline: Option<Instant>) -> io::Result<String> {
+        let line = match deadline {
+            Some(deadline) => self
+                .lines
+                .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => self
+                .lines
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        line.map_err(|error| match error {
+            RecvTimeoutError::Timeout => {
+                io::Error::new(io::ErrorKind::TimedOut, "the engine didn't answer in time")
+            }
+            RecvTimeoutError::Disconnected => {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "the engine has stopped")
+            }
+        })
+    }
+
+    fn wait_until_ready(&mut self) -> io::Result<()> {
+        self.send("isready")?;
+        let deadline = Instant::now() + STARTUP_TIME;
+        while self.receive(Some(deadline))?.trim() != "readyok" {}
+        Ok(())
+    }
+}
+
+impl Player for UciEngine {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn new_game(&mut self) -> io::Result<()> {
+        self.send("ucinewgame")?;
+        self.wait_until_ready()
+    }
+
+    fn go(
+        &mut self,
+        game: &Game,
+        control: &TimeControl,
+        clocks: [Duration; 2],
+    ) -> io::Result<Option<EngineMove>> {
+        if game.is_chess960() != self.chess960 {
+            self.chess960 = game.is_chess960();
+            let value = self.chess960.to_string();
+            self.set_option("UCI_Chess960", &value)?;
+        }
+        self.send(&position_command(game))?;
+        let deadline = match *control {
+            TimeControl::Depth(depth) => {
+                self.send(&format!("go depth {}", depth))?;
+                None
+            }
+            TimeControl::MoveTime(time) => {
+                self.send(&format!("go movetime {}", time.as_millis()))?;
+                Instant::now().checked_add(time.saturating_add(TIME_MARGIN))
+            }
+            TimeControl::Clock { increment, .. } => {
+                self.send(&format!(
+                    "go wtime {} btime {} winc {} binc {}",
+                    clocks[0].as_millis(),
+                    clocks[1].as_millis(),
+                    increment.as_millis(),
+                    increment.as_millis()
+                ))?;
+                let left = clocks[color_index(game.get_active_color())];
+                Instant::now().checked_add(left.saturating_add(TIME_MARGIN))
+            }
+        };
+
+        let mut score = None;
+        loop {
+            let line = self.receive(deadline)?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["info", ..] => {
+                    if let Some(value) = info_score(&words) {
+                        score = Some(value);
+                    }
+                }
+                ["bestmove", "(none)", ..] | ["bestmove", "0000", ..] => return Ok(None),
+                ["bestmove", text, ..] => {
+                    let (from, to) = parse_bestmove(game, text).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, format!("illegal move {}", text))
+                    })?;
+                    return Ok(Some(EngineMove { from, to, score }));
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        // Give the engine a moment to quit on its own
+        for _ in 0..10 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Reads the move of a bestmove reply. A promotion keeps its suffix, like
+// `e7e8q`, which is only allowed on pawn moves to the last rank
+fn parse_bestmove(game: &Game, text: &str) -> Option<(String, String)> {
+    let (text, promotion) = match text.len() {
+        5 if text.is_ascii() => (&text[..4], Some(&text[4..])),
+        _ => (text, None),
+    };
+    let (from, to) = parse_uci(game, text, game.is_chess960())?;
+    match promotion {
+        None => Some((from, to)),
+        Some("q") | Some("r") | Some("b") | Some("n") if is_promotion(game, &from, &to) => {
+            Some((from, to))
+        }
+        Some(_) => None,
+    }
+}
+
+// If a move takes a pawn to the last rank
+fn is_promotion(game: &Game, from: &str, to: &str) -> bool {
+    let is_pawn = parse_square(from)
+        .and_then(|from| game.get_board()[from])
+        .is_some_and(|piece| piece.title() == PieceType::Pawn);
+    is_pawn && parse_square(to).is_some_and(|to| to / 8 == 0 || to / 8 == 7)
+}
+
+// The UCI command setting up the position of a game, from its starting
+// position with the moves played since
+fn position_command(game: &Game) -> String {
+    let start = game.get_starting_fen();
+    let mut command = format!("position fen {}", start);
+    let mut replay = GameBuilder::from_fen(&start)
+        .unwrap()
+        .chess960(game.is_chess960())
+        .build()
+        .unwrap();
+    let history = game.get_move_history();
+    if !history.is_empty() {
+        command.push_str(" moves");
+    }
+    for (from, to) in history {
+        command.push(' ');
+        command.push_str(&to_uci(&replay, &from, &to, game.is_chess960()).unwrap());
+        replay.make_move(from, to);
+    }
+    command
+}
+
+// The score of an info line, with mates just below the mate score
+fn info_score(words: &[&str]) -> Option<i32> {
+    let at = words.iter().position(|&word| word == "score")?;
+    let value: i32 = words.get(at + 2)?.parse().ok()?;
+    match *words.get(at + 1)? {
+        "cp" => Some(value),
+        "mate" if value > 0 => Some(MATE_SCORE - value),
+        "mate" => Some(-MATE_SCORE - value),
+        _ => None,
+    }
+}
+
+/// When games are stopped before they end on the board.
+#[derive(Clone, Debug)]
+pub struct Adjudication {
+    /// A side loses when both players' scores have been at least this many
+    /// centipawns against it for `resign_moves` moves each.
+    pub resign_score: Option<i32>,
+    pub resign_moves: u32,
+    /// The game is drawn when both players' scores have been within this
+    /// many centipawns of 0 for `draw_moves` moves each, from move
+    /// `draw_start` on.
+    pub draw_score: Option<i32>,
+    pub draw_moves: u32,
+    pub draw_start: u32,
+    /// Positions the tablebase knows the result of are adjudicated.
+    pub tablebase: Option<Tablebase>,
+    /// The game is drawn after this many moves by each side.
+    pub max_moves: Option<u32>,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Adjudication {
+            resign_score: None,
+            resign_moves: 3,
+            draw_score: None,
+            draw_moves: 8,
+            draw_start: 40,
+            tablebase: None,
+            max_moves: None,
+        }
+    }
+}
+
+/// The result of a game.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// The game was stopped without a result, e.g. at a promotion, which
+    /// `Game` doesn't support.
+    Unfinished,
+}
+
+impl Outcome {
+    /// The result as written in PGN, like `1-0`.
+    pub fn as_pgn(&self) -> &'static str {
+        match self {
+            Outcome::WhiteWins => "1-0",
+            Outcome::BlackWins => "0-1",
+            Outcome::Draw => "1/2-1/2",
+            Outcome::Unfinished => "*",
+        }
+    }
+
+    fn win_for(color: Color) -> Outcome {
+        match color {
+            Color::White => Outcome::WhiteWins,
+            Color::Black => Outcome::BlackWins,
+        }
+    }
+}
+
+/// A finished game of a match.
+#[derive(Clone)]
+pub struct PlayedGame {
+    pub game: Game,
+    pub white: String,
+    pub black: String,
+    pub outcome: Outcome,
+    /// How the game ended, like `checkmate`, `time forfeit` or
+    /// `adjudication`.
+    pub termination: String,
+}
+
+impl PlayedGame {
+    /// Writes the game as PGN with the given event and round.
+    pub fn to_pgn(&self, event: &str, round: u32) -> String {
+        let tags = [
+            ("Event", event.to_string()),
+            ("Round", round.to_string()),
+            ("White", self.white.clone()),
+            ("Black", self.black.clone()),
+            ("Result", self.outcome.as_pgn().to_string()),
+            ("Termination", self.termination.clone()),
+        ];
+        let tags: Vec<(String, String)> = tags
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        to_pgn(&self.game, &tags)
+    }
+}
+
+/// Plays a game between two players from an opening position, which may
+/// already have moves. A game is stopped unfinished before a pawn is
+/// promoted, since `Game` can't promote it.
+pub fn play_game(
+    white: &mut dyn Player,
+    black: &mut dyn Player,
+    opening: &Game,
+    control: &TimeControl,
+    adjudication: &Adjudication,
+) -> io::Result<PlayedGame> {
+    white.new_game()?;
+    black.new_game()?;
+    let mut game = opening.clone();
+    let mut clocks = match *control {
+        TimeControl::Clock { base, .. } => [base, base],
+        _ => [Duration::from_secs(0); 2],
+    };
+    let mut repetitions: HashMap<u64, u32> = HashMap::new();
+    *repetitions.entry(polyglot_hash(&game)).or_insert(0) += 1;
+    // Scores from white's view, None where a player gave no score
+    let mut scores: Vec<Option<i32>> = Vec::new();
+
+    let (outcome, termination) = loop {
+        if let Some(ended) = game_end(&game, &repetitions, &scores, adjudication) {
+            break ended;
+        }
+        let color = game.get_active_color();
+        let player: &mut dyn Player = match color {
+            Color::White => &mut *white,
+            Color::Black => &mut *black,
+        };
+        let started = Instant::now();
+        let chosen = player.go(&game, control, clocks);
+        let elapsed = started.elapsed();
+        let chosen = match chosen {
+            Ok(Some(chosen)) => chosen,
+            Err(error) if error.kind() == io::ErrorKind::TimedOut => {
+                break (Outcome::win_for(color.opposite()), "time forfeit");
+            }
+            Ok(None) | Err(_) => break (Outcome::win_for(color.opposite()), "rules infraction"),
+        };
+        if let TimeControl::Clock { increment, .. } = *control {
+            let clock = &mut clocks[color_index(color)];
+            if elapsed > *clock {
+                break (Outcome::win_for(color.opposite()), "time forfeit");
+            }
+            *clock = (*clock - elapsed).saturating_add(increment);
+        }
+        if is_promotion(&game, &chosen.from, &chosen.to) {
+            break (Outcome::Unfinished, "unsupported promotion");
+        }
+        if game.make_move(chosen.from, chosen.to).is_none() {
+            break (Outcome::win_for(color.opposite()), "rules infraction");
+        }
+        *repetitions.entry(polyglot_hash(&game)).or_insert(0) += 1;
+        scores.push(chosen.score.map(|score| match color {
+            Color::White => score,
+            Color::Black => -score,
+        }));
+    };
+
+    Ok(PlayedGame {
+        game,
+        white: white.name(),
+        black: black.name(),
+        outcome,
+        termination: termination.to_string(),
+    })
+}
+
+// The result of a game if it has ended by the rules or is adjudicated
+fn game_end(
+    game: &Game,
+    repetitions: &HashMap<u64, u32>,
+    scores: &[Option<i32>],
+    adjudication: &Adjudication,
+) -> Option<(Outcome, &'static str)> {
+    match game.get_game_state() {
+        GameState::WhiteCheckMate => return Some((Outcome::BlackWins, "checkmate")),
+        GameState::BlackCheckMate => return Some((Outcome::WhiteWins, "checkmate")),
+        GameState::Tie => return Some((Outcome::Draw, "stalemate")),
+        _ => (),
+    }
+    if repetitions.values().any(|&count| count >= 3) {
+        return Some((Outcome::Draw, "threefold repetition"));
+    }
+    if game.get_halfmove_clock() >= 100 {
+        return Some((Outcome::Draw, "fifty move rule"));
+    }
+    if is_drawn_material(game.get_board()) {
+        return Some((Outcome::Draw, "insufficient material"));
+    }
+
+    if let Some(wdl) = adjudication
+        .tablebase
+        .as_ref()
+        .and_then(|tablebase| tablebase.probe_wdl(game))
+    {
+        let color = game.get_active_color();
+        let outcome = match wdl {
+            Wdl::Win => Outcome::win_for(color),
+            Wdl::Loss => Outcome::win_for(color.opposite()),
+            _ => Outcome::Draw,
+        };
+        return Some((outcome, "adjudication"));
+    }
+    // The last moves of both players, each score from white's view
+    let last = |moves: u32| -> Option<Vec<i32>> {
+        let plies = 2 * moves as usize;
+        if moves == 0 || scores.len() < plies {
+            return None;
+        }
+        scores[scores.len() - plies..].iter().copied().collect()
+    };
+    if let Some(limit) = adjudication.resign_score {
+        if let Some(last) = last(adjudication.resign_moves) {
+            if last.iter().all(|&score| score >= limit) {
+                return Some((Outcome::WhiteWins, "adjudication"));
+            }
+            if last.iter().all(|&score| score <= -limit) {
+                return Some((Outcome::BlackWins, "adjudication"));
+            }
+        }
+    }
+    if let Some(limit) = adjudication.draw_score {
+        if let Some(last) = last(adjudication.draw_moves) {
+            if game.get_fullmove_number() >= adjudication.draw_start
+                && last.iter().all(|score| score.abs() <= limit)
+            {
+                return Some((Outcome::Draw, "adjudication"));
+            }
+        }
+    }
+    if adjudication
+        .max_moves
+        .is_some_and(|moves| scores.len() >= 2 * moves as usize)
+    {
+        return Some((Outcome::Draw, "adjudication"));
+    }
+    None
+}
+
+/// Wins, draws and losses of one player against another, with the
+/// results of each pair of games played from the same opening with
+/// swapped colours. Unfinished games and their pairs aren't counted.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// How many pairs scored 0, 1/2, 1, 3/2 and 2 points.
+    pub pairs: [u32; 5],
+}
+
+/// A difference in Elo with the margin of its 95% confidence interval.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EloEstimate {
+    pub difference: f64,
+    pub error: f64,
+}
+
+impl Score {
+    /// Get the number of games played.
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Get the points scored, 1 for a win and 1/2 for a draw.
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+
+    /// Estimates the Elo difference from the score. Returns None until a
+    /// game is played, and while every game is won or every game is lost.
+    pub fn elo(&self) -> Option<EloEstimate> {
+        let games = self.games() as f64;
+        if games == 0.0 {
+            return None;
+        }
+        let score = self.points() / games;
+        if score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+        let deviation = ((self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / games)
+            .sqrt();
+        let margin = 1.96 * deviation / games.sqrt();
+        let bound = |score: f64| elo_difference(score.clamp(1e-6, 1.0 - 1e-6));
+        Some(EloEstimate {
+            difference: elo_difference(score),
+            error: (bound(score + margin) - bound(score - margin)) / 2.0,
+        })
+    }
+
+    // Counts a game and returns the points of the first player, None if
+    // the game is unfinished
+    fn add(&mut self, outcome: Outcome, first_is_white: bool) -> Option<f64> {
+        let points = match (outcome, first_is_white) {
+            (Outcome::Unfinished, _) => return None,
+            (Outcome::Draw, _) => 0.5,
+            (Outcome::WhiteWins, true) | (Outcome::BlackWins, false) => 1.0,
+            _ => 0.0,
+        };
+        if points == 1.0 {
+            self.wins += 1;
+        } else if points == 0.5 {
+            self.draws += 1;
+        } else {
+            self.losses += 1;
+        }
+        Some(points)
+    }
+}
+
+/// The Elo difference that gives an expected score from 0 to 1.
+pub fn elo_difference(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+/// How the players of a tournament are paired.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Every player plays every other player.
+    RoundRobin,
+    /// The first player plays every other player.
+    Gauntlet,
+}
+
+/// The pairs of players, by index, that play each other in a tournament.
+pub fn pairings(players: usize, format: Format) -> Vec<(usize, usize)> {
+    match format {
+        Format::RoundRobin => (0..players)
+            .flat_map(|first| (first + 1..players).map(move |second| (first, second)))
+            .collect(),
+        Format::Gauntlet => (1..players).map(|second| (0, second)).collect(),
+    }
+}
+
+/// The settings of a match between two players.
+#[derive(Clone)]
+pub struct MatchOptions {
+    /// The number of games, rounded up to an even number so every opening
+    /// is played with both colours.
+    pub games: u32,
+    pub time_control: TimeControl,
+    pub adjudication: Adjudication,
+    /// Starting positions, used in turn. Empty for the standard position.
+    pub openings: Vec<Game>,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions {
+            games: 2,
+            time_control: TimeControl::Depth(SearchLimits::default().depth),
+            adjudication: Adjudication::default(),
+            openings: Vec::new(),
+        }
+    }
+}
+
+/// Plays a match in pairs of games from the same opening, the first
+/// player taking white in the first game of each pair. After every game
+/// `on_game` is called with it and the score of the first player so far,
+/// and the match stops early if it returns false.
+pub fn play_match<F>(
+    first: &mut dyn Player,
+    second: &mut dyn Player,
+    options: &MatchOptions,
+    mut on_game: F,
+) -> io::Result<Score>
+where
+    F: FnMut(&PlayedGame, &Score) -> bool,
+{
+    let mut score = Score::default();
+    for pair in 0..options.games.div_ceil(2) as usize {
+        let opening = match options.openings.len() {
+            0 => Game::new(),
+            count => options.openings[pair % count].clone(),
+        };
+        let mut pair_points = Some(0.0);
+        for &first_is_white in &[true, false] {
+            let played = if first_is_white {
+                play_game(
+                    first,
+                    second,
+                    &opening,
+                    &options.time_control,
+                    &options.adjudication,
+                )?
+            } else {
+                play_game(
+                    second,
+                    first,
+                    &opening,
+                    &options.time_control,
+                    &options.adjudication,
+                )?
+            };
+            let points = score.add(played.outcome, first_is_white);
+            pair_points = pair_points.zip(points).map(|(pair, game)| pair + game);
+            if let (false, Some(pair_points)) = (first_is_white, pair_points) {
+                score.pairs[(pair_points * 2.0) as usize] += 1;
+            }
+            if !on_game(&played, &score) {
+                return Ok(score);
+            }
+        }
+    }
+    Ok(score)
+}
+
+/// Plays a match between every pair of players of a tournament and
+/// returns each pair with the score of the first. `on_game` is called
+/// after every game.
+pub fn play_tournament<F>(
+    players: &mut [Box<dyn Player>],
+    format: Format,
+    options: &MatchOptions,
+    mut on_game: F,
+) -> io::Result<Vec<(usize, usize, Score)>>
+where
+    F: FnMut(&PlayedGame),
+{
+    let mut results = Vec::new();
+    for (first, second) in pairings(players.len(), format) {
+        let (left, right) = players.split_at_mut(second);
+        let score = play_match(
+            left[first].as_mut(),
+            right[0].as_mut(),
+            options,
+            |played, _| {
+                on_game(played);
+                true
+            },
+        )?;
+        results.push((first, second, score));
+    }
+    Ok(results)
+}
+
+/// Reads an opening suite, either PGN games or one FEN per line. PGN
+/// games start from the position after their moves. Returns None if a
+/// position or game can't be read.
+pub fn read_openings(text: &str) -> Option<Vec<Game>> {
+    if !text.trim_start().starts_with('[') {
+        return text
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Game::from_fen)
+            .collect();
+    }
+    // A tag after movetext starts the next game
+    let mut games = Vec::new();
+    let mut current = String::new();
+    let mut in_movetext = false;
+    for line in text.lines() {
+        let is_tag = line.trim_start().starts_with('[');
+        if is_tag && in_movetext {
+            games.push(parse_pgn(&current)?.0);
+            current.clear();
+            in_movetext = false;
+        }
+        if !is_tag && !line.trim().is_empty() {
+            in_movetext = true;
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !current.trim().is_empty() {
+        games.push(parse_pgn(&current)?.0);
+    }
+    Some(games)
+}
+
+fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Plays fixed moves with fixed scores
+    struct Scripted {
+        moves: Vec<(&'static str, &'static str, i32)>,
+        played: usize,
+    }
+
+    impl Scripted {
+        fn new(moves: &[(&'static str, &'static str, i32)]) -> Scripted {
+            Scripted {
+                moves: moves.to_vec(),
+                played: 0,
+            }
+        }
+    }
+
+    impl Player for Scripted {
+        fn name(&self) -> String {
+            String::from("scripted")
+        }
+
+        fn new_game(&mut self) -> io::Result<()> {
+            self.played = 0;
+            Ok(())
+        }
+
+        fn go(
+            &mut self,
+            _game: &Game,
+            _control: &TimeControl,
+            _clocks: [Duration; 2],
+        ) -> io::Result<Option<EngineMove>> {
+            let chosen = self
+                .moves
+                .get(self.played)
+                .map(|&(from, to, score)| EngineMove {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    score: Some(score),
+                });
+            self.played += 1;
+            Ok(chosen)
+        }
+    }
+
+    #[test]
+    fn plays_and_adjudicates_games() {
+        let mut white = Scripted::new(&[("F2", "F3", 0), ("G2", "G4", 0)]);
+        let mut black = Scripted::new(&[("E7", "E5", 0), ("D8", "H4", MATE_SCORE - 1)]);
+        let control = TimeControl::Depth(1);
+        let played = play_game(
+            &mut white,
+            &mut black,
+            &Game::new(),
+            &control,
+            &Adjudication::default(),
+        )
+        .unwrap();
+        assert_eq!(played.outcome, Outcome::BlackWins);
+        assert_eq!(played.termination, "checkmate");
+        assert!(played
+            .to_pgn("Test", 1)
+            .contains("[Termination \"checkmate\"]"));
+
+        // Both players agree that white is lost
+        let mut white = Scripted::new(&[("G1", "F3", -600), ("F3", "G1", -700)]);
+        let mut black = Scripted::new(&[("G8", "F6", 500), ("F6", "G8", 650)]);
+        let adjudication = Adjudication {
+            resign_score: Some(500),
+            resign_moves: 2,
+            ..Adjudication::default()
+        };
+        let played = play_game(
+            &mut white,
+            &mut black,
+            &Game::new(),
+            &control,
+            &adjudication,
+        )
+        .unwrap();
+        assert_eq!(played.outcome, Outcome::BlackWins);
+        assert_eq!(played.termination, "adjudication");
+
+        // A player without a move loses
+        let mut black = Scripted::new(&[]);
+        let played = play_game(
+            &mut Scripted::new(&[("E2", "E4", 0)]),
+            &mut black,
+            &Game::new(),
+            &control,
+            &Adjudication::default(),
+        )
+        .unwrap();
+        assert_eq!(played.outcome, Outcome::WhiteWins);
+        assert_eq!(played.termination, "rules infraction");
+
+        // Huge clocks don't overflow
+        let control = TimeControl::Clock {
+            base: Duration::MAX,
+            increment: Duration::MAX,
+        };
+        let mut white = Scripted::new(&[("F2", "F3", 0), ("G2", "G4", 0)]);
+        let mut black = Scripted::new(&[("E7", "E5", 0), ("D8", "H4", MATE_SCORE - 1)]);
+        let played = play_game(
+            &mut white,
+            &mut black,
+            &Game::new(),
+            &control,
+            &Adjudication::default(),
+        )
+        .unwrap();
+        assert_eq!(played.termination, "checkmate");
+        let chosen = BuiltinEngine::new(1)
+            .go(&played.game, &control, [Duration::MAX; 2])
+            .unwrap();
+        assert!(chosen.is_none());
+        let chosen = BuiltinEngine::new(1)
+            .go(&Game::new(), &control, [Duration::MAX; 2])
+            .unwrap();
+        assert!(chosen.is_some());
+    }
+
+    #[test]
+    fn stops_games_at_promotions() {
+        let opening = Game::from_fen("8/4P1k1/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            parse_bestmove(&opening, "e7e8q"),
+            Some((String::from("E7"), String::from("E8")))
+        );
+        assert_eq!(parse_bestmove(&opening, "e7e8k"), None);
+        assert_eq!(parse_bestmove(&opening, "e1d1q"), None);
+        assert_eq!(parse_bestmove(&opening, "e7é8"), None);
+
+        let mut white = Scripted::new(&[("E1", "D1", 0), ("E7", "E8", 900)]);
+        let mut black = Scripted::new(&[("G7", "G6", 0)]);
+        let played = play_game(
+            &mut white,
+            &mut black,
+            &opening,
+            &TimeControl::Depth(1),
+            &Adjudication::default(),
+        )
+        .unwrap();
+        assert_eq!(played.outcome, Outcome::Unfinished);
+        assert_eq!(played.termination, "unsupported promotion");
+        assert_eq!(played.game.get_move_history().len(), 2);
+        assert!(played.to_pgn("Test", 1).ends_with(" *\n"));
+
+        // The unfinished game and its pair aren't scored
+        let mut score = Score::default();
+        assert_eq!(score.add(played.outcome, true), None);
+        assert_eq!(score.games(), 0);
+    }
+
+    #[test]
+    fn draws_by_repetition() {
+        let shuffle = |from: &'static str, to: &'static str| {
+            Scripted::new(&[(from, to, 0), (to, from, 0), (from, to, 0), (to, from, 0)])
+        };
+        let played = play_game(
+            &mut shuffle("G1", "F3"),
+            &mut shuffle("G8", "F6"),
+            &Game::new(),
+            &TimeControl::Depth(1),
+            &Adjudication::default(),
+        )
+        .unwrap();
+        assert_eq!(played.outcome, Outcome::Draw);
+        assert_eq!(played.termination, "threefold repetition");
+    }
+
+    #[test]
+    fn plays_matches_in_pairs() {
+        let mut first = BuiltinEngine::new(1);
+        let mut second = BuiltinEngine::new(1);
+        let options = MatchOptions {
+            games: 3,
+            time_control: TimeControl::Depth(1),
+            adjudication: Adjudication {
+                max_moves: Some(3),
+                ..Adjudication::default()
+            },
+            openings: read_openings("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1\n").unwrap(),
+        };
+        let mut games = 0;
+        let score = play_match(&mut first, &mut second, &options, |played, _| {
+            games += 1;
+            assert!(played.game.get_starting_fen().starts_with("4k3/"));
+            true
+        })
+        .unwrap();
+        assert_eq!(games, 4);
+        assert_eq!(score.games(), 4);
+        assert_eq!(score.pairs.iter().sum::<u32>(), 2);
+
+        let mut games = 0;
+        play_match(&mut first, &mut second, &options, |_, _| {
+            games += 1;
+            false
+        })
+        .unwrap();
+        assert_eq!(games, 1);
+    }
+
+    #[test]
+    fn estimates_elo() {
+        let score = Score {
+            wins: 60,
+            draws: 20,
+            losses: 20,
+            pairs: [0; 5],
+        };
+        let elo = score.elo().unwrap();
+        assert!((elo.difference - 147.2).abs() < 0.1);
+        assert!(elo.error > 50.0 && elo.error < 100.0);
+        assert_eq!(elo_difference(0.5), 0.0);
+        let all_wins = Score {
+            wins: 2,
+            ..Score::default()
+        };
+        assert_eq!(all_wins.elo(), None);
+
+        assert_eq!(
+            pairings(3, Format::RoundRobin),
+            vec![(0, 1), (0, 2), (1, 2)]
+        );
+        assert_eq!(pairings(3, Format::Gauntlet), vec![(0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn reads_openings_and_writes_positions() {
+        let pgn = "[Event \"A\"]\n\n1. e4 e5 *\n\n[Event \"B\"]\n\n1. d4 *\n";
+        let openings = read_openings(pgn).unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(openings[1].get_move_history().len(), 1);
+        assert!(read_openings("not a position").is_none());
+
+        assert_eq!(
+            position_command(&openings[0]),
+            format!("position fen {} moves e2e4 e7e5", Game::new().to_fen())
+        );
+        assert_eq!(
+            info_score(&["info", "depth", "3", "score", "mate", "-2"]),
+            Some(-MATE_SCORE + 2)
+        );
+    }
+}
@@ -0,0 +1,493 @@
+use crate::game::*;
+use crate::notation::*;
+
+// The annotation symbols written after a move for NAGs 1 to 6
+const NAG_SYMBOLS: [&str; 6] = ["!", "?", "!!", "??", "!?", "?!"];
+
+/// A move in a game tree with its annotations and the moves that can
+/// follow it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    from: String,
+    to: String,
+    san: String,
+    /// A comment written after the move.
+    pub comment: Option<String>,
+    /// Numeric annotation glyphs, e.g. 1 for `!`, 2 for `?` and 5 for `!?`.
+    pub nags: Vec<u8>,
+    /// Arrows to draw on the board after the move, as (from, to) pairs.
+    pub arrows: Vec<(String, String)>,
+    /// Squares to highlight on the board after the move.
+    pub highlights: Vec<String>,
+    // The main continuation first, then the alternatives
+    variations: Vec<Node>,
+}
+
+impl Node {
+    fn new(from: String, to: String, san: String) -> Node {
+        Node {
+            from,
+            to,
+            san,
+            comment: None,
+            nags: Vec::new(),
+            arrows: Vec::new(),
+            highlights: Vec::new(),
+            variations: Vec::new(),
+        }
+    }
+
+    /// Get the square the move starts from.
+    pub fn from(&self) -> &str {
+        &self.from
+    }
+
+    /// Get the square the move goes to.
+    pub fn to(&self) -> &str {
+        &self.to
+    }
+
+    /// Get the move in Standard Algebraic Notation.
+    pub fn san(&self) -> &str {
+        &self.san
+    }
+
+    /// Get the moves that can follow this one, the main line first.
+    pub fn variations(&self) -> &[Node] {
+        &self.variations
+    }
+}
+
+/// The moves of a game with all their alternatives, starting from a
+/// position. The tree has a current position that moves are made from,
+/// which can be moved back and forward through the tree.
+#[derive(Clone)]
+pub struct GameTree {
+    start: Game,
+    /// A comment before the first move.
+    pub comment: Option<String>,
+    variations: Vec<Node>,
+    // The variation chosen at each move from the start to the current position
+    path: Vec<usize>,
+    game: Game,
+}
+
+impl GameTree {
+    /// Starts a tree at the current position of a game. Moves made before
+    /// that aren't part of the tree.
+    pub fn new(game: Game) -> GameTree {
+        GameTree {
+            start: game.clone(),
+            comment: None,
+            variations: Vec::new(),
+            path: Vec::new(),
+            game,
+        }
+    }
+
+    /// Get the game at the start of the tree.
+    pub fn start(&self) -> &Game {
+        &self.start
+    }
+
+    /// Get the game at the current position.
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Get the game following the main line from the start to its end.
+    pub fn main_line(&self) -> Game {
+        let mut game = self.start.clone();
+        let mut variations = &self.variations;
+        while let Some(node) = variations.first() {
+            game.make_move(node.from.clone(), node.to.clone());
+            variations = &node.variations;
+        }
+        game
+    }
+
+    /// Get the moves that can be made from the current position, the main
+    /// line first.
+    pub fn variations(&self) -> &[Node] {
+        children_at(&self.variations, &self.path)
+    }
+
+    /// Get the move that led to the current position, None at the start.
+    pub fn current_node(&self) -> Option<&Node> {
+        let (last, parent) = self.path.split_last()?;
+        children_at(&self.variations, parent).get(*last)
+    }
+
+    /// Get the move that led to the current position to annotate it, None
+    /// at the start.
+    pub fn current_node_mut(&mut self) -> Option<&mut Node> {
+        let (last, parent) = self.path.split_last()?;
+        children_at_mut(&mut self.variations, parent).get_mut(*last)
+    }
+
+    /// Makes a move from the current position. If the move is already in
+    /// the tree it's followed, otherwise it's added as a new variation, or
+    /// as the main line if it's the first move from the position. Returns
+    /// None if the move isn't legal.
+    pub fn make_move(&mut self, from: String, to: String) -> Option<GameState> {
+        let san = to_san(&self.game, &from, &to)?;
+        let state = self.game.make_move(from.clone(), to.clone())?;
+        let variations = children_at_mut(&mut self.variations, &self.path);
+        let index = match variations
+            .iter()
+            .position(|node| node.from == from && node.to == to)
+        {
+            Some(index) => index,
+            None => {
+                variations.push(Node::new(from, to, san));
+                variations.len() - 1
+            }
+        };
+        self.path.push(index);
+        Some(state)
+    }
+
+    /// Goes back one move. Returns false at the start.
+    pub fn back(&mut self) -> bool {
+        if self.path.pop().is_none() {
+            return false;
+        }
+        self.game.undo_move();
+        true
+    }
+
+    /// Goes forward along the main line. Returns false at its end.
+    pub fn forward(&mut self) -> bool {
+        self.enter_variation(0)
+    }
+
+    /// Goes forward with one of the moves from `variations`. Returns false
+    /// if there is no such move.
+    pub fn enter_variation(&mut self, index: usize) -> bool {
+        let (from, to) = match self.variations().get(index) {
+            Some(node) => (node.from.clone(), node.to.clone()),
+            None => return false,
+        };
+        self.game.make_move(from, to);
+        self.path.push(index);
+        true
+    }
+
+    /// Goes back to the start of the tree.
+    pub fn go_to_start(&mut self) {
+        while self.back() {}
+    }
+
+    /// Goes forward along the main line to its end.
+    pub fn go_to_end(&mut self) {
+        while self.forward() {}
+    }
+
+    /// Makes the variation the current move is in the main line where it
+    /// branches off. Returns false if the current move is on the main line.
+    pub fn promote_variation(&mut self) -> bool {
+        let depth = match self.path.iter().rposition(|&index| index > 0) {
+            Some(depth) => depth,
+            None => return false,
+        };
+        let siblings = children_at_mut(&mut self.variations, &self.path[..depth]);
+        let node = siblings.remove(self.path[depth]);
+        siblings.insert(0, node);
+        self.path[depth] = 0;
+        true
+    }
+
+    /// Deletes the current move with all the moves after it and goes back
+    /// to the position before it. Returns false at the start.
+    pub fn delete_variation(&mut self) -> bool {
+        let index = match self.path.last() {
+            Some(&index) => index,
+            None => return false,
+        };
+        self.back();
+        children_at_mut(&mut self.variations, &self.path).remove(index);
+        true
+    }
+
+    /// Writes the whole tree as PGN, with variations in parentheses, NAGs,
+    /// comments, and arrows and highlights as `[%cal]` and `[%csl]`
+    /// commands in the comments. If no `Result` tag is given, or it isn't
+    /// a PGN result, it's taken from the end of the main line. Tags are
+    /// written like in `to_pgn`.
+    pub fn to_pgn(&self, tags: &[(String, String)]) -> String {
+        let result = pgn_result(tags, game_result(&self.main_line()));
+        let mut pgn = pgn_tags(
+            &self.start.to_fen(),
+            self.start.is_chess960(),
+            &result,
+            tags,
+        );
+        let mut tokens = Vec::new();
+        if let Some(comment) = &self.comment {
+            tokens.push(format!("{{{}}}", comment));
+        }
+        write_variations(&self.start, &self.variations, true, &mut tokens);
+        tokens.push(result);
+        pgn.push_str(&wrap_movetext(&tokens));
+        pgn
+    }
+
+    /// Reads the first game of a PGN text with all its variations and
+    /// annotations, and returns the tree at its start with the tags.
+    /// Annotation symbols like `!?` after moves are read as NAGs. Returns
+    /// None if the PGN can't be read or a move isn't legal.
+    pub fn from_pgn(pgn: &str) -> Option<(GameTree, Vec<(String, String)>)> {
+        let (tags, movetext) = split_pgn(pgn)?;
+        let mut tree = GameTree::new(pgn_start(&tags)?);
+        // Where to continue when each open variation ends
+        let mut returns: Vec<Vec<usize>> = Vec::new();
+        for token in pgn_tokens(&movetext)? {
+            match token {
+                PgnToken::Move(san) => {
+                    let (from, to) = parse_san(&tree.game, &san)?;
+                    tree.make_move(from, to)?;
+                    let symbol = san.trim_start_matches(|c| c != '!' && c != '?');
+                    if let Some(nag) = NAG_SYMBOLS.iter().position(|&s| s == symbol) {
+                        tree.current_node_mut()?.nags.push(nag as u8 + 1);
+                    }
+                }
+                PgnToken::Nag(nag) => tree.current_node_mut()?.nags.push(nag),
+                PgnToken::Comment(text) => tree.add_comment(&text),
+                // A variation is an alternative to the move before it
+                PgnToken::StartVariation => {
+                    returns.push(tree.path.clone());
+                    if !tree.back() {
+                        return None;
+                    }
+                }
+                PgnToken::EndVariation => {
+                    let path = returns.pop()?;
+                    tree.go_to_start();
+                    for index in path {
+                        tree.enter_variation(index);
+                    }
+                }
+                PgnToken::Result(_) if returns.is_empty() => break,
+                PgnToken::Result(_) => (),
+            }
+        }
+        tree.go_to_start();
+        Some((tree, tags))
+    }
+
+    // Adds a PGN comment to the current move, or to the start of the tree
+    fn add_comment(&mut self, text: &str) {
+        let mut arrows = Vec::new();
+        let mut highlights = Vec::new();
+        let mut words = Vec::new();
+        let text = text.replace('[', " [").replace(']', " ] ");
+        let mut parts = text.split_whitespace();
+        while let Some(word) = parts.next() {
+            if word != "[%cal" && word != "[%csl" {
+                words.push(word);
+                continue;
+            }
+            // The first letter of each item is a colour, which isn't kept
+            for item in parts.by_ref().take_while(|&part| part != "]") {
+                for item in item.split(',').filter(|item| item.len() >= 3) {
+                    let square = |range: std::ops::Range<usize>| {
+                        item.get(range).map(|square| square.to_uppercase())
+                    };
+                    match (word, square(1..3), square(3..5)) {
+                        ("[%cal", Some(from), Some(to)) => arrows.push((from, to)),
+                        ("[%csl", Some(square), _) => highlights.push(square),
+                        _ => (),
+                    }
+                }
+            }
+        }
+        let text = words.join(" ").replace(" ]", "]");
+
+        let comment = match self.current_node_mut() {
+            Some(node) => {
+                node.arrows.extend(arrows);
+                node.highlights.extend(highlights);
+                &mut node.comment
+            }
+            None => &mut self.comment,
+        };
+        if !text.is_empty() {
+            *comment = match comment.take() {
+                Some(old) => Some(format!("{} {}", old, text)),
+                None => Some(text),
+            };
+        }
+    }
+}
+
+fn children_at<'a>(variations: &'a [Node], path: &[usize]) -> &'a [Node] {
+    path.iter()
+        .fold(variations, |nodes, &index| &nodes[index].variations)
+}
+
+fn children_at_mut<'a>(variations: &'a mut Vec<Node>, path: &[usize]) -> &'a mut Vec<Node> {
+    let mut nodes = variations;
+    for &index in path {
+        nodes = &mut nodes[index].variations;
+    }
+    nodes
+}
+
+// Writes the moves from a position: the main move, the alternatives to it
+// in parentheses and then the rest of the main line
+fn write_variations(game: &Game, variations: &[Node], is_first: bool, tokens: &mut Vec<String>) {
+    let (main, alternatives) = match variations.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    write_move(game, main, is_first, tokens);
+    for alternative in alternatives {
+        let mut line = Vec::new();
+        write_move(game, alternative, true, &mut line);
+        let mut after = game.clone();
+        after.make_move(alternative.from.clone(), alternative.to.clone());
+        write_variations(&after, &alternative.variations, false, &mut line);
+        line[0].insert(0, '(');
+        line.last_mut().unwrap().push(')');
+        tokens.extend(line);
+    }
+
+    let mut after = game.clone();
+    after.make_move(main.from.clone(), main.to.clone());
+    // Black's move needs its number again after a comment or variation
+    let is_interrupted =
+        !alternatives.is_empty() || tokens.last().is_some_and(|t| t.ends_with('}'));
+    write_variations(&after, &main.variations, is_interrupted, tokens);
+}
+
+fn write_move(game: &Game, node: &Node, is_first: bool, tokens: &mut Vec<String>) {
+    if let Some(number) = move_number(game, is_first) {
+        tokens.push(number);
+    }
+    let mut san = node.san.clone();
+    for (i, &nag) in node.nags.iter().enumerate() {
+        match NAG_SYMBOLS.get((nag as usize).wrapping_sub(1)) {
+            Some(symbol) if i == 0 => san.push_str(symbol),
+            _ => {
+                tokens.push(san);
+                san = format!("${}", nag);
+            }
+        }
+    }
+    tokens.push(san);
+
+    let mut comment: Vec<String> = Vec::new();
+    if !node.arrows.is_empty() {
+        let arrows: Vec<String> = node
+            .arrows
+            .iter()
+            .map(|(from, to)| format!("G{}{}", from.to_lowercase(), to.to_lowercase()))
+            .collect();
+        comment.push(format!("[%cal {}]", arrows.join(",")));
+    }
+    if !node.highlights.is_empty() {
+        let highlights: Vec<String> = node
+            .highlights
+            .iter()
+            .map(|square| format!("G{}", square.to_lowercase()))
+            .collect();
+        comment.push(format!("[%csl {}]", highlights.join(",")));
+    }
+    if let Some(text) = &node.comment {
+        comment.push(text.clone());
+    }
+    if !comment.is_empty() {
+        tokens.push(format!("{{{}}}", comment.join(" ")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(tree: &mut GameTree, from: &str, to: &str) {
+        tree.make_move(from.to_string(), to.to_string()).unwrap();
+    }
+
+    #[test]
+    fn navigates_variations() {
+        let mut tree = GameTree::new(Game::new());
+        play(&mut tree, "E2", "E4");
+        play(&mut tree, "E7", "E5");
+        tree.back();
+        play(&mut tree, "C7", "C5");
+        assert_eq!(tree.current_node().unwrap().san(), "c5");
+
+        tree.go_to_start();
+        assert!(!tree.back());
+        tree.go_to_end();
+        assert_eq!(tree.game().get_move_history().len(), 2);
+        assert_eq!(tree.current_node().unwrap().san(), "e5");
+
+        tree.back();
+        let sans: Vec<&str> = tree.variations().iter().map(|node| node.san()).collect();
+        assert_eq!(sans, vec!["e5", "c5"]);
+        assert!(tree.enter_variation(1));
+        assert!(tree.promote_variation());
+        assert_eq!(tree.main_line().get_move_history()[1].1, "C5");
+        assert!(!tree.promote_variation());
+
+        assert!(tree.delete_variation());
+        assert_eq!(tree.variations().len(), 1);
+        assert_eq!(tree.variations()[0].san(), "e5");
+        assert!(tree
+            .make_move(String::from("E4"), String::from("E6"))
+            .is_none());
+    }
+
+    #[test]
+    fn writes_the_whole_tree_as_pgn() {
+        let mut tree = GameTree::new(Game::new());
+        tree.comment = Some(String::from("A short game"));
+        play(&mut tree, "E2", "E4");
+        play(&mut tree, "E7", "E5");
+        tree.current_node_mut().unwrap().nags.push(1);
+        tree.back();
+        play(&mut tree, "C7", "C5");
+        play(&mut tree, "G1", "F3");
+        let node = tree.current_node_mut().unwrap();
+        node.comment = Some(String::from("Open Sicilian next"));
+        node.arrows.push((String::from("D2"), String::from("D4")));
+        node.highlights.push(String::from("D4"));
+        tree.go_to_start();
+        tree.forward();
+        tree.forward();
+        play(&mut tree, "G1", "F3");
+
+        let pgn = tree.to_pgn(&[]);
+        assert!(pgn.replace('\n', " ").ends_with(
+            "{A short game} 1. e4 e5! (1... c5 2. Nf3 {[%cal Gd2d4] [%csl Gd4] Open \
+             Sicilian next}) 2. Nf3 * "
+        ));
+
+        let (read, _) = GameTree::from_pgn(&pgn).unwrap();
+        assert_eq!(read.comment, tree.comment);
+        assert_eq!(read.variations(), {
+            tree.go_to_start();
+            tree.variations()
+        });
+        assert_eq!(read.to_pgn(&[]), pgn);
+    }
+
+    #[test]
+    fn reads_nested_variations_and_nags() {
+        let pgn = "1. d4 (1. e4 e5 (1... c5 $2) 2. Nf3) 1... d5 2. c4?! $14 *";
+        let (mut tree, _) = GameTree::from_pgn(pgn).unwrap();
+        assert_eq!(tree.variations().len(), 2);
+        tree.enter_variation(1);
+        assert_eq!(tree.variations().len(), 2);
+        assert_eq!(tree.variations()[1].nags, vec![2]);
+
+        tree.go_to_start();
+        tree.go_to_end();
+        assert_eq!(tree.current_node().unwrap().nags, vec![6, 14]);
+        assert_eq!(tree.main_line().get_move_history().len(), 3);
+        assert!(tree.to_pgn(&[]).contains("2. c4?! $14 *"));
+        assert!(GameTree::from_pgn("1. e4 (e5)").is_none());
+    }
+}
@@ -0,0 +1,223 @@
+use crate::game::*;
+use crate::piece::*;
+use std::fmt;
+
+/// A problem that makes a position impossible to play from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PositionError {
+    MissingKing(Color),
+    TooManyKings(Color),
+    /// A pawn stands on the first or eighth rank.
+    PawnOnBackRank(String),
+    /// The side that just moved has left its king in check.
+    InactiveSideInCheck(Color),
+    /// A pawn is marked as not having moved but isn't on its starting rank.
+    UnmovedPawnOutOfPlace(String),
+    TooManyPawns(Color),
+    /// More than 16 pieces, or more promoted pieces than missing pawns.
+    TooManyPieces(Color),
+    /// The king or a rook isn't where the castling rights need it.
+    ImpossibleCastlingRights(Color),
+    /// No pawn can have passed over the en passant square in the last move.
+    ImpossibleEnPassant(String),
+    /// The full move number is 0.
+    InvalidMoveNumber,
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionError::MissingKing(color) => write!(f, "{:?} has no king", color),
+            PositionError::TooManyKings(color) => write!(f, "{:?} has more than one king", color),
+            PositionError::PawnOnBackRank(square) => {
+                write!(f, "Pawn on the back rank at {}", square)
+            }
+            PositionError::InactiveSideInCheck(color) => {
+                write!(f, "{:?} is in check but it isn't their turn", color)
+            }
+            PositionError::UnmovedPawnOutOfPlace(square) => {
+                write!(
+                    f,
+                    "Pawn at {} can't be unmoved off its starting rank",
+                    square
+                )
+            }
+            PositionError::TooManyPawns(color) => write!(f, "{:?} has more than 8 pawns", color),
+            PositionError::TooManyPieces(color) => {
+                write!(
+                    f,
+                    "{:?} has more pieces than can be reached by promotion",
+                    color
+                )
+            }
+            PositionError::ImpossibleCastlingRights(color) => {
+                write!(f, "{:?} can't have the castling rights given", color)
+            }
+            PositionError::ImpossibleEnPassant(square) => {
+                write!(f, "No pawn can be captured en passant on {}", square)
+            }
+            PositionError::InvalidMoveNumber => write!(f, "The full move number starts at 1"),
+        }
+    }
+}
+
+/// Checks that a position with the given colour to move could be played,
+/// and returns all problems found. An empty list means the position is valid.
+pub fn validate_position(board: &[Option<Piece>; 64], active_color: Color) -> Vec<PositionError> {
+    let mut problems = Vec::new();
+
+    for &color in &[Color::White, Color::Black] {
+        let count = |title: PieceType| {
+            board
+                .iter()
+                .filter(|tile| {
+                    tile.is_some_and(|piece| piece.color() == color && piece.title() == title)
+                })
+                .count()
+        };
+
+        match count(PieceType::King) {
+            0 => problems.push(PositionError::MissingKing(color)),
+            1 => (),
+            _ => problems.push(PositionError::TooManyKings(color)),
+        }
+
+        let pawns = count(PieceType::Pawn);
+        if pawns > 8 {
+            problems.push(PositionError::TooManyPawns(color));
+        }
+
+        // Pieces beyond the starting set must have been promoted from pawns
+        let promoted: usize = [
+            (PieceType::Queen, 1),
+            (PieceType::Rook, 2),
+            (PieceType::Bishop, 2),
+            (PieceType::Knight, 2),
+        ]
+        .iter()
+        .map(|&(title, start)| count(title).saturating_sub(start))
+        .sum();
+        let total = board
+            .iter()
+            .filter(|tile| tile.is_some_and(|piece| piece.color() == color))
+            .count();
+        if total > 16 || promoted > 8usize.saturating_sub(pawns) {
+            problems.push(PositionError::TooManyPieces(color));
+        }
+    }
+
+    for (square, tile) in board.iter().enumerate() {
+        if let Some(piece) = tile {
+            if piece.title() != PieceType::Pawn {
+                continue;
+            }
+            let rank = square / 8;
+            let start_rank = if piece.color() == Color::White { 1 } else { 6 };
+            if rank == 0 || rank == 7 {
+                problems.push(PositionError::PawnOnBackRank(as_standard_notation(&square)));
+            } else if !piece.has_moved() && rank != start_rank {
+                problems.push(PositionError::UnmovedPawnOutOfPlace(as_standard_notation(
+                    &square,
+                )));
+            }
+        }
+    }
+
+    let inactive_color = match active_color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    };
+    if king_in_check(board, inactive_color) {
+        problems.push(PositionError::InactiveSideInCheck(inactive_color));
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board_from(pieces: &[(usize, Color, PieceType)]) -> [Option<Piece>; 64] {
+        let mut board = [None; 64];
+        for &(square, color, title) in pieces {
+            board[square] = Some(Piece::new(color, title, true));
+        }
+        board
+    }
+
+    #[test]
+    fn starting_position_is_valid() {
+        let game = Game::new();
+        assert!(validate_position(game.get_board(), Color::White).is_empty());
+    }
+
+    #[test]
+    fn finds_missing_and_extra_kings() {
+        let board = board_from(&[
+            (4, Color::White, PieceType::King),
+            (5, Color::White, PieceType::King),
+        ]);
+        let problems = validate_position(&board, Color::White);
+        assert!(problems.contains(&PositionError::TooManyKings(Color::White)));
+        assert!(problems.contains(&PositionError::MissingKing(Color::Black)));
+    }
+
+    #[test]
+    fn finds_pawns_on_back_ranks() {
+        let board = board_from(&[
+            (4, Color::White, PieceType::King),
+            (60, Color::Black, PieceType::King),
+            (0, Color::White, PieceType::Pawn),
+        ]);
+        assert_eq!(
+            validate_position(&board, Color::White),
+            vec![PositionError::PawnOnBackRank(String::from("A1"))]
+        );
+    }
+
+    #[test]
+    fn finds_unmoved_pawns_off_their_start() {
+        let mut board = board_from(&[
+            (4, Color::White, PieceType::King),
+            (60, Color::Black, PieceType::King),
+        ]);
+        board[28] = Some(Piece::new(Color::White, PieceType::Pawn, false));
+        assert_eq!(
+            validate_position(&board, Color::White),
+            vec![PositionError::UnmovedPawnOutOfPlace(String::from("E4"))]
+        );
+    }
+
+    #[test]
+    fn finds_side_not_to_move_in_check() {
+        let board = board_from(&[
+            (4, Color::White, PieceType::King),
+            (60, Color::Black, PieceType::King),
+            (12, Color::White, PieceType::Rook),
+        ]);
+        assert_eq!(
+            validate_position(&board, Color::White),
+            vec![PositionError::InactiveSideInCheck(Color::Black)]
+        );
+        assert!(validate_position(&board, Color::Black).is_empty());
+    }
+
+    #[test]
+    fn finds_too_many_pieces() {
+        let mut pieces = vec![
+            (4, Color::White, PieceType::King),
+            (60, Color::Black, PieceType::King),
+        ];
+        for square in 16..19 {
+            pieces.push((square, Color::White, PieceType::Knight));
+        }
+        for square in 8..16 {
+            pieces.push((square, Color::White, PieceType::Pawn));
+        }
+        assert_eq!(
+            validate_position(&board_from(&pieces), Color::White),
+            vec![PositionError::TooManyPieces(Color::White)]
+        );
+    }
+}
@@ -0,0 +1,437 @@
+use crate::book::*;
+use crate::engine::*;
+use crate::game::*;
+use crate::piece::*;
+use crate::tablebase::Tablebase;
+use std::time::Duration;
+
+/// A front end speaking the Chess Engine Communication Protocol (xboard),
+/// playing with the search in `engine` on top of a `Game`.
+///
+/// Feed it one line of input at a time with `handle_command` and send the
+/// returned lines back to the interface. Moves are sent in coordinate
+/// notation, e.g. `move e7e5`.
+pub struct XBoard {
+    game: Game,
+    // The colour the engine plays, None in force mode
+    engine_color: Option<Color>,
+    max_depth: u32,
+    // Fixed time per move, set with `st`
+    move_time: Option<Duration>,
+    // Time control set with `level`
+    moves_per_session: u32,
+    increment: Duration,
+    engine_clock: Option<Duration>,
+    opponent_clock: Option<Duration>,
+    engine_moves: u32,
+    book: Option<(OpeningBook, BookOptions)>,
+    tablebase: Option<Tablebase>,
+}
+
+impl Default for XBoard {
+    fn default() -> Self {
+        XBoard::new()
+    }
+}
+
+impl XBoard {
+    /// Creates a front end with a new game where the engine plays black.
+    pub fn new() -> XBoard {
+        XBoard {
+            game: Game::new(),
+            engine_color: Some(Color::Black),
+            max_depth: SearchLimits::default().depth,
+            move_time: None,
+            moves_per_session: 0,
+            increment: Duration::from_secs(0),
+            engine_clock: None,
+            opponent_clock: None,
+            engine_moves: 0,
+            book: None,
+            tablebase: None,
+        }
+    }
+
+    /// Plays moves from an opening book while the position is in it.
+    pub fn set_book(&mut self, book: OpeningBook, options: BookOptions) {
+        self.book = Some((book, options));
+    }
+
+    /// Plays positions the tablebase can probe from its tables.
+    pub fn set_tablebase(&mut self, tablebase: Tablebase) {
+        self.tablebase = Some(tablebase);
+    }
+
+    /// Get the game being played.
+    pub fn get_game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Handles one line of input from the interface and returns the lines
+    /// to send back. Unknown commands are answered with an error.
+    pub fn handle_command(&mut self, line: &str) -> Vec<String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, arguments) = match words.split_first() {
+            Some((command, arguments)) => (*command, arguments),
+            None => return Vec::new(),
+        };
+
+        match command {
+            "xboard" | "accepted" | "rejected" | "random" | "post" | "nopost" | "hard" | "easy"
+            | "computer" | "name" | "rating" | "ics" | "?" | "." | "quit" => Vec::new(),
+            "protover" => vec![
+                "feature myname=\"isaklar-chess\" ping=1 setboard=1 usermove=1 san=0 \
+                 colors=0 sigint=0 sigterm=0 analyze=0 done=1"
+                    .to_string(),
+            ],
+            "ping" => vec![format!("pong {}", arguments.join(" "))],
+            "new" => {
+                self.game = Game::new();
+                self.engine_color = Some(Color::Black);
+                self.max_depth = SearchLimits::default().depth;
+                self.move_time = None;
+                self.engine_moves = 0;
+                Vec::new()
+            }
+            "force" | "result" => {
+                self.engine_color = None;
+                Vec::new()
+            }
+            "go" => {
+                self.engine_color = Some(self.game.get_active_color());
+                self.engine_move()
+            }
+            "playother" => {
+                self.engine_color = Some(self.game.get_active_color().opposite());
+                Vec::new()
+            }
+            "white" | "black" => {
+                // Old protocol: set the side to move, engine plays the other side
+                let color = if command == "white" {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                self.engine_color = Some(color.opposite());
+                Vec::new()
+            }
+            "setboard" => match Game::from_fen(&arguments.join(" ")) {
+                Some(game) => {
+                    self.game = game;
+                    self.engine_moves = 0;
+                    Vec::new()
+                }
+                None => vec!["tellusererror Illegal position".to_string()],
+            },
+            "usermove" => match arguments.first() {
+                Some(user_move) => self.user_move(user_move),
+                None => vec![format!("Error (missing move): {}", line.trim())],
+            },
+            "undo" => {
+                self.game.undo_move();
+                Vec::new()
+            }
+            "remove" => {
+                self.game.undo_move();
+                self.game.undo_move();
+                Vec::new()
+            }
+            "level" => self.set_level(arguments, line),
+            "st" => match arguments.first().and_then(|s| parse_seconds(s)) {
+                Some(seconds) if seconds > Duration::ZERO => {
+                    self.move_time = Some(seconds);
+                    Vec::new()
+                }
+                _ => vec![format!("Error (bad time): {}", line.trim())],
+            },
+            "sd" => match arguments.first().and_then(|s| s.parse::<u32>().ok()) {
+                Some(depth) if depth > 0 => {
+                    self.max_depth = depth;
+                    Vec::new()
+                }
+                _ => vec![format!("Error (bad depth): {}", line.trim())],
+            },
+            "time" => match parse_centiseconds(arguments) {
+                Some(clock) => {
+                    self.engine_clock = Some(clock);
+                    Vec::new()
+                }
+                None => vec![format!("Error (bad time): {}", line.trim())],
+            },
+            "otim" => match parse_centiseconds(arguments) {
+                Some(clock) => {
+                    self.opponent_clock = Some(clock);
+                    Vec::new()
+                }
+                None => vec![format!("Error (bad time): {}", line.trim())],
+            },
+            _ if is_coordinate_move(command) => self.user_move(command),
+            _ => vec![format!("Error (unknown command): {}", command)],
+        }
+    }
+
+    // Makes the opponent's move and replies with the engine's move if it's its turn
+    fn user_move(&mut self, user_move: &str) -> Vec<String> {
+        let (from, to) = match parse_coordinate_move(user_move) {
+            Some(squares) => squares,
+            None => return vec![format!("Illegal move: {}", user_move)],
+        };
+        if self.game.make_move(from, to).is_none() {
+            return vec![format!("Illegal move: {}", user_move)];
+        }
+
+        let mut output = result_line(&self.game).into_iter().collect::<Vec<String>>();
+        if output.is_empty() && self.engine_color == Some(self.game.get_active_color()) {
+            output.append(&mut self.engine_move());
+        }
+        output
+    }
+
+    // Searches for and makes a move for the side to move
+    fn engine_move(&mut self) -> Vec<String> {
+        let book_move = self
+            .book
+            .as_ref()
+            .and_then(|(book, options)| book.choose_move(&self.game, options));
+        let (from, to) = match book_move {
+            Some(book_move) => book_move,
+            None => {
+                let limits = SearchLimits {
+                    depth: self.max_depth,
+                    time: Some(self.time_for_move()),
+                };
+                let result = match &self.tablebase {
+                    Some(tablebase) => search_with_tablebase(&self.game, limits, tablebase),
+                    None => search(&self.game, limits),
+                };
+                match result {
+                    Some(result) => (result.from, result.to),
+                    None => return result_line(&self.game).into_iter().collect(),
+                }
+            }
+        };
+
+        self.game.make_move(from.clone(), to.clone());
+        self.engine_moves += 1;
+
+        let mut output = vec![format!("move {}{}", from.to_lowercase(), to.to_lowercase())];
+        output.extend(result_line(&self.game));
+        output
+    }
+
+    // Sets the time control from `level MPS BASE INC`, where BASE is minutes or minutes:seconds
+    fn set_level(&mut self, arguments: &[&str], line: &str) -> Vec<String> {
+        if arguments.len() != 3 {
+            return vec![format!("Error (bad time control): {}", line.trim())];
+        }
+        let base: Vec<Option<u64>> = arguments[1].split(':').map(|s| s.parse().ok()).collect();
+        let base_seconds = match base.as_slice() {
+            [Some(minutes)] => minutes.checked_mul(60),
+            [Some(minutes), Some(seconds)] => minutes
+                .checked_mul(60)
+                .and_then(|minutes| minutes.checked_add(*seconds)),
+            _ => None,
+        };
+
+        match (
+            arguments[0].parse::<u32>(),
+            base_seconds,
+            parse_seconds(arguments[2]),
+        ) {
+            (Ok(moves), Some(seconds), Some(increment)) => {
+                self.moves_per_session = moves;
+                self.increment = increment;
+                self.engine_clock = Some(Duration::from_secs(seconds));
+                self.move_time = None;
+                Vec::new()
+            }
+            _ => vec![format!("Error (bad time control): {}", line.trim())],
+        }
+    }
+
+    // Decides how long the engine may think about its next move
+    fn time_for_move(&self) -> Duration {
+        if let Some(move_time) = self.move_time {
+            return move_time;
+        }
+        match self.engine_clock {
+            Some(clock) => {
+                let moves_to_go = if self.moves_per_session > 0 {
+                    self.moves_per_session - self.engine_moves % self.moves_per_session
+                } else {
+                    30
+                };
+                // Never plan to use more than half of the remaining time
+                (clock / moves_to_go)
+                    .saturating_add(self.increment)
+                    .min(clock / 2)
+            }
+            None => Duration::from_secs(5),
+        }
+    }
+}
+
+// Returns the result command if the game is over
+fn result_line(game: &Game) -> Option<String> {
+    match game.get_game_state() {
+        GameState::BlackCheckMate => Some("1-0 {White mates}".to_string()),
+        GameState::WhiteCheckMate => Some("0-1 {Black mates}".to_string()),
+        GameState::Tie => Some("1/2-1/2 {Stalemate}".to_string()),
+        _ => None,
+    }
+}
+
+// Reads a number of seconds, which may have a fraction, refusing negative
+// and too large ones
+fn parse_seconds(input: &str) -> Option<Duration> {
+    Duration::try_from_secs_f64(input.parse().ok()?).ok()
+}
+
+fn parse_centiseconds(arguments: &[&str]) -> Option<Duration> {
+    let centiseconds: u64 = arguments.first()?.parse().ok()?;
+    Some(Duration::from_millis(centiseconds.checked_mul(10)?))
+}
+
+fn is_coordinate_move(input: &str) -> bool {
+    parse_coordinate_move(input).is_some()
+}
+
+// Converts a move like "e2e4" into the squares "E2" and "E4"
+fn parse_coordinate_move(input: &str) -> Option<(String, String)> {
+    let chars: Vec<char> = input.to_uppercase().chars().collect();
+    // A trailing promotion piece is accepted but promotion isn't implemented
+    if chars.len() != 4 && chars.len() != 5 {
+        return None;
+    }
+    let is_square =
+        |file: char, rank: char| ('A'..='H').contains(&file) && ('1'..='8').contains(&rank);
+    if !is_square(chars[0], chars[1]) || !is_square(chars[2], chars[3]) {
+        return None;
+    }
+    Some((chars[0..2].iter().collect(), chars[2..4].iter().collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_features() {
+        let mut xboard = XBoard::new();
+        let output = xboard.handle_command("protover 2");
+        assert!(output[0].starts_with("feature "));
+        assert!(output[0].contains("usermove=1"));
+        assert!(output[0].ends_with("done=1"));
+    }
+
+    #[test]
+    fn replies_to_user_move() {
+        let mut xboard = XBoard::new();
+        xboard.handle_command("new");
+        xboard.handle_command("sd 1");
+        let output = xboard.handle_command("usermove e2e4");
+        assert_eq!(output.len(), 1);
+        assert!(output[0].starts_with("move "));
+        assert_eq!(xboard.get_game().get_active_color(), Color::White);
+    }
+
+    #[test]
+    fn plays_book_moves() {
+        let mut game = Game::new();
+        game.make_move(String::from("E2"), String::from("E4"));
+        // c7c5 in the Polyglot move encoding
+        let mut bytes = crate::zobrist::polyglot_hash(&game).to_be_bytes().to_vec();
+        bytes.extend_from_slice(&[0x0c, 0xa2, 0, 1, 0, 0, 0, 0]);
+
+        let mut xboard = XBoard::new();
+        xboard.set_book(OpeningBook::from_bytes(&bytes), BookOptions::default());
+        assert_eq!(xboard.handle_command("usermove e2e4"), vec!["move c7c5"]);
+    }
+
+    #[test]
+    fn force_mode_only_makes_moves() {
+        let mut xboard = XBoard::new();
+        xboard.handle_command("force");
+        assert!(xboard.handle_command("usermove e2e4").is_empty());
+        assert!(xboard.handle_command("usermove e7e5").is_empty());
+        assert_eq!(xboard.get_game().get_active_color(), Color::White);
+    }
+
+    #[test]
+    fn rejects_illegal_moves() {
+        let mut xboard = XBoard::new();
+        xboard.handle_command("force");
+        assert_eq!(
+            xboard.handle_command("usermove e2e5"),
+            vec!["Illegal move: e2e5".to_string()]
+        );
+    }
+
+    #[test]
+    fn go_mates_from_setboard() {
+        let mut xboard = XBoard::new();
+        xboard.handle_command("force");
+        xboard.handle_command("setboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        xboard.handle_command("sd 2");
+        assert_eq!(
+            xboard.handle_command("go"),
+            vec!["move a1a8".to_string(), "1-0 {White mates}".to_string()]
+        );
+    }
+
+    #[test]
+    fn undo_and_remove_take_back_moves() {
+        let mut xboard = XBoard::new();
+        xboard.handle_command("force");
+        xboard.handle_command("e2e4");
+        xboard.handle_command("e7e5");
+        xboard.handle_command("undo");
+        assert_eq!(xboard.get_game().get_active_color(), Color::Black);
+        xboard.handle_command("e7e5");
+        xboard.handle_command("remove");
+        assert_eq!(xboard.get_game().get_active_color(), Color::White);
+        assert_eq!(
+            xboard.get_game().get_board()[12],
+            Some(Piece::new(Color::White, PieceType::Pawn, false))
+        );
+    }
+
+    #[test]
+    fn time_controls_limit_thinking_time() {
+        let mut xboard = XBoard::new();
+        xboard.handle_command("level 40 5 0");
+        assert_eq!(xboard.time_for_move(), Duration::from_millis(7500));
+        xboard.handle_command("time 1000");
+        assert_eq!(xboard.time_for_move(), Duration::from_millis(250));
+        xboard.handle_command("st 2");
+        assert_eq!(xboard.time_for_move(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn rejects_times_out_of_range() {
+        let mut xboard = XBoard::new();
+        for command in [
+            "st 1e30",
+            "st -1",
+            "st NaN",
+            "level 40 5 1e300",
+            "level 40 307445734561825861 0",
+            "level 40 5:18446744073709551615 0",
+            "time 18446744073709551615",
+            "otim 18446744073709551615",
+        ] {
+            let output = xboard.handle_command(command);
+            assert_eq!(output.len(), 1, "{}", command);
+            assert!(output[0].starts_with("Error (bad time"), "{}", command);
+        }
+        assert_eq!(xboard.time_for_move(), Duration::from_secs(5));
+
+        // Large times that fit are kept without overflowing
+        assert!(xboard.handle_command("level 0 5 1e19").is_empty());
+        assert!(xboard.handle_command("time 1844674407370955161").is_empty());
+        assert_eq!(
+            xboard.time_for_move(),
+            Duration::from_millis(18446744073709551610) / 2
+        );
+    }
+}